@@ -66,6 +66,11 @@ impl TestClass {
         println!("{}", System::get_property(self.env(), "java.home")?);
         Ok(self.increment_bar(self))
     }
+
+    #[deprecated(since = "0.2.0", note = "Use `increment_bar` instead.")]
+    pub fn old_increment_bar(&self) -> u32 {
+        self.counter
+    }
 }
 
 pub struct System;
@@ -1,17 +1,13 @@
 use crate::native_loader::{EntryPointArch, EntryPointPlatform, ParsedBinary};
-use nekojni::{
-    __macro_internals::{
-        exported_class::{jni_native_name, ExportedClass, ExportedItem},
-        MARKER_STR,
-    },
-    *,
-};
-use nekojni_utils::{signatures::ClassName, ClassData, Hasher, MFlags, NativeClassWrapper};
+use nekojni::{__macro_internals::MARKER_STR, *};
+use nekojni_utils::{signatures::ClassName, ClassData, GenerationOptions, Hasher, JavaVersion};
 use std::collections::HashSet;
 
 pub struct BuildJarOptions {
     pub main_bin: Option<String>,
     pub use_null_loader: bool,
+    pub use_cleaner: bool,
+    pub target_version: JavaVersion,
 }
 
 pub fn make_jar_data(
@@ -20,7 +16,14 @@ pub fn make_jar_data(
 ) -> Result<ClassData> {
     let mut binaries: Vec<_> = modules.iter().collect();
     binaries.sort_by_key(|m| (&m.platform, &m.arch, m.path.file_name().unwrap()));
-    let mut data = ClassData::new();
+    let options = GenerationOptions {
+        use_cleaner: build_jar_options.use_cleaner,
+        target_version: build_jar_options.target_version,
+    };
+    if let Err(e) = options.validate() {
+        jni_bail!("{e}");
+    }
+    let mut data = ClassData::with_options(options);
 
     // check for duplicated platform/arch combinations
     let mut used_combos = HashSet::new();
@@ -117,11 +120,12 @@ pub fn make_jar_data(
 
             // generate all classes to the .jar
             for module in &binary.info {
-                data.add_module_loader(module.init_class_name);
-                data.add_module_exception(module.except_class_name);
-                for class in module.class_info {
-                    generate_class(&class.exported, &mut data, module.init_class_name);
-                }
+                module.generate_classes(&mut data);
+            }
+
+            // check the generated classes before they are written to the jar
+            if let Err(e) = data.verify() {
+                jni_bail!("{e}");
             }
 
             // generate a basic manifest
@@ -142,80 +146,3 @@ pub fn make_jar_data(
     // oh no :(
     jni_bail!("No modules could be loaded!")
 }
-
-fn generate_class(data: &ExportedClass, class_data: &mut ClassData, init_class: &str) {
-    let mut class = NativeClassWrapper::new(
-        data.access,
-        &data.name,
-        match &data.super_class {
-            None => "java/lang/Object",
-            Some(v) => v,
-        },
-        data.id_field_name,
-    );
-    class.generate_init(init_class, &data.static_init);
-    for class_name in data.implements {
-        class.implements(class_name);
-    }
-    class.source_file(data.source_file);
-    class.dispose_funcs(&data.free_fn, true);
-
-    for exports in data.exports {
-        match exports {
-            ExportedItem::NativeConstructor {
-                flags,
-                signature,
-                native_name,
-                native_signature,
-                super_signature,
-            } => {
-                class.export_constructor(
-                    *flags,
-                    signature,
-                    native_name,
-                    native_signature,
-                    super_signature,
-                    data.instance_init,
-                );
-            }
-            ExportedItem::NativeMethodWrapper {
-                flags,
-                name,
-                signature,
-                native_name,
-                native_signature,
-                has_id_param,
-            } => {
-                class.export_native_wrapper(
-                    *flags,
-                    name,
-                    signature,
-                    &jni_native_name(native_name, flags.contains(MFlags::Static), false),
-                    native_signature,
-                    *has_id_param,
-                );
-            }
-            ExportedItem::JavaField { flags, name, field } => {
-                class.export_field(*flags, name, &field);
-            }
-        }
-    }
-    for method in data.native_methods {
-        if method.export_direct {
-            class.export_native_direct(
-                method.export_direct_flags,
-                &method.name,
-                &method.sig,
-                method.is_static,
-            );
-        } else {
-            class.export_native(
-                &jni_native_name(method.name, method.is_static, false),
-                &method.sig,
-                method.is_static,
-            );
-        }
-    }
-
-    class_data.add_exported_class(class);
-}
@@ -24,8 +24,12 @@ fn main() {
         vec.push(binary);
     }
 
-    let class_data =
-        build_jar::make_jar_data(&vec, &BuildJarOptions { main_bin: None, use_null_loader: false })
-            .unwrap();
+    let class_data = build_jar::make_jar_data(&vec, &BuildJarOptions {
+        main_bin: None,
+        use_null_loader: false,
+        use_cleaner: false,
+        target_version: Default::default(),
+    })
+    .unwrap();
     std::fs::write("test.jar", class_data.make_jar()).unwrap();
 }
@@ -8,7 +8,10 @@ use enumset::EnumSet;
 use nekojni_utils::{signatures::ClassName, MFlags};
 use proc_macro2::{Ident, Span, TokenStream as SynTokenStream};
 use quote::{quote, quote_spanned};
-use syn::{parse2, spanned::Spanned, Abi, ImplItemMethod, Lifetime, ReturnType, Type};
+use syn::{
+    parse2, spanned::Spanned, Abi, Attribute, FnArg, ImplItemMethod, Lifetime, Lit, Meta,
+    NestedMeta, ReturnType, Type,
+};
 
 // TODO: Rewrite methods to allow better interop between `JniRef` and `JniRefMut`.
 
@@ -23,6 +26,10 @@ pub struct FunctionAttrs {
     pub init: bool,
     #[darling(default)]
     pub export_direct: bool,
+    #[darling(default)]
+    pub lock: Option<String>,
+    #[darling(default)]
+    pub on_close: bool,
 
     #[darling(default, rename = "__njni_direct_export")]
     pub direct_export: Option<String>,
@@ -44,10 +51,29 @@ pub struct FunctionAttrs {
     #[darling(default, rename = "synchronized")]
     pub acc_synchronized: bool,
 
+    #[darling(multiple)]
+    pub annotate: Vec<String>,
+    #[darling(multiple)]
+    pub annotate_invisible: Vec<String>,
+    #[darling(default)]
+    pub deprecated: bool,
+    #[darling(default)]
+    pub nullable: bool,
+    #[darling(default)]
+    pub not_null: bool,
+
     #[darling(skip)]
     pub acc_synthetic: bool,
     #[darling(skip)]
     pub override_rust_name: Option<Ident>,
+    #[darling(skip)]
+    pub param_attrs: Vec<ParamAttrs>,
+    #[darling(skip)]
+    pub rust_deprecated: bool,
+    #[darling(skip)]
+    pub deprecated_since: Option<String>,
+    #[darling(skip)]
+    pub deprecated_note: Option<String>,
 }
 impl FunctionAttrs {
     fn check_internal_used(&self) -> Result<()> {
@@ -104,6 +130,114 @@ impl FunctionAttrs {
 
         Ok(flags)
     }
+
+    pub fn has_annotations(&self) -> bool {
+        !self.annotate.is_empty()
+            || !self.annotate_invisible.is_empty()
+            || self.deprecated
+            || self.nullable
+            || self.not_null
+            || self.param_attrs.iter().any(ParamAttrs::has_annotations)
+    }
+
+    pub fn annotations(&self, span: Span) -> Result<JavaAnnotations> {
+        let mut annotations =
+            JavaAnnotations::parse(span, &self.annotate, &self.annotate_invisible)?;
+        if self.deprecated || self.rust_deprecated {
+            annotations
+                .deprecated(self.deprecated_since.as_deref(), self.deprecated_note.as_deref());
+        }
+        annotations.nullability(span, self.nullable, self.not_null)?;
+        Ok(annotations)
+    }
+
+    pub fn param_annotations(&self) -> Result<Vec<JavaAnnotations>> {
+        self.param_attrs
+            .iter()
+            .map(ParamAttrs::annotations)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, FromAttributes, Default)]
+#[darling(attributes(jni))]
+pub struct ParamAttrs {
+    #[darling(multiple)]
+    pub annotate: Vec<String>,
+    #[darling(multiple)]
+    pub annotate_invisible: Vec<String>,
+    #[darling(default)]
+    pub nullable: bool,
+    #[darling(default)]
+    pub not_null: bool,
+
+    #[darling(skip)]
+    pub span: Option<Span>,
+}
+impl ParamAttrs {
+    fn has_annotations(&self) -> bool {
+        !self.annotate.is_empty()
+            || !self.annotate_invisible.is_empty()
+            || self.nullable
+            || self.not_null
+    }
+
+    fn annotations(&self) -> Result<JavaAnnotations> {
+        let span = self.span.unwrap_or_else(Span::call_site);
+        let mut annotations =
+            JavaAnnotations::parse(span, &self.annotate, &self.annotate_invisible)?;
+        annotations.nullability(span, self.nullable, self.not_null)?;
+        Ok(annotations)
+    }
+}
+
+/// Parses Rust's `#[deprecated]` attribute, returning the `since` version and the note if they
+/// are present.
+fn parse_rust_deprecated(attr: &Attribute) -> Result<(Option<String>, Option<String>)> {
+    let mut since = None;
+    let mut note = None;
+    match attr.parse_meta()? {
+        Meta::List(list) => {
+            for nested in &list.nested {
+                if let NestedMeta::Meta(Meta::NameValue(nv)) = nested {
+                    if let Lit::Str(str) = &nv.lit {
+                        if nv.path.is_ident("since") {
+                            since = Some(str.value());
+                        } else if nv.path.is_ident("note") {
+                            note = Some(str.value());
+                        }
+                    }
+                }
+            }
+        }
+        Meta::NameValue(nv) => {
+            if let Lit::Str(str) = &nv.lit {
+                note = Some(str.value());
+            }
+        }
+        Meta::Path(_) => {}
+    }
+    Ok((since, note))
+}
+
+/// Parses the `#[jni]` attributes on the parameters of a method, and removes them.
+fn process_param_attrs(item: &mut ImplItemMethod) -> Result<Vec<ParamAttrs>> {
+    let mut param_attrs = Vec::new();
+    for (i, param) in item.sig.inputs.iter_mut().enumerate() {
+        if let FnArg::Typed(param) = param {
+            let mut attrs: ParamAttrs = FromAttributes::from_attributes(&param.attrs)?;
+            attrs.span = Some(param.span());
+            param.attrs.retain(|x| last_path_segment(&x.path) != "jni");
+            if i == 0 {
+                if attrs.has_annotations() {
+                    error(param.span(), "Annotations cannot be added to this parameter.")?;
+                }
+            } else {
+                param_attrs.push(attrs);
+            }
+        }
+    }
+    Ok(param_attrs)
 }
 
 fn process_params_java(
@@ -111,7 +245,16 @@ fn process_params_java(
     components: &mut JavaClassCtx,
     item: &ImplItemMethod,
     args: &[FuncArgMode],
-) -> Result<(Vec<Type>, Vec<Type>, Vec<Ident>, Vec<Ident>, SynTokenStream, Type, Type)> {
+) -> Result<(
+    Vec<Type>,
+    Vec<Type>,
+    Vec<Ident>,
+    Vec<Ident>,
+    SynTokenStream,
+    SynTokenStream,
+    Type,
+    Type,
+)> {
     let nekojni = &ctx.nekojni;
     let sig_span = item.span();
 
@@ -126,6 +269,7 @@ fn process_params_java(
     let mut params = Vec::new();
     let mut params_java = Vec::new();
     let mut java_convert = SynTokenStream::new();
+    let mut java_release = SynTokenStream::new();
     for arg in args {
         let in_name = components.gensym("in");
         let java_name = components.gensym("java");
@@ -142,6 +286,9 @@ fn process_params_java(
                 #in_arg, env,
             );
         });
+        java_release.extend(quote_spanned! { sig_span =>
+            <#ty as #nekojni::conversions::JavaConversion>::delete_java_value(#java_name, env);
+        });
         params.push(in_name);
         params_java.push(java_name);
     }
@@ -158,6 +305,7 @@ fn process_params_java(
         params,
         params_java,
         java_convert,
+        java_release,
         ret_ty,
         ret_ty_elided,
     ))
@@ -187,8 +335,16 @@ fn method_wrapper_java(
     };
 
     // Parse the type signature of the function.
-    let (param_tys, param_tys_elided, params, params_java, java_convert, ret_ty, ret_ty_elided) =
-        process_params_java(ctx, components, item, &args)?;
+    let (
+        param_tys,
+        param_tys_elided,
+        params,
+        params_java,
+        java_convert,
+        java_release,
+        ret_ty,
+        ret_ty_elided,
+    ) = process_params_java(ctx, components, item, &args)?;
     let lt = check_only_lt(item)?.unwrap_or_else(|| Lifetime::new("'env", Span::call_site()));
 
     // Setup the parameter types.
@@ -214,28 +370,26 @@ fn method_wrapper_java(
 
     // Generate the body of the function
     let rust_class_name = item.sig.ident.to_string();
+    let class_name = &components.class_name;
     let (wrap_params, wrap_call, call_method) = match self_mode {
         FuncSelfMode::EnvRef(_) => (
             quote_spanned! { sig_span => this: #jni::sys::jobject, },
             quote_spanned! { sig_span => #nekojni::JniRef::this(self).into_inner(), },
             quote_spanned! { sig_span =>
-                let ret_val = env.call_method(
-                    this, #java_name, SIGNATURE, &[#(#params_java,)*],
+                let ret_val = cache.call_method(
+                    env, this, #class_name, #java_name, SIGNATURE, &[#(#params_java,)*],
+                );
+            },
+        ),
+        FuncSelfMode::Static => (
+            quote_spanned! { sig_span => },
+            quote_spanned! { sig_span => },
+            quote_spanned! { sig_span =>
+                let ret_val = cache.call_static_method(
+                    env, #class_name, #java_name, SIGNATURE, &[#(#params_java,)*],
                 );
             },
         ),
-        FuncSelfMode::Static => {
-            let class_name = &components.class_name;
-            (
-                quote_spanned! { sig_span => },
-                quote_spanned! { sig_span => },
-                quote_spanned! { sig_span =>
-                    let ret_val = env.call_static_method(
-                        #class_name, #java_name, SIGNATURE, &[#(#params_java,)*],
-                    );
-                },
-            )
-        }
         _ => unreachable!(),
     };
 
@@ -258,9 +412,15 @@ fn method_wrapper_java(
                     <#ret_ty_elided as #nekojni_internal::ImportReturnTy>::JNI_TYPE,
                 );
 
+                enum MethodCacheKey {}
+                let cache = env.get_jvm_instance(
+                    #nekojni_internal::MethodCache::<MethodCacheKey>::new,
+                );
+
                 #java_convert
 
                 #call_method
+                #java_release
 
                 #nekojni_internal::ImportReturnTy::from_return_ty(
                     #rust_class_name, env, ret_val.map_err(|x| x.into()),
@@ -310,8 +470,16 @@ fn constructor_wrapper_java(
     }
 
     // Parse the type signature of the function.
-    let (param_tys, param_tys_elided, params, params_java, java_convert, ret_ty, ret_ty_elided) =
-        process_params_java(ctx, components, item, &args)?;
+    let (
+        param_tys,
+        param_tys_elided,
+        params,
+        params_java,
+        java_convert,
+        java_release,
+        ret_ty,
+        ret_ty_elided,
+    ) = process_params_java(ctx, components, item, &args)?;
     let lt = check_only_lt(item)?.unwrap_or_else(|| Lifetime::new("'env", Span::call_site()));
 
     // Generate the body of the function
@@ -334,11 +502,17 @@ fn constructor_wrapper_java(
                     ")V",
                 );
 
+                enum MethodCacheKey {}
+                let cache = env.get_jvm_instance(
+                    #nekojni_internal::MethodCache::<MethodCacheKey>::new,
+                );
+
                 #java_convert
 
-                let ret_val = env.new_object(
-                    #java_class_name, SIGNATURE, &[#(#params_java,)*],
+                let ret_val = cache.new_object(
+                    env, #java_class_name, SIGNATURE, &[#(#params_java,)*],
                 );
+                #java_release
 
                 #nekojni_internal::ImportCtorReturnTy::<#lt, #self_ty>::from_return_ty(
                     #rust_class_name,
@@ -439,6 +613,8 @@ fn constructor_wrapper_exported(
     let rust_name_str = rust_name.to_string();
     let self_ty = components.self_ty.clone();
     let sig_span = item.sig.span();
+    let rust_path = format!("{}::{rust_name_str}", quote!(#self_ty).to_string().replace(' ', ""));
+    let param_names = java_param_names(&item.sig);
 
     // Check the function signature.
     match self_mode {
@@ -453,6 +629,17 @@ fn constructor_wrapper_exported(
     }
     m_flags.remove(MFlags::Final);
 
+    // Parse the annotations of the constructor
+    if attrs.nullable || attrs.not_null {
+        error(sig_span, "Constructors cannot be `nullable` or `not_null`.")?;
+    }
+    let annotations = attrs.annotations(sig_span)?.to_tokens(ctx);
+    let param_annotations: Vec<_> = attrs
+        .param_annotations()?
+        .iter()
+        .map(|x| x.to_tokens(ctx))
+        .collect();
+
     // Copy the constructor function into a private impl.
     let rust_name = {
         let exported_method = components.gensym(&rust_name_str);
@@ -503,6 +690,7 @@ fn constructor_wrapper_exported(
 
     // Create the constructor definitions
     let ctor_sig = components.gensym_const("CTOR_SIG");
+    let ctor_generic_sig = components.gensym_const("CTOR_GENERIC_SIG");
     let method_sig = components.gensym_const("METHOD_SIG");
     let exported_method = components.gensym_const("EXPORT_JAVA_FN");
     let access = enumset_to_toks(&ctx, quote!(#nekojni_internal::MFlags), m_flags - MFlags::Static);
@@ -514,6 +702,12 @@ fn constructor_wrapper_exported(
                 #(<#param_tys_elided as #nekojni::conversions::JavaConversionType>::JNI_TYPE,)*
                 ")V",
             );
+            const #ctor_generic_sig: &'static str = #nekojni_internal::constcat_const!(
+                "(",
+                #(<#param_tys_elided as #nekojni::conversions::JavaConversionType>
+                    ::JNI_GENERIC_TYPE,)*
+                ")V",
+            );
             const #method_sig: &'static str = #nekojni_internal::constcat_const!(
                 "(",
                 #(<#param_tys_elided as #nekojni::conversions::JavaConversionType>::JNI_TYPE,)*
@@ -525,12 +719,18 @@ fn constructor_wrapper_exported(
                 #nekojni_internal::exported_class::ExportedItem::NativeConstructor {
                     flags: #access,
                     signature: #ctor_sig,
+                    generic_signature: #ctor_generic_sig,
                     native_name: #wrapper_java_name,
                     native_signature: #method_sig,
                     super_signature: #nekojni_internal::constcat_const!(
                         <#ret_ty_elided as #nekojni_internal::ConstructorReturnTy<#synthetic_ty>>
                             ::SUPER_CTOR_SIGNATURE
                     ),
+                    rust_name: #rust_path,
+                    line: line!(),
+                    param_names: &[#(#param_names,)*],
+                    annotations: #annotations,
+                    param_annotations: &[#(#param_annotations,)*],
                 };
         });
     components
@@ -545,6 +745,82 @@ fn constructor_wrapper_exported(
     Ok(true)
 }
 
+fn on_close_hook(
+    ctx: &MacroCtx,
+    components: &mut JavaClassCtx,
+    item: &mut ImplItemMethod,
+    attrs: &FunctionAttrs,
+) -> Result<bool> {
+    let nekojni = &ctx.nekojni;
+    let nekojni_internal = &ctx.internal;
+    let std = &ctx.std;
+    let sig_span = item.sig.span();
+
+    // Check the function signature.
+    if attrs.constructor
+        || attrs.init
+        || attrs.export_direct
+        || attrs.rename.is_some()
+        || attrs.has_annotations()
+    {
+        error(sig_span, "`#[jni(on_close)]` methods cannot be exported to Java.")?;
+    }
+    if attrs.lock.is_some() {
+        error(sig_span, "`#[jni(on_close)]` methods cannot specify a lock mode.")?;
+    }
+    if item.sig.abi.is_some() {
+        error(sig_span, "`#[jni(on_close)]` methods cannot be `extern`.")?;
+    }
+    if components.on_close.is_some() {
+        error(sig_span, "Only one `#[jni(on_close)]` method may be defined for a class.")?;
+    }
+    if item.sig.output != ReturnType::Default {
+        error(item.sig.output.span(), "`#[jni(on_close)]` methods cannot return a value.")?;
+    }
+
+    let mut sig = item.sig.clone();
+    let (self_mode, args) = process_method_args(ctx, components, &mut sig)?;
+    let env_ty = match args.as_slice() {
+        [FuncArgMode::ParamOwned(ty)] => elide_lifetimes(ty),
+        _ => error(
+            item.sig.inputs.span(),
+            "`#[jni(on_close)]` methods must take `self` and a `JniEnv` as parameters.",
+        )?,
+    };
+    components
+        .generated_type_checks
+        .extend(quote_spanned! { env_ty.span() =>
+            let promise = #nekojni_internal::promise::<#env_ty>();
+            #nekojni_internal::check_jnienv(promise);
+        });
+
+    let on_close_fn = match self_mode {
+        FuncSelfMode::SelfRef => quote! { on_close_ref },
+        FuncSelfMode::SelfMut if components.lock == LockMode::None => error(
+            item.sig.inputs.span(),
+            "`#[jni(on_close)]` methods cannot take `self` mutably in classes with \
+            `#[jni(lock = \"none\")]`.",
+        )?,
+        FuncSelfMode::SelfMut => quote! { on_close_mut },
+        _ => error(
+            item.sig.inputs.span(),
+            "`#[jni(on_close)]` methods must take `&self` or `&mut self`.",
+        )?,
+    };
+
+    let rust_name = &item.sig.ident;
+    components.on_close = Some(quote_spanned! { sig_span =>
+        fn on_close(
+            this: &#std::sync::Arc<#nekojni_internal::parking_lot::RwLock<Self>>,
+            env: #nekojni::JniEnv<'env>,
+        ) -> #nekojni::Result<()> {
+            #nekojni_internal::jni_env::#on_close_fn(this, |this| Self::#rust_name(this, env))
+        }
+    });
+
+    Ok(false)
+}
+
 pub(crate) fn method_wrapper(
     ctx: &MacroCtx,
     components: &mut JavaClassCtx,
@@ -562,6 +838,13 @@ pub(crate) fn method_wrapper(
         attrs.check_internal_used()?;
     }
     attrs.acc_synthetic = is_synthetic;
+    attrs.param_attrs = process_param_attrs(item)?;
+    for attr in &item.attrs {
+        if attr.path.is_ident("deprecated") {
+            attrs.rust_deprecated = true;
+            (attrs.deprecated_since, attrs.deprecated_note) = parse_rust_deprecated(attr)?;
+        }
+    }
 
     // process export to CLI tool
     if let Some(class_name) = &attrs.export_module_info {
@@ -587,6 +870,9 @@ pub(crate) fn method_wrapper(
     }
 
     // process the method itself
+    if attrs.on_close {
+        return on_close_hook(ctx, components, item, &attrs);
+    }
     if attrs.init && attrs.constructor {
         error(Span::call_site(), "`#[jni(init)]` methods cannot be `#[jni(constructor)]`.")?;
     }
@@ -603,6 +889,12 @@ pub(crate) fn method_wrapper(
             if attrs.init {
                 error(Span::call_site(), "`#[jni(init)]` methods cannot be `extern \"Java\"`.")?;
             }
+            if attrs.has_annotations() {
+                error(
+                    Span::call_site(),
+                    "Annotations cannot be added to `extern \"Java\"` methods.",
+                )?;
+            }
             if attrs.constructor {
                 return constructor_wrapper_java(ctx, components, item, &attrs);
             } else {
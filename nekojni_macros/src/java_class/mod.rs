@@ -2,10 +2,14 @@ mod method_handler;
 mod utils;
 
 use crate::{errors::*, utils::*};
+use utils::{JavaAnnotations, LockMode};
 use darling::FromAttributes;
 use enumset::EnumSet;
-use nekojni_utils::CFlags;
-use proc_macro2::{Ident, TokenStream};
+use nekojni_utils::{
+    signatures::{ClassName, GenericClassSig},
+    CFlags,
+};
+use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote;
 use syn::{parse2, spanned::Spanned, ImplItem, ItemImpl, Type};
 
@@ -24,10 +28,13 @@ pub struct JavaClassCtx {
 
     exports: Vec<TokenStream>,
     native_methods: Vec<TokenStream>,
+    exported_names: Vec<(String, Span)>,
 
     static_init: Vec<String>,
     instance_init: Vec<String>,
 
+    lock: LockMode,
+    on_close: Option<TokenStream>,
     is_internal: bool,
 }
 impl JavaClassCtx {
@@ -76,6 +83,23 @@ pub struct MacroArgs {
     #[darling(multiple)]
     implements: Vec<String>,
 
+    #[darling(default)]
+    lock: Option<String>,
+
+    #[darling(default)]
+    closeable: Option<bool>,
+    #[darling(default)]
+    close_method: Option<String>,
+    #[darling(default)]
+    from_rust: bool,
+
+    #[darling(multiple)]
+    annotate: Vec<String>,
+    #[darling(multiple)]
+    annotate_invisible: Vec<String>,
+    #[darling(default)]
+    deprecated: bool,
+
     // TODO: only_statics for classes with only statics
     // TODO: singleton(/object drawing on Scala syntax?) for... well, singletons
     #[darling(default, rename = "internal")]
@@ -163,14 +187,63 @@ fn jni_process_impl(
     let cl_id = if is_import { 0 } else { super::chain_next() };
 
     // Parse the supertypes.
-    let extends_class = match &args.extends {
-        Some(x) => Some(parse_class_name(&x)?.display_jni().to_string()),
+    let extends_generic = match &args.extends {
+        Some(x) => Some(parse_generic_class_name(&x)?),
         None => None,
     };
-    let mut implements_classes = Vec::new();
+    let mut implements_generic = Vec::new();
     for class in &args.implements {
-        implements_classes.push(parse_class_name(&class)?.display_jni().to_string());
+        implements_generic.push(parse_generic_class_name(&class)?);
     }
+    let extends_class = extends_generic
+        .as_ref()
+        .map(|x| x.erase().display_jni().to_string());
+    let implements_classes: Vec<_> = implements_generic
+        .iter()
+        .map(|x| x.erase().display_jni().to_string())
+        .collect();
+    let generic_signature = if extends_generic
+        .iter()
+        .chain(&implements_generic)
+        .any(|x| x.is_parameterized())
+    {
+        let extends = match extends_generic {
+            Some(class) => class,
+            None => ClassName::new(["java", "lang"].map(String::from), "Object").into(),
+        };
+        let signature = GenericClassSig::new(extends, implements_generic);
+        let signature = signature.display_generic().to_string();
+        Some(signature)
+    } else {
+        None
+    };
+
+    // Parse the annotations of the class.
+    let mut annotations =
+        JavaAnnotations::parse(attr.span(), &args.annotate, &args.annotate_invisible)?;
+    if args.deprecated {
+        annotations.deprecated(None, None);
+    }
+    if is_import && !annotations.is_empty() {
+        error(attr.span(), "Annotations cannot be added to `#[jni_import]` classes.")?;
+    }
+
+    // Parse the default lock mode for the class.
+    let lock = match &args.lock {
+        Some(mode) => LockMode::parse(attr.span(), mode)?,
+        None => LockMode::Block,
+    };
+
+    // Parse the name of the method used to close objects of the class.
+    let close_method = match (args.closeable, &args.close_method) {
+        (Some(false), Some(_)) => error(
+            attr.span(),
+            "`#[jni(close_method = ...)]` cannot be used with `#[jni(closeable = false)]`.",
+        )?,
+        (Some(false), None) => None,
+        (_, Some(name)) => Some(name.clone()),
+        (_, None) => Some("close".to_string()),
+    };
 
     // Build the context.
     let self_ty = &impl_block.self_ty;
@@ -186,8 +259,11 @@ fn jni_process_impl(
         generated_type_checks: Default::default(),
         exports: Default::default(),
         native_methods: Default::default(),
+        exported_names: Default::default(),
         static_init: vec![],
         instance_init: vec![],
+        lock,
+        on_close: None,
         is_internal,
     };
 
@@ -208,6 +284,28 @@ fn jni_process_impl(
     if !errors.is_empty() {
         return Err(errors);
     }
+    if is_import && components.on_close.is_some() {
+        error(attr.span(), "`#[jni(on_close)]` cannot be used in `#[jni_import]` classes.")?;
+    }
+    if is_import && components.settings.from_rust {
+        error(attr.span(), "`#[jni(from_rust)]` cannot be used in `#[jni_import]` classes.")?;
+    }
+    if let (false, Some(close_method)) = (is_import, &close_method) {
+        if close_method == "finalize" {
+            error(attr.span(), "`finalize` cannot be used as the close method of a class.")?;
+        }
+        for (name, span) in &components.exported_names {
+            if name == close_method {
+                error(
+                    *span,
+                    format!(
+                        "`{name}` conflicts with the method generated to close objects of this \
+                         class. Rename the method, or set `#[jni(close_method = ...)]`."
+                    ),
+                )?;
+            }
+        }
+    }
 
     // Retrieve ctx crate paths
     let nekojni = &ctx.nekojni;
@@ -216,22 +314,27 @@ fn jni_process_impl(
     let jni = &ctx.jni;
 
     // Create required supporting functions
-    let free_fn_rust = components.gensym_const("FREE_FN_NATIVE_METHOD");
     let free_fn_java = components.gensym_java("free");
-    components.generated_private_items.extend(quote! {
-        pub const #free_fn_rust: #nekojni_internal::exported_class::RustNativeMethod =
-            #nekojni_internal::exported_class::RustNativeMethod {
-                name: #free_fn_java,
-                sig: "(IZ)V",
-                fn_ptr: #nekojni_internal::jni_env::export_free::<#self_ty> as *mut _,
-                is_static: true,
-                export_direct_flags: #nekojni_internal::enumset::enum_set!(),
-                export_direct: false,
-            };
-    });
-    components
-        .native_methods
-        .push(quote! { __njni_priv::#free_fn_rust });
+    if !is_import {
+        let free_fn_rust = components.gensym_const("FREE_FN_NATIVE_METHOD");
+        components.generated_private_items.extend(quote! {
+            pub const #free_fn_rust: #nekojni_internal::exported_class::RustNativeMethod =
+                #nekojni_internal::exported_class::RustNativeMethod {
+                    name: #free_fn_java,
+                    sig: "(JZ)V",
+                    generic_sig: "(JZ)V",
+                    fn_ptr: #nekojni_internal::jni_env::export_free::<#self_ty> as *mut _,
+                    is_static: true,
+                    export_direct_flags: #nekojni_internal::enumset::enum_set!(),
+                    export_direct: false,
+                    annotations: #nekojni_internal::AnnotationList::EMPTY,
+                    param_annotations: &[],
+                };
+        });
+        components
+            .native_methods
+            .push(quote! { __njni_priv::#free_fn_rust });
+    }
 
     // Create the actual impl block
     let generated_impls = &components.generated_impls;
@@ -242,6 +345,7 @@ fn jni_process_impl(
     let native_methods = &components.native_methods;
     let static_init = &components.static_init;
     let instance_init = &components.instance_init;
+    let from_rust = components.settings.from_rust;
 
     let create_ref = if is_import {
         quote! {
@@ -251,7 +355,8 @@ fn jni_process_impl(
             fn create_jni_ref(
                 env: #nekojni::JniEnv<'env>,
                 obj: #jni::objects::JObject<'env>,
-                id: Option<u32>,
+                id: Option<u64>,
+                lock: #nekojni_internal::jni_ref::LockPolicy,
             ) -> #nekojni::Result<#nekojni::JniRef<'env, Self>>
                 where Self: #nekojni::objects::JavaClass<'env>
             {
@@ -266,11 +371,12 @@ fn jni_process_impl(
             fn create_jni_ref(
                 env: #nekojni::JniEnv<'env>,
                 obj: #jni::objects::JObject<'env>,
-                id: Option<u32>,
+                id: Option<u64>,
+                lock: #nekojni_internal::jni_ref::LockPolicy,
             ) -> #nekojni::Result<#nekojni::JniRef<'env, Self>>
                 where Self: #nekojni::objects::JavaClass<'env>
             {
-                #nekojni_internal::jni_ref::new_rust(env, #class_name, obj, id)
+                #nekojni_internal::jni_ref::new_rust(env, #class_name, obj, id, lock)
             }
         }
     };
@@ -281,6 +387,15 @@ fn jni_process_impl(
             Some(name) => quote! { #std::option::Option::Some(#name) },
             None => quote! { #std::option::Option::None },
         };
+        let close_method = match close_method {
+            Some(name) => quote! { #std::option::Option::Some(#name) },
+            None => quote! { #std::option::Option::None },
+        };
+        let generic_signature = match generic_signature {
+            Some(signature) => quote! { #std::option::Option::Some(#signature) },
+            None => quote! { #std::option::Option::None },
+        };
+        let annotations = annotations.to_tokens(&ctx);
         quote! {
             static CLASS_INFO: #nekojni_internal::JavaClassInfo =
                 #nekojni_internal::JavaClassInfo {
@@ -290,12 +405,16 @@ fn jni_process_impl(
                         name: #class_name,
                         super_class: #extends,
                         implements: &[#(#implements_classes,)*],
+                        generic_signature: #generic_signature,
+                        annotations: #annotations,
                         source_file: file!(),
 
                         id_field_name: "njni$$i",
                         static_init: &[#(#static_init,)*],
                         instance_init: &[#(#instance_init,)*],
                         free_fn: #free_fn_java,
+                        close_method: #close_method,
+                        from_rust: #from_rust,
 
                         exports: {
                             const LIST:
@@ -320,10 +439,15 @@ fn jni_process_impl(
             fn append_to_list(classes: &crate::__njni_module_info::GatherClasses) {}
         }
     };
+    let lock_free = components.lock == LockMode::None;
+    let on_close = &components.on_close;
     let import_export_items = if !is_import {
         quote! {
             impl<'env> #nekojni_internal::RustContents<'env> for #self_ty {
                 const ID_FIELD: &'static str = "njni$$i";
+                const LOCK_FREE: bool = #lock_free;
+                const FROM_RUST: bool = #from_rust;
+                #on_close
             }
             impl<'a> #nekojni_internal::Registration<#cl_id>
                 for crate::__njni_module_info::GatherClasses<'a>
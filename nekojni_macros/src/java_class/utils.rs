@@ -5,10 +5,10 @@ use crate::{
 };
 use enumset::EnumSet;
 use nekojni_utils::{
-    signatures::{ClassName, MethodName},
+    signatures::{Annotation, ClassName, ElementValue, MethodName},
     MFlags,
 };
-use proc_macro2::{Ident, Span};
+use proc_macro2::{Ident, Span, TokenStream};
 use quote::{quote, quote_spanned};
 use syn::{
     parse2, spanned::Spanned, Expr, FnArg, ImplItemMethod, Lifetime, Pat, ReturnType, Signature,
@@ -24,6 +24,46 @@ pub enum FuncSelfMode {
     Static,
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LockMode {
+    Block,
+    Try,
+    Timeout(u64),
+    None,
+}
+impl LockMode {
+    pub fn parse(span: Span, mode: &str) -> Result<LockMode> {
+        match mode {
+            "block" => Ok(LockMode::Block),
+            "try" => Ok(LockMode::Try),
+            "none" => Ok(LockMode::None),
+            _ => match mode.strip_prefix("timeout_ms=").map(|x| x.parse::<u64>()) {
+                Some(Ok(ms)) => Ok(LockMode::Timeout(ms)),
+                _ => error(
+                    span,
+                    "Lock mode must be one of `block`, `try`, `timeout_ms=<n>` or `none`.",
+                ),
+            },
+        }
+    }
+
+    fn to_policy(&self, ctx: &MacroCtx) -> TokenStream {
+        let nekojni_internal = &ctx.internal;
+        let std = &ctx.std;
+        match self {
+            LockMode::Block | LockMode::None => {
+                quote! { #nekojni_internal::jni_ref::LockPolicy::Block }
+            }
+            LockMode::Try => quote! { #nekojni_internal::jni_ref::LockPolicy::Try },
+            LockMode::Timeout(ms) => quote! {
+                #nekojni_internal::jni_ref::LockPolicy::Timeout(
+                    #std::time::Duration::from_millis(#ms)
+                )
+            },
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum FuncArgMode {
     ParamOwned(Type),
@@ -230,6 +270,117 @@ pub fn process_params_exported(
     Ok((param_tys, param_tys_elided, params_java, params_rust, ret_ty, ret_ty_elided))
 }
 
+/// Returns the Java names of the parameters of a function, not including its first parameter.
+///
+/// Parameters that are not bound to a simple identifier are left unnamed.
+pub fn java_param_names(sig: &Signature) -> Vec<String> {
+    let mut names = Vec::new();
+    for param in sig.inputs.iter().skip(1) {
+        match param {
+            FnArg::Typed(ty) => match &*ty.pat {
+                Pat::Ident(id) => {
+                    names.push(heck::AsLowerCamelCase(id.ident.to_string()).to_string())
+                }
+                _ => names.push(String::new()),
+            },
+            FnArg::Receiver(_) => unreachable!(),
+        }
+    }
+    names
+}
+
+/// The annotations added to an exported class, method or parameter, in Java syntax.
+#[derive(Clone, Debug, Default)]
+pub struct JavaAnnotations {
+    visible: Vec<String>,
+    invisible: Vec<String>,
+}
+impl JavaAnnotations {
+    /// Parses the annotations passed to `#[jni(annotate = ...)]` and
+    /// `#[jni(annotate_invisible = ...)]`.
+    pub fn parse(span: Span, visible: &[String], invisible: &[String]) -> Result<Self> {
+        let mut annotations = JavaAnnotations::default();
+        for (list, is_visible) in [(visible, true), (invisible, false)] {
+            for annotation in list {
+                match Annotation::parse_java(annotation) {
+                    Ok(v) => annotations.push(&v, is_visible),
+                    Err(e) => error(span, format!("Could not parse annotation: {e}"))?,
+                }
+            }
+        }
+        Ok(annotations)
+    }
+
+    fn push(&mut self, annotation: &Annotation, visible: bool) {
+        let annotation = annotation.display_java().to_string();
+        if visible {
+            self.visible.push(annotation);
+        } else {
+            self.invisible.push(annotation);
+        }
+    }
+    fn push_marker(&mut self, class: &str, visible: bool) {
+        let class = ClassName::parse_java(class).unwrap();
+        self.push(&Annotation::new(class), visible);
+    }
+
+    /// Adds the `@Deprecated` annotation used for `#[jni(deprecated)]` and Rust's `#[deprecated]`.
+    ///
+    /// `@Deprecated` has no element for the note of a Rust `#[deprecated]` attribute, so it is
+    /// added in an invisible `@DeprecationNote` annotation instead. nekojni adds the class of this
+    /// annotation to the jar.
+    pub fn deprecated(&mut self, since: Option<&str>, note: Option<&str>) {
+        let mut annotation =
+            Annotation::new(ClassName::parse_java("java.lang.Deprecated").unwrap());
+        if let Some(since) = since {
+            let since = ElementValue::String(since.to_string());
+            annotation.elements.push(("since".to_string(), since));
+        }
+        self.push(&annotation, true);
+
+        if let Some(note) = note {
+            let mut annotation = Annotation::new(
+                ClassName::parse_java("moe.lymia.nekojni.DeprecationNote").unwrap(),
+            );
+            let note = ElementValue::String(note.to_string());
+            annotation.elements.push(("value".to_string(), note));
+            self.push(&annotation, false);
+        }
+    }
+
+    /// Adds the annotations used for `#[jni(nullable)]` and `#[jni(not_null)]`. These are the
+    /// JetBrains annotations, which are understood by Kotlin and most Java tooling.
+    pub fn nullability(&mut self, span: Span, nullable: bool, not_null: bool) -> Result<()> {
+        if nullable && not_null {
+            error(span, "`nullable` and `not_null` cannot be used together.")?;
+        }
+        if nullable {
+            self.push_marker("org.jetbrains.annotations.Nullable", false);
+        }
+        if not_null {
+            self.push_marker("org.jetbrains.annotations.NotNull", false);
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.visible.is_empty() && self.invisible.is_empty()
+    }
+
+    /// Creates an `AnnotationList` containing these annotations.
+    pub fn to_tokens(&self, ctx: &MacroCtx) -> TokenStream {
+        let nekojni_internal = &ctx.internal;
+        let visible = &self.visible;
+        let invisible = &self.invisible;
+        quote! {
+            #nekojni_internal::AnnotationList {
+                visible: &[#(#visible,)*],
+                invisible: &[#(#invisible,)*],
+            }
+        }
+    }
+}
+
 pub struct ExportedFunction {
     pub java_name: String,
 }
@@ -258,10 +409,23 @@ pub fn export_function(
         None => &item.sig.ident,
     };
     let rust_name_str = item.sig.ident.to_string();
+    let rust_path = format!("{}::{rust_name_str}", quote!(#self_ty).to_string().replace(' ', ""));
     let java_name = match &attrs.rename {
         None => heck::AsLowerCamelCase(&rust_name_str).to_string(),
         Some(name) => name.clone(),
     };
+    if export_to_java {
+        components
+            .exported_names
+            .push((java_name.clone(), sig_span));
+    }
+    let param_names = java_param_names(&item.sig);
+    let annotations = attrs.annotations(sig_span)?.to_tokens(ctx);
+    let param_annotations: Vec<_> = attrs
+        .param_annotations()?
+        .iter()
+        .map(|x| x.to_tokens(ctx))
+        .collect();
 
     // Parse the type signature of the function.
     let (param_tys, param_tys_elided, params_java, params_rust, ret_ty, ret_ty_elided) =
@@ -279,37 +443,73 @@ pub fn export_function(
             )
         } else {
             (
-                quote! { id_param: u32, },
+                quote! { id_param: u64, },
                 quote! { id_param, },
-                quote! { "I", },
+                quote! { "J", },
                 quote! { let id_option = #std::option::Option::Some(id_param); },
             )
         };
+    let method_name = format!("{}.{}", components.class_name.replace('/', "."), java_name);
+    let lock = match &attrs.lock {
+        Some(_) if components.lock == LockMode::None => error(
+            sig_span,
+            "Lock modes cannot be set on methods of classes exported with `lock = \"none\"`.",
+        )?,
+        Some(mode) => match LockMode::parse(sig_span, mode)? {
+            LockMode::None => error(
+                sig_span,
+                "`lock = \"none\"` applies to the entire class, and must be set on the impl block.",
+            )?,
+            mode => mode,
+        },
+        None => components.lock,
+    };
+    if components.lock == LockMode::None {
+        if let FuncSelfMode::SelfMut | FuncSelfMode::EnvMut(_) = self_mode {
+            error(
+                sig_span,
+                "Classes exported with `lock = \"none\"` cannot have methods that take `self` \
+                 mutably.",
+            )?;
+        }
+    }
+    let lock = lock.to_policy(ctx);
     let extract_ref = match self_mode {
         FuncSelfMode::SelfRef => quote_spanned! { sig_span =>
             <#nekojni::JniRef<#lt, #self_ty> as #nekojni_internal::ExtractSelfParam<#lt>>
-                ::extract(env, this, id_option)
+                ::extract(env, this, id_option, #method_name, #lock)
         },
         FuncSelfMode::SelfMut => quote_spanned! { sig_span =>
             <#nekojni::JniRefMut<#lt, #self_ty> as #nekojni_internal::ExtractSelfParam<#lt>>
-                ::extract(env, this, id_option)
+                ::extract(env, this, id_option, #method_name, #lock)
         },
         FuncSelfMode::EnvRef(ty) | FuncSelfMode::EnvMut(ty) => {
             let ty = rewrite_self(&ty, &self_ty);
             quote_spanned! { sig_span =>
                 <#ty as #nekojni_internal::ExtractSelfParam<#lt>>
-                    ::extract(env, this, id_option)
+                    ::extract(env, this, id_option, #method_name, #lock)
             }
         }
         FuncSelfMode::Static => quote!(),
     };
     let (self_param, mut fn_call_body, is_static) = match self_mode {
-        FuncSelfMode::SelfRef | FuncSelfMode::EnvRef(_) => (
+        FuncSelfMode::SelfRef => (
             quote_spanned! { sig_span => this: #jni::sys::jobject },
             quote_spanned! { sig_span =>
                 #extract_id_option
-                let this_ref = unsafe { #extract_ref };
-                this_ref.#rust_name(#(#params_rust,)*)
+                let this_ref = unsafe { #extract_ref }?;
+                Ok(#nekojni_internal::jni_ref::with_current_self(&this_ref, || {
+                    this_ref.#rust_name(#(#params_rust,)*)
+                }))
+            },
+            false,
+        ),
+        FuncSelfMode::EnvRef(_) => (
+            quote_spanned! { sig_span => this: #jni::sys::jobject },
+            quote_spanned! { sig_span =>
+                #extract_id_option
+                let this_ref = unsafe { #extract_ref }?;
+                Ok(this_ref.#rust_name(#(#params_rust,)*))
             },
             false,
         ),
@@ -317,15 +517,15 @@ pub fn export_function(
             quote_spanned! { sig_span => this: #jni::sys::jobject },
             quote_spanned! { sig_span =>
                 #extract_id_option
-                let mut this_ref = unsafe { #extract_ref };
-                this_ref.#rust_name(#(#params_rust,)*)
+                let mut this_ref = unsafe { #extract_ref }?;
+                Ok(this_ref.#rust_name(#(#params_rust,)*))
             },
             false,
         ),
         FuncSelfMode::Static => (
             quote_spanned! { sig_span => this: #jni::sys::jclass },
             quote_spanned! { sig_span =>
-                #self_ty::#rust_name(env, #(#params_rust,)*)
+                Ok(#self_ty::#rust_name(env, #(#params_rust,)*))
             },
             true,
         ),
@@ -339,7 +539,7 @@ pub fn export_function(
                 fn_call_body = quote_spanned! { sig_span =>
                     let #name_param =
                         <#ty as #nekojni::conversions::JavaConversionOwned<#lt>>
-                            ::from_java(#name_java, env);
+                            ::from_java(#name_java, env)?;
                     #fn_call_body
                 };
             }
@@ -350,7 +550,7 @@ pub fn export_function(
                         #name_java, env, |#name_param| {
                             #fn_call_body
                         }
-                    )
+                    )?
                 };
             }
             FuncArgMode::ParamMut(ty) => {
@@ -360,7 +560,7 @@ pub fn export_function(
                         #name_java, env, |#name_param| {
                             #fn_call_body
                         }
-                    )
+                    )?
                 };
             }
         }
@@ -374,6 +574,8 @@ pub fn export_function(
 
     let method_sig = components.gensym_const("METHOD_SIG");
     let method_sig_native = components.gensym_const("METHOD_SIG_NATIVE");
+    let generic_sig = components.gensym_const("GENERIC_SIG");
+    let generic_sig_native = components.gensym_const("GENERIC_SIG_NATIVE");
 
     let access = enumset_to_toks(&ctx, quote!(#nekojni_internal::MFlags), m_flags);
     let export_direct = export_to_java && (is_static || attrs.export_direct);
@@ -412,7 +614,7 @@ pub fn export_function(
                 #extra_param
                 #(#params_java:
                     <#param_tys as #nekojni::conversions::JavaConversionType>::JavaType,)*
-            ) -> #ret_ty {
+            ) -> #nekojni::Result<#ret_ty> {
                 #fn_call_body
             }
 
@@ -437,6 +639,9 @@ pub fn export_function(
     let java_sig_params = quote_spanned! { sig_span =>
         #(<#param_tys_elided as #nekojni::conversions::JavaConversionType>::JNI_TYPE,)*
     };
+    let java_generic_sig_params = quote_spanned! { sig_span =>
+        #(<#param_tys_elided as #nekojni::conversions::JavaConversionType>::JNI_GENERIC_TYPE,)*
+    };
     let method_sig_body = quote_spanned! { sig_span =>
         const #method_sig: &'static str = #nekojni_internal::constcat_const!(
             "(",
@@ -444,9 +649,15 @@ pub fn export_function(
             ")",
             <#ret_ty_elided as #nekojni_internal::MethodReturn>::JNI_RETURN_TYPE
         );
+        const #generic_sig: &'static str = #nekojni_internal::constcat_const!(
+            "(",
+            #java_generic_sig_params
+            ")",
+            <#ret_ty_elided as #nekojni_internal::MethodReturn>::JNI_GENERIC_RETURN_TYPE
+        );
     };
-    let (method_sig, method_sig_native) = if is_static {
-        (method_sig.clone(), method_sig.clone())
+    let (method_sig, method_sig_native, generic_sig, generic_sig_native) = if is_static {
+        (method_sig.clone(), method_sig.clone(), generic_sig.clone(), generic_sig.clone())
     } else {
         components
             .generated_private_items
@@ -458,8 +669,15 @@ pub fn export_function(
                     ")",
                     <#ret_ty_elided as #nekojni_internal::MethodReturn>::JNI_RETURN_TYPE
                 );
+                const #generic_sig_native: &'static str = #nekojni_internal::constcat_const!(
+                    "(",
+                    #extra_param_java
+                    #java_generic_sig_params
+                    ")",
+                    <#ret_ty_elided as #nekojni_internal::MethodReturn>::JNI_GENERIC_RETURN_TYPE
+                );
             });
-        (method_sig, method_sig_native)
+        (method_sig, method_sig_native, generic_sig, generic_sig_native)
     };
     components
         .generated_private_items
@@ -468,10 +686,13 @@ pub fn export_function(
                 #nekojni_internal::exported_class::RustNativeMethod {
                     name: #java_name,
                     sig: #method_sig_native,
+                    generic_sig: #generic_sig_native,
                     fn_ptr: #entry_point_name as *mut #std::ffi::c_void,
                     is_static: #is_static,
                     export_direct_flags: #access,
                     export_direct: #export_direct,
+                    annotations: #annotations,
+                    param_annotations: &[#(#param_annotations,)*],
                 };
         });
     if export_to_java && !export_direct {
@@ -484,9 +705,15 @@ pub fn export_function(
                         flags: #access,
                         name: #java_name,
                         signature: #method_sig,
+                        generic_signature: #generic_sig,
                         native_name: #java_name,
                         native_signature: #method_sig_native,
                         has_id_param: !#is_static,
+                        rust_name: #rust_path,
+                        line: line!(),
+                        param_names: &[#(#param_names,)*],
+                        annotations: #annotations,
+                        param_annotations: &[#(#param_annotations,)*],
                     };
             });
         components
@@ -2,7 +2,7 @@
 
 use crate::errors::{Error, Result};
 use enumset::{EnumSet, EnumSetType};
-use nekojni_utils::signatures::ClassName;
+use nekojni_utils::signatures::{ClassName, GenericBasicType, GenericClassType, GenericType};
 use proc_macro::TokenStream;
 use proc_macro2::{Span, TokenStream as SynTokenStream};
 use proc_macro_crate::FoundCrate;
@@ -178,6 +178,15 @@ pub fn parse_class_name<'a>(name: &str) -> Result<ClassName> {
     }
 }
 
+/// Parses a Java formatted class name that may have type arguments.
+pub fn parse_generic_class_name(name: &str) -> Result<GenericClassType> {
+    match GenericType::parse_java(name) {
+        Ok(GenericType { basic_sig: GenericBasicType::Class(class), array_dim: 0 }) => Ok(class),
+        Ok(_) => error(Span::call_site(), format!("`{name}` is not a class.")),
+        Err(e) => error(Span::call_site(), format!("Could not parse class name: {e:?}")),
+    }
+}
+
 /// Returns the only lifetime in a method's generics, or return an error.
 pub fn check_only_lt(item: &ImplItemMethod) -> Result<Option<Lifetime>> {
     if item.sig.generics.params.is_empty() {
@@ -0,0 +1,17 @@
+/// The annotations applied to an exported class, method or parameter, in Java syntax.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AnnotationList<'a> {
+    /// Annotations that are available through reflection at runtime.
+    pub visible: &'a [&'a str],
+    /// Annotations that are stored in the class file, but are not available through reflection.
+    pub invisible: &'a [&'a str],
+}
+impl<'a> AnnotationList<'a> {
+    /// An empty list of annotations.
+    pub const EMPTY: AnnotationList<'static> = AnnotationList { visible: &[], invisible: &[] };
+
+    /// Returns whether this list contains any annotations.
+    pub fn is_empty(&self) -> bool {
+        self.visible.is_empty() && self.invisible.is_empty()
+    }
+}
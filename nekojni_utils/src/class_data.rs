@@ -1,28 +1,127 @@
-use crate::{generate_precompiled, NativeClassWrapper};
+use crate::{
+    classfile, generate_precompiled, native_class_wrapper, NativeClassWrapper, VerifyError,
+};
 use std::{
     collections::HashMap,
     io::{Cursor, Write},
 };
 use zip::{write::FileOptions, ZipWriter};
 
+/// A version of Java that generated classes may target.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct JavaVersion(u8);
+impl JavaVersion {
+    pub const JAVA_8: JavaVersion = JavaVersion(8);
+    pub const JAVA_9: JavaVersion = JavaVersion(9);
+    pub const JAVA_11: JavaVersion = JavaVersion(11);
+    pub const JAVA_17: JavaVersion = JavaVersion(17);
+    pub const JAVA_21: JavaVersion = JavaVersion(21);
+
+    /// Returns the given Java version, or `None` if it cannot be targeted.
+    pub fn new(version: u8) -> Option<Self> {
+        if (8..=21).contains(&version) {
+            Some(JavaVersion(version))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the release number of this Java version, such as `8` or `17`.
+    pub fn release(&self) -> u8 {
+        self.0
+    }
+
+    /// Returns the class file major version used by this Java version.
+    pub fn major_version(&self) -> u16 {
+        self.0 as u16 + 44
+    }
+
+    /// Whether classes may use the `NestHost` and `NestMembers` attributes to access each other's
+    /// private members.
+    pub fn supports_nestmates(&self) -> bool {
+        *self >= JavaVersion::JAVA_11
+    }
+}
+impl Default for JavaVersion {
+    fn default() -> Self {
+        JavaVersion::JAVA_8
+    }
+}
+
+/// Options controlling how the Java side of exported classes is generated.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GenerationOptions {
+    /// Whether objects are freed by a shared `java.lang.ref.Cleaner` rather than by `finalize`.
+    ///
+    /// This requires Java 9 or later.
+    pub use_cleaner: bool,
+    /// The Java version generated classes target.
+    pub target_version: JavaVersion,
+}
+impl GenerationOptions {
+    /// Checks that the features used are supported by the target version.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.use_cleaner && self.target_version < JavaVersion::JAVA_9 {
+            return Err(format!(
+                "Freeing objects with a `java.lang.ref.Cleaner` requires Java 9 or later, but \
+                 Java {} is targeted.",
+                self.target_version.release(),
+            ));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub struct ClassData {
     class_info: HashMap<String, Vec<u8>>,
     resources: HashMap<String, Vec<u8>>,
+    method_origins: HashMap<(String, String, String), String>,
+    errors: Vec<VerifyError>,
     loader_name: Option<String>,
+    options: GenerationOptions,
+}
+impl Default for ClassData {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl ClassData {
     pub fn new() -> Self {
+        Self::with_options(GenerationOptions::default())
+    }
+    pub fn with_options(options: GenerationOptions) -> Self {
+        if let Err(e) = options.validate() {
+            panic!("{e}");
+        }
         ClassData {
             class_info: Default::default(),
             resources: Default::default(),
+            method_origins: Default::default(),
+            errors: Vec::new(),
             loader_name: None,
+            options,
         }
     }
 
-    pub(crate) fn add_class(&mut self, name: &str, data: impl Into<Vec<u8>>) {
+    pub fn options(&self) -> &GenerationOptions {
+        &self.options
+    }
+
+    /// Adds a class to the generated data, replacing any existing class with the same name.
+    pub fn add_class(&mut self, name: &str, data: impl Into<Vec<u8>>) {
         self.class_info.insert(name.to_string(), data.into());
     }
+    /// Records the Rust function a generated method was created for, for use in error messages.
+    pub(crate) fn add_method_origin(&mut self, class: &str, name: &str, sig: &str, origin: &str) {
+        self.method_origins
+            .insert((class.to_string(), name.to_string(), sig.to_string()), origin.to_string());
+    }
+    /// Records an error in the information passed in for a generated class, to be reported by
+    /// [`ClassData::verify`].
+    pub(crate) fn add_error(&mut self, error: VerifyError) {
+        self.errors.push(error);
+    }
 
     pub fn add_null_loader(&mut self, name: &str) {
         assert!(self.loader_name.is_none());
@@ -59,6 +158,14 @@ impl ClassData {
                 self.loader_name.as_deref().unwrap(),
             ),
         );
+        if self.options.use_cleaner {
+            let holder_name = native_class_wrapper::cleaner_holder_name(name);
+            let holder = native_class_wrapper::generate_cleaner_holder(
+                &holder_name,
+                self.options.target_version,
+            );
+            self.class_info.insert(holder_name, holder);
+        }
     }
     pub fn add_module_exception(&mut self, name: &str) {
         self.class_info
@@ -72,6 +179,34 @@ impl ClassData {
         self.resources.insert(name.to_string(), data);
     }
 
+    pub fn classes(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.class_info
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_slice()))
+    }
+
+    /// Checks that all classes are well-formed, and that the code in them passes verification.
+    ///
+    /// Errors recorded while generating the classes, such as invalid generic signatures, are
+    /// reported first.
+    pub fn verify(&self) -> Result<(), Box<VerifyError>> {
+        if let Some(e) = self.errors.first() {
+            return Err(Box::new(e.clone()));
+        }
+        let mut names: Vec<_> = self.class_info.keys().collect();
+        names.sort();
+        for name in names {
+            if let Err(mut e) = classfile::verify_class(name, &self.class_info[name]) {
+                if let Some((method, sig)) = &e.method {
+                    let key = (name.clone(), method.clone(), sig.clone());
+                    e.origin = self.method_origins.get(&key).cloned();
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
     pub fn make_jar(&self) -> Vec<u8> {
         let mut writer = ZipWriter::new(Cursor::new(Vec::<u8>::new()));
 
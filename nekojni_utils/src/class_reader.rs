@@ -0,0 +1,858 @@
+use crate::{CFlags, FFlags, MFlags};
+use enumset::EnumSet;
+use std::io::{Cursor, Error, ErrorKind, Result, Write};
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            return Err(invalid("Unexpected end of class file."));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+    fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+fn write_u8(out: &mut impl Write, v: u8) -> Result<()> {
+    out.write_all(&[v])
+}
+fn write_u16(out: &mut impl Write, v: u16) -> Result<()> {
+    out.write_all(&v.to_be_bytes())
+}
+fn write_u32(out: &mut impl Write, v: u32) -> Result<()> {
+    out.write_all(&v.to_be_bytes())
+}
+fn write_len(out: &mut impl Write, len: usize) -> Result<()> {
+    assert!(len <= u16::MAX as usize);
+    write_u16(out, len as u16)
+}
+
+/// An entry in the constant pool of a class file.
+///
+/// References to other entries are stored as raw constant pool indexes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PoolEntry {
+    Utf8(String),
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    Class(u16),
+    String(u16),
+    FieldRef {
+        class_index: u16,
+        name_and_type_index: u16,
+    },
+    MethodRef {
+        class_index: u16,
+        name_and_type_index: u16,
+    },
+    InterfaceMethodRef {
+        class_index: u16,
+        name_and_type_index: u16,
+    },
+    NameAndType {
+        name_index: u16,
+        descriptor_index: u16,
+    },
+    MethodHandle {
+        reference_kind: u8,
+        reference_index: u16,
+    },
+    MethodType {
+        descriptor_index: u16,
+    },
+    Dynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
+    InvokeDynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
+    Module {
+        name_index: u16,
+    },
+    Package {
+        name_index: u16,
+    },
+    /// The unusable slot at index 0, or following a `Long` or `Double` entry.
+    Unusable,
+}
+impl PoolEntry {
+    fn kind(&self) -> &'static str {
+        match self {
+            PoolEntry::Utf8(_) => "Utf8",
+            PoolEntry::Integer(_) => "Integer",
+            PoolEntry::Float(_) => "Float",
+            PoolEntry::Long(_) => "Long",
+            PoolEntry::Double(_) => "Double",
+            PoolEntry::Class(_) => "Class",
+            PoolEntry::String(_) => "String",
+            PoolEntry::FieldRef { .. } => "Fieldref",
+            PoolEntry::MethodRef { .. } => "Methodref",
+            PoolEntry::InterfaceMethodRef { .. } => "InterfaceMethodref",
+            PoolEntry::NameAndType { .. } => "NameAndType",
+            PoolEntry::MethodHandle { .. } => "MethodHandle",
+            PoolEntry::MethodType { .. } => "MethodType",
+            PoolEntry::Dynamic { .. } => "Dynamic",
+            PoolEntry::InvokeDynamic { .. } => "InvokeDynamic",
+            PoolEntry::Module { .. } => "Module",
+            PoolEntry::Package { .. } => "Package",
+            PoolEntry::Unusable => "unusable",
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self> {
+        Ok(match reader.u8()? {
+            1 => {
+                let len = reader.u16()? as usize;
+                let data = reader.bytes(len)?;
+                match cesu8::from_java_cesu8(data) {
+                    Ok(str) => PoolEntry::Utf8(str.into_owned()),
+                    Err(_) => return Err(invalid("Invalid modified UTF-8 in constant pool.")),
+                }
+            }
+            3 => PoolEntry::Integer(reader.u32()? as i32),
+            4 => PoolEntry::Float(f32::from_bits(reader.u32()?)),
+            5 => PoolEntry::Long(reader.u64()? as i64),
+            6 => PoolEntry::Double(f64::from_bits(reader.u64()?)),
+            7 => PoolEntry::Class(reader.u16()?),
+            8 => PoolEntry::String(reader.u16()?),
+            9 => PoolEntry::FieldRef {
+                class_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            10 => PoolEntry::MethodRef {
+                class_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            11 => PoolEntry::InterfaceMethodRef {
+                class_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            12 => PoolEntry::NameAndType {
+                name_index: reader.u16()?,
+                descriptor_index: reader.u16()?,
+            },
+            15 => PoolEntry::MethodHandle {
+                reference_kind: reader.u8()?,
+                reference_index: reader.u16()?,
+            },
+            16 => PoolEntry::MethodType { descriptor_index: reader.u16()? },
+            17 => PoolEntry::Dynamic {
+                bootstrap_method_attr_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            18 => PoolEntry::InvokeDynamic {
+                bootstrap_method_attr_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
+            },
+            19 => PoolEntry::Module { name_index: reader.u16()? },
+            20 => PoolEntry::Package { name_index: reader.u16()? },
+            tag => return Err(invalid(format!("Unknown constant pool tag {tag}."))),
+        })
+    }
+    fn write(&self, out: &mut impl Write) -> Result<()> {
+        match self {
+            PoolEntry::Utf8(str) => {
+                let cesu = cesu8::to_java_cesu8(str);
+                write_u8(out, 1)?;
+                write_len(out, cesu.len())?;
+                out.write_all(&cesu)?;
+            }
+            PoolEntry::Integer(v) => {
+                write_u8(out, 3)?;
+                write_u32(out, *v as u32)?;
+            }
+            PoolEntry::Float(v) => {
+                write_u8(out, 4)?;
+                write_u32(out, v.to_bits())?;
+            }
+            PoolEntry::Long(v) => {
+                write_u8(out, 5)?;
+                out.write_all(&v.to_be_bytes())?;
+            }
+            PoolEntry::Double(v) => {
+                write_u8(out, 6)?;
+                out.write_all(&v.to_bits().to_be_bytes())?;
+            }
+            PoolEntry::Class(a) => {
+                write_u8(out, 7)?;
+                write_u16(out, *a)?;
+            }
+            PoolEntry::String(a) => {
+                write_u8(out, 8)?;
+                write_u16(out, *a)?;
+            }
+            PoolEntry::FieldRef { class_index: a, name_and_type_index: b } => {
+                write_u8(out, 9)?;
+                write_u16(out, *a)?;
+                write_u16(out, *b)?;
+            }
+            PoolEntry::MethodRef { class_index: a, name_and_type_index: b } => {
+                write_u8(out, 10)?;
+                write_u16(out, *a)?;
+                write_u16(out, *b)?;
+            }
+            PoolEntry::InterfaceMethodRef { class_index: a, name_and_type_index: b } => {
+                write_u8(out, 11)?;
+                write_u16(out, *a)?;
+                write_u16(out, *b)?;
+            }
+            PoolEntry::NameAndType { name_index: a, descriptor_index: b } => {
+                write_u8(out, 12)?;
+                write_u16(out, *a)?;
+                write_u16(out, *b)?;
+            }
+            PoolEntry::MethodHandle { reference_kind, reference_index } => {
+                write_u8(out, 15)?;
+                write_u8(out, *reference_kind)?;
+                write_u16(out, *reference_index)?;
+            }
+            PoolEntry::MethodType { descriptor_index } => {
+                write_u8(out, 16)?;
+                write_u16(out, *descriptor_index)?;
+            }
+            PoolEntry::Dynamic { bootstrap_method_attr_index: a, name_and_type_index: b } => {
+                write_u8(out, 17)?;
+                write_u16(out, *a)?;
+                write_u16(out, *b)?;
+            }
+            PoolEntry::InvokeDynamic { bootstrap_method_attr_index: a, name_and_type_index: b } => {
+                write_u8(out, 18)?;
+                write_u16(out, *a)?;
+                write_u16(out, *b)?;
+            }
+            PoolEntry::Module { name_index } => {
+                write_u8(out, 19)?;
+                write_u16(out, *name_index)?;
+            }
+            PoolEntry::Package { name_index } => {
+                write_u8(out, 20)?;
+                write_u16(out, *name_index)?;
+            }
+            PoolEntry::Unusable => {}
+        }
+        Ok(())
+    }
+}
+
+/// The constant pool of a class file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConstantPool {
+    entries: Vec<PoolEntry>,
+}
+impl ConstantPool {
+    fn read(reader: &mut Reader) -> Result<Self> {
+        let count = reader.u16()? as usize;
+        if count == 0 {
+            return Err(invalid("Constant pool count must be at least 1."));
+        }
+
+        let mut entries = vec![PoolEntry::Unusable];
+        while entries.len() < count {
+            let entry = PoolEntry::read(reader)?;
+            let is_wide = matches!(entry, PoolEntry::Long(_) | PoolEntry::Double(_));
+            entries.push(entry);
+            if is_wide {
+                entries.push(PoolEntry::Unusable);
+            }
+        }
+        if entries.len() != count {
+            return Err(invalid("Long or Double entry at the end of the constant pool."));
+        }
+        Ok(ConstantPool { entries })
+    }
+    fn write(&self, out: &mut impl Write) -> Result<()> {
+        write_len(out, self.entries.len())?;
+        for entry in &self.entries {
+            entry.write(out)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of slots in the constant pool, including the unusable slot at index 0.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Returns whether the constant pool has no usable entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.len() <= 1
+    }
+
+    /// Returns the entries in the constant pool, along with their indexes.
+    pub fn entries(&self) -> impl Iterator<Item = (u16, &PoolEntry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (i as u16, x))
+            .filter(|x| x.1 != &PoolEntry::Unusable)
+    }
+
+    pub fn get(&self, index: u16) -> Result<&PoolEntry> {
+        match self.entries.get(index as usize) {
+            Some(PoolEntry::Unusable) | None => {
+                Err(invalid(format!("Constant pool index {index} is not valid.")))
+            }
+            Some(entry) => Ok(entry),
+        }
+    }
+    fn wrong_kind(&self, index: u16, expected: &str) -> Error {
+        let found = self
+            .entries
+            .get(index as usize)
+            .map_or("unusable", PoolEntry::kind);
+        invalid(format!(
+            "Constant pool entry {index} is a {found} entry, not a {expected} entry."
+        ))
+    }
+
+    pub fn utf8(&self, index: u16) -> Result<&str> {
+        match self.get(index)? {
+            PoolEntry::Utf8(str) => Ok(str),
+            _ => Err(self.wrong_kind(index, "Utf8")),
+        }
+    }
+    pub fn class_name(&self, index: u16) -> Result<&str> {
+        match self.get(index)? {
+            PoolEntry::Class(name) => self.utf8(*name),
+            _ => Err(self.wrong_kind(index, "Class")),
+        }
+    }
+    /// Returns the name and descriptor of a `NameAndType` entry.
+    pub fn name_and_type(&self, index: u16) -> Result<(&str, &str)> {
+        match self.get(index)? {
+            PoolEntry::NameAndType { name_index, descriptor_index } => {
+                Ok((self.utf8(*name_index)?, self.utf8(*descriptor_index)?))
+            }
+            _ => Err(self.wrong_kind(index, "NameAndType")),
+        }
+    }
+    /// Returns the class, name and descriptor of a field or method reference.
+    pub fn member_ref(&self, index: u16) -> Result<(&str, &str, &str)> {
+        match self.get(index)? {
+            PoolEntry::FieldRef { class_index, name_and_type_index }
+            | PoolEntry::MethodRef { class_index, name_and_type_index }
+            | PoolEntry::InterfaceMethodRef { class_index, name_and_type_index } => {
+                let (name, desc) = self.name_and_type(*name_and_type_index)?;
+                Ok((self.class_name(*class_index)?, name, desc))
+            }
+            _ => Err(self.wrong_kind(index, "Fieldref or Methodref")),
+        }
+    }
+
+    /// Replaces all `Utf8` entries exactly equal to `source`, returning the number replaced.
+    pub fn replace_utf8(&mut self, source: &str, target: &str) -> usize {
+        let mut count = 0;
+        for entry in &mut self.entries {
+            if let PoolEntry::Utf8(str) = entry {
+                if str == source {
+                    *str = target.to_string();
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Renames a class, including in any descriptors or signatures it is used in. Returns the
+    /// number of entries changed.
+    pub fn rename_class(&mut self, source: &str, target: &str) -> usize {
+        let source_desc = format!("L{source};");
+        let target_desc = format!("L{target};");
+        let source_generic = format!("L{source}<");
+        let target_generic = format!("L{target}<");
+
+        let mut count = 0;
+        for entry in &mut self.entries {
+            if let PoolEntry::Utf8(str) = entry {
+                if str == source {
+                    *str = target.to_string();
+                    count += 1;
+                } else if str.contains(&source_desc) || str.contains(&source_generic) {
+                    *str = str
+                        .replace(&source_desc, &target_desc)
+                        .replace(&source_generic, &target_generic);
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+}
+
+/// An attribute, with its contents left unparsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeInfo {
+    pub name_index: u16,
+    pub data: Vec<u8>,
+}
+impl AttributeInfo {
+    fn read_table(reader: &mut Reader) -> Result<Vec<Self>> {
+        let count = reader.u16()?;
+        let mut attributes = Vec::new();
+        for _ in 0..count {
+            let name_index = reader.u16()?;
+            let len = reader.u32()? as usize;
+            let data = reader.bytes(len)?.to_vec();
+            attributes.push(AttributeInfo { name_index, data });
+        }
+        Ok(attributes)
+    }
+    fn write_table(attributes: &[Self], out: &mut impl Write) -> Result<()> {
+        write_len(out, attributes.len())?;
+        for attribute in attributes {
+            assert!(attribute.data.len() <= u32::MAX as usize);
+            write_u16(out, attribute.name_index)?;
+            write_u32(out, attribute.data.len() as u32)?;
+            out.write_all(&attribute.data)?;
+        }
+        Ok(())
+    }
+
+    pub fn name<'a>(&self, pool: &'a ConstantPool) -> Result<&'a str> {
+        pool.utf8(self.name_index)
+    }
+}
+
+/// A field of a class file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldInfo {
+    pub access_flags: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<AttributeInfo>,
+}
+impl FieldInfo {
+    pub fn flags(&self) -> EnumSet<FFlags> {
+        EnumSet::from_u16_truncated(self.access_flags)
+    }
+    pub fn name<'a>(&self, pool: &'a ConstantPool) -> Result<&'a str> {
+        pool.utf8(self.name_index)
+    }
+    pub fn descriptor<'a>(&self, pool: &'a ConstantPool) -> Result<&'a str> {
+        pool.utf8(self.descriptor_index)
+    }
+}
+
+/// A method of a class file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodInfo {
+    pub access_flags: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<AttributeInfo>,
+}
+impl MethodInfo {
+    pub fn flags(&self) -> EnumSet<MFlags> {
+        EnumSet::from_u16_truncated(self.access_flags)
+    }
+    pub fn name<'a>(&self, pool: &'a ConstantPool) -> Result<&'a str> {
+        pool.utf8(self.name_index)
+    }
+    pub fn descriptor<'a>(&self, pool: &'a ConstantPool) -> Result<&'a str> {
+        pool.utf8(self.descriptor_index)
+    }
+
+    /// Parses the `Code` attribute of this method, if it has one.
+    pub fn code(&self, pool: &ConstantPool) -> Result<Option<Code>> {
+        for attribute in &self.attributes {
+            if attribute.name(pool)? == "Code" {
+                return Code::parse(&attribute.data).map(Some);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// An entry in the exception table of a method.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExceptionTableEntry {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    /// The class of exceptions caught, or 0 if all exceptions are caught.
+    pub catch_type: u16,
+}
+
+/// The contents of a `Code` attribute.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Code {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: Vec<u8>,
+    pub exception_table: Vec<ExceptionTableEntry>,
+    pub attributes: Vec<AttributeInfo>,
+}
+impl Code {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+        let max_stack = reader.u16()?;
+        let max_locals = reader.u16()?;
+        let len = reader.u32()? as usize;
+        let code = reader.bytes(len)?.to_vec();
+        let mut exception_table = Vec::new();
+        for _ in 0..reader.u16()? {
+            exception_table.push(ExceptionTableEntry {
+                start_pc: reader.u16()?,
+                end_pc: reader.u16()?,
+                handler_pc: reader.u16()?,
+                catch_type: reader.u16()?,
+            });
+        }
+        let attributes = AttributeInfo::read_table(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(invalid("Code attribute has trailing data."));
+        }
+        Ok(Code { max_stack, max_locals, code, exception_table, attributes })
+    }
+
+    /// Decodes the instructions in this method.
+    pub fn instructions(&self) -> Result<Vec<Instruction>> {
+        let mut reader = Reader::new(&self.code);
+        let mut instructions = Vec::new();
+        while !reader.is_empty() {
+            instructions.push(Instruction::read(&mut reader)?);
+        }
+        Ok(instructions)
+    }
+}
+
+/// The operand of an instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    None,
+    /// An immediate value, as used by `bipush` and `sipush`.
+    Immediate(i32),
+    /// A local variable index.
+    Local(u16),
+    /// A constant pool index.
+    Pool(u16),
+    /// The absolute bytecode offset of a branch target.
+    Branch(i64),
+    Iinc {
+        local: u16,
+        delta: i16,
+    },
+    NewArray {
+        atype: u8,
+    },
+    MultiANewArray {
+        class: u16,
+        dims: u8,
+    },
+    InvokeInterface {
+        method: u16,
+        count: u8,
+    },
+    TableSwitch {
+        default: i64,
+        low: i32,
+        high: i32,
+        targets: Vec<i64>,
+    },
+    LookupSwitch {
+        default: i64,
+        pairs: Vec<(i32, i64)>,
+    },
+}
+
+/// A decoded instruction.
+///
+/// For instructions using the `wide` prefix, `opcode` is the opcode of the modified instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub offset: u32,
+    pub opcode: u8,
+    pub operand: Operand,
+}
+impl Instruction {
+    fn read(reader: &mut Reader) -> Result<Self> {
+        let offset = reader.pos as u32;
+        let opcode = reader.u8()?;
+        let branch = |delta: i64| offset as i64 + delta;
+
+        let operand = match opcode {
+            0x10 => Operand::Immediate(reader.u8()? as i8 as i32),
+            0x11 => Operand::Immediate(reader.u16()? as i16 as i32),
+            0x12 => Operand::Pool(reader.u8()? as u16),
+            0x13 | 0x14 => Operand::Pool(reader.u16()?),
+            0x15..=0x19 | 0x36..=0x3a | 0xa9 => Operand::Local(reader.u8()? as u16),
+            0x84 => Operand::Iinc { local: reader.u8()? as u16, delta: reader.u8()? as i8 as i16 },
+            0x99..=0xa8 | 0xc6 | 0xc7 => Operand::Branch(branch(reader.u16()? as i16 as i64)),
+            0xc8 | 0xc9 => Operand::Branch(branch(reader.u32()? as i32 as i64)),
+            0xaa | 0xab => {
+                for _ in 0..(4 - reader.pos % 4) % 4 {
+                    reader.u8()?;
+                }
+                let default = branch(reader.u32()? as i32 as i64);
+                if opcode == 0xaa {
+                    let low = reader.u32()? as i32;
+                    let high = reader.u32()? as i32;
+                    if high < low {
+                        return Err(invalid(format!("tableswitch at {offset} has high < low.")));
+                    }
+                    let mut targets = Vec::new();
+                    for _ in low..=high {
+                        targets.push(branch(reader.u32()? as i32 as i64));
+                    }
+                    Operand::TableSwitch { default, low, high, targets }
+                } else {
+                    let count = reader.u32()? as i32;
+                    if count < 0 {
+                        return Err(invalid(format!("lookupswitch at {offset} has npairs < 0.")));
+                    }
+                    let mut pairs = Vec::new();
+                    for _ in 0..count {
+                        pairs.push((reader.u32()? as i32, branch(reader.u32()? as i32 as i64)));
+                    }
+                    Operand::LookupSwitch { default, pairs }
+                }
+            }
+            0xb2..=0xb8 | 0xbb | 0xbd | 0xc0 | 0xc1 => Operand::Pool(reader.u16()?),
+            0xb9 => {
+                let operand =
+                    Operand::InvokeInterface { method: reader.u16()?, count: reader.u8()? };
+                reader.u8()?;
+                operand
+            }
+            0xba => {
+                let operand = Operand::Pool(reader.u16()?);
+                reader.u16()?;
+                operand
+            }
+            0xbc => Operand::NewArray { atype: reader.u8()? },
+            0xc5 => Operand::MultiANewArray { class: reader.u16()?, dims: reader.u8()? },
+            0xc4 => {
+                let opcode = reader.u8()?;
+                let operand = match opcode {
+                    0x15..=0x19 | 0x36..=0x3a | 0xa9 => Operand::Local(reader.u16()?),
+                    0x84 => Operand::Iinc { local: reader.u16()?, delta: reader.u16()? as i16 },
+                    _ => {
+                        return Err(invalid(format!(
+                            "{} at {offset} cannot be used with wide.",
+                            opcode_name(opcode),
+                        )))
+                    }
+                };
+                return Ok(Instruction { offset, opcode, operand });
+            }
+            0xcb..=0xfd => {
+                return Err(invalid(format!("Unknown opcode 0x{opcode:02x} at {offset}.")))
+            }
+            _ => Operand::None,
+        };
+        Ok(Instruction { offset, opcode, operand })
+    }
+
+    /// Returns the mnemonic of this instruction.
+    pub fn name(&self) -> &'static str {
+        opcode_name(self.opcode)
+    }
+}
+
+/// Returns the mnemonic of an opcode.
+pub fn opcode_name(opcode: u8) -> &'static str {
+    OPCODE_NAMES
+        .get(opcode as usize)
+        .copied()
+        .unwrap_or(match opcode {
+            0xfe => "impdep1",
+            0xff => "impdep2",
+            _ => "<unknown>",
+        })
+}
+
+#[rustfmt::skip]
+const OPCODE_NAMES: &[&str] = &[
+    "nop", "aconst_null", "iconst_m1", "iconst_0", "iconst_1", "iconst_2", "iconst_3", "iconst_4",
+    "iconst_5", "lconst_0", "lconst_1", "fconst_0", "fconst_1", "fconst_2", "dconst_0", "dconst_1",
+    "bipush", "sipush", "ldc", "ldc_w", "ldc2_w", "iload", "lload", "fload",
+    "dload", "aload", "iload_0", "iload_1", "iload_2", "iload_3", "lload_0", "lload_1",
+    "lload_2", "lload_3", "fload_0", "fload_1", "fload_2", "fload_3", "dload_0", "dload_1",
+    "dload_2", "dload_3", "aload_0", "aload_1", "aload_2", "aload_3", "iaload", "laload",
+    "faload", "daload", "aaload", "baload", "caload", "saload", "istore", "lstore",
+    "fstore", "dstore", "astore", "istore_0", "istore_1", "istore_2", "istore_3", "lstore_0",
+    "lstore_1", "lstore_2", "lstore_3", "fstore_0", "fstore_1", "fstore_2", "fstore_3", "dstore_0",
+    "dstore_1", "dstore_2", "dstore_3", "astore_0", "astore_1", "astore_2", "astore_3", "iastore",
+    "lastore", "fastore", "dastore", "aastore", "bastore", "castore", "sastore", "pop",
+    "pop2", "dup", "dup_x1", "dup_x2", "dup2", "dup2_x1", "dup2_x2", "swap",
+    "iadd", "ladd", "fadd", "dadd", "isub", "lsub", "fsub", "dsub",
+    "imul", "lmul", "fmul", "dmul", "idiv", "ldiv", "fdiv", "ddiv",
+    "irem", "lrem", "frem", "drem", "ineg", "lneg", "fneg", "dneg",
+    "ishl", "lshl", "ishr", "lshr", "iushr", "lushr", "iand", "land",
+    "ior", "lor", "ixor", "lxor", "iinc", "i2l", "i2f", "i2d",
+    "l2i", "l2f", "l2d", "f2i", "f2l", "f2d", "d2i", "d2l",
+    "d2f", "i2b", "i2c", "i2s", "lcmp", "fcmpl", "fcmpg", "dcmpl",
+    "dcmpg", "ifeq", "ifne", "iflt", "ifge", "ifgt", "ifle", "if_icmpeq",
+    "if_icmpne", "if_icmplt", "if_icmpge", "if_icmpgt", "if_icmple", "if_acmpeq", "if_acmpne", "goto",
+    "jsr", "ret", "tableswitch", "lookupswitch", "ireturn", "lreturn", "freturn", "dreturn",
+    "areturn", "return", "getstatic", "putstatic", "getfield", "putfield", "invokevirtual", "invokespecial",
+    "invokestatic", "invokeinterface", "invokedynamic", "new", "newarray", "anewarray", "arraylength", "athrow",
+    "checkcast", "instanceof", "monitorenter", "monitorexit", "wide", "multianewarray", "ifnull", "ifnonnull",
+    "goto_w", "jsr_w", "breakpoint",
+];
+
+/// A parsed Java class file.
+///
+/// Only the structure of the class file is parsed. Attributes are left as raw bytes, except for
+/// `Code`, which can be parsed with [`MethodInfo::code`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassFile {
+    pub minor_version: u16,
+    pub major_version: u16,
+    pub constant_pool: ConstantPool,
+    pub access_flags: u16,
+    pub this_class: u16,
+    /// The index of the superclass, or 0 for `java/lang/Object`.
+    pub super_class: u16,
+    pub interfaces: Vec<u16>,
+    pub fields: Vec<FieldInfo>,
+    pub methods: Vec<MethodInfo>,
+    pub attributes: Vec<AttributeInfo>,
+}
+impl ClassFile {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(data);
+        if reader.u32()? != 0xCAFEBABE {
+            return Err(invalid("Class file has the wrong magic number."));
+        }
+        let minor_version = reader.u16()?;
+        let major_version = reader.u16()?;
+        let constant_pool = ConstantPool::read(&mut reader)?;
+        let access_flags = reader.u16()?;
+        let this_class = reader.u16()?;
+        let super_class = reader.u16()?;
+
+        let mut interfaces = Vec::new();
+        for _ in 0..reader.u16()? {
+            interfaces.push(reader.u16()?);
+        }
+
+        let mut fields = Vec::new();
+        for _ in 0..reader.u16()? {
+            fields.push(FieldInfo {
+                access_flags: reader.u16()?,
+                name_index: reader.u16()?,
+                descriptor_index: reader.u16()?,
+                attributes: AttributeInfo::read_table(&mut reader)?,
+            });
+        }
+
+        let mut methods = Vec::new();
+        for _ in 0..reader.u16()? {
+            methods.push(MethodInfo {
+                access_flags: reader.u16()?,
+                name_index: reader.u16()?,
+                descriptor_index: reader.u16()?,
+                attributes: AttributeInfo::read_table(&mut reader)?,
+            });
+        }
+
+        let attributes = AttributeInfo::read_table(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(invalid("Class file has trailing data."));
+        }
+
+        Ok(ClassFile {
+            minor_version,
+            major_version,
+            constant_pool,
+            access_flags,
+            this_class,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            attributes,
+        })
+    }
+
+    pub fn flags(&self) -> EnumSet<CFlags> {
+        EnumSet::from_u16_truncated(self.access_flags)
+    }
+    pub fn name(&self) -> Result<&str> {
+        self.constant_pool.class_name(self.this_class)
+    }
+    pub fn super_name(&self) -> Result<Option<&str>> {
+        if self.super_class == 0 {
+            Ok(None)
+        } else {
+            self.constant_pool.class_name(self.super_class).map(Some)
+        }
+    }
+    pub fn interface_names(&self) -> Result<Vec<&str>> {
+        self.interfaces
+            .iter()
+            .map(|x| self.constant_pool.class_name(*x))
+            .collect()
+    }
+
+    pub fn write(&self, mut out: impl Write) -> Result<()> {
+        let out = &mut out;
+        write_u32(out, 0xCAFEBABE)?;
+        write_u16(out, self.minor_version)?;
+        write_u16(out, self.major_version)?;
+        self.constant_pool.write(out)?;
+        write_u16(out, self.access_flags)?;
+        write_u16(out, self.this_class)?;
+        write_u16(out, self.super_class)?;
+
+        write_len(out, self.interfaces.len())?;
+        for interface in &self.interfaces {
+            write_u16(out, *interface)?;
+        }
+
+        write_len(out, self.fields.len())?;
+        for field in &self.fields {
+            write_u16(out, field.access_flags)?;
+            write_u16(out, field.name_index)?;
+            write_u16(out, field.descriptor_index)?;
+            AttributeInfo::write_table(&field.attributes, out)?;
+        }
+
+        write_len(out, self.methods.len())?;
+        for method in &self.methods {
+            write_u16(out, method.access_flags)?;
+            write_u16(out, method.name_index)?;
+            write_u16(out, method.descriptor_index)?;
+            AttributeInfo::write_table(&method.attributes, out)?;
+        }
+
+        AttributeInfo::write_table(&self.attributes, out)
+    }
+    pub fn into_vec(self) -> Vec<u8> {
+        let mut cursor = Cursor::new(Vec::<u8>::new());
+        self.write(&mut cursor)
+            .expect("Could not generate Java classfile.");
+        cursor.into_inner()
+    }
+}
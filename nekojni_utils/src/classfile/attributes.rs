@@ -1,4 +1,7 @@
-use crate::classfile::PoolWriter;
+use crate::{
+    classfile::PoolWriter,
+    signatures::{Annotation, ElementValue, Type},
+};
 use byteorder::{WriteBytesExt, BE};
 use std::{
     fmt::Debug,
@@ -54,3 +57,266 @@ impl Attribute for SourceFile {
         pool.utf8(&self.0).write(out)
     }
 }
+
+/// Represents the `Signature` attribute of a Java class, field or method.
+#[derive(Debug)]
+pub struct Signature(String);
+impl Signature {
+    pub fn new(signature: &str) -> Self {
+        Signature(signature.to_string())
+    }
+}
+impl Attribute for Signature {
+    fn name(&self) -> &str {
+        "Signature"
+    }
+    fn write(&self, pool: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        pool.utf8(&self.0).write(out)
+    }
+}
+
+/// Represents the `Deprecated` attribute of a Java class, field or method.
+#[derive(Debug)]
+pub struct Deprecated;
+impl Attribute for Deprecated {
+    fn name(&self) -> &str {
+        "Deprecated"
+    }
+    fn write(&self, _: &mut PoolWriter, _: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Represents the `NestHost` attribute of a Java class.
+#[derive(Debug)]
+pub struct NestHost(String);
+impl NestHost {
+    pub fn new(name: &str) -> Self {
+        NestHost(name.to_string())
+    }
+}
+impl Attribute for NestHost {
+    fn name(&self) -> &str {
+        "NestHost"
+    }
+    fn write(&self, pool: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        pool.class(&self.0).write(out)
+    }
+}
+
+/// Represents the `NestMembers` attribute of a Java class.
+#[derive(Debug)]
+pub struct NestMembers(Vec<String>);
+impl NestMembers {
+    pub fn new(members: Vec<String>) -> Self {
+        NestMembers(members)
+    }
+}
+impl Attribute for NestMembers {
+    fn name(&self) -> &str {
+        "NestMembers"
+    }
+    fn write(&self, pool: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        assert!(self.0.len() <= u16::MAX as usize);
+        out.write_u16::<BE>(self.0.len() as u16)?;
+        for member in &self.0 {
+            pool.class(member).write(&mut *out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents the `LineNumberTable` attribute of a method's code.
+#[derive(Debug)]
+pub struct LineNumberTable(Vec<(u16, u16)>);
+impl LineNumberTable {
+    /// Creates a new line number table from a list of bytecode offsets and the source lines that
+    /// start at them.
+    pub fn new(lines: Vec<(u16, u16)>) -> Self {
+        LineNumberTable(lines)
+    }
+}
+impl Attribute for LineNumberTable {
+    fn name(&self) -> &str {
+        "LineNumberTable"
+    }
+    fn write(&self, _: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        assert!(self.0.len() <= u16::MAX as usize);
+        out.write_u16::<BE>(self.0.len() as u16)?;
+        for (offset, line) in &self.0 {
+            out.write_u16::<BE>(*offset)?;
+            out.write_u16::<BE>(*line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents the `MethodParameters` attribute of a Java method.
+#[derive(Debug)]
+pub struct MethodParameters(Vec<Option<String>>);
+impl MethodParameters {
+    pub fn new(names: Vec<Option<String>>) -> Self {
+        MethodParameters(names)
+    }
+}
+impl Attribute for MethodParameters {
+    fn name(&self) -> &str {
+        "MethodParameters"
+    }
+    fn write(&self, pool: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        assert!(self.0.len() <= u8::MAX as usize);
+        out.write_u8(self.0.len() as u8)?;
+        for name in &self.0 {
+            match name {
+                Some(name) => pool.utf8(name).write(&mut *out)?,
+                None => out.write_u16::<BE>(0)?,
+            }
+            out.write_u16::<BE>(0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents the `RuntimeVisibleAnnotations` or `RuntimeInvisibleAnnotations` attribute of a Java
+/// class, field or method.
+#[derive(Debug)]
+pub struct RuntimeAnnotations {
+    visible: bool,
+    annotations: Vec<Annotation>,
+}
+impl RuntimeAnnotations {
+    pub fn new(visible: bool, annotations: Vec<Annotation>) -> Self {
+        RuntimeAnnotations { visible, annotations }
+    }
+}
+impl Attribute for RuntimeAnnotations {
+    fn name(&self) -> &str {
+        if self.visible {
+            "RuntimeVisibleAnnotations"
+        } else {
+            "RuntimeInvisibleAnnotations"
+        }
+    }
+    fn write(&self, pool: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        write_annotations(pool, out, &self.annotations)
+    }
+}
+
+/// Represents the `RuntimeVisibleParameterAnnotations` or `RuntimeInvisibleParameterAnnotations`
+/// attribute of a Java method.
+#[derive(Debug)]
+pub struct RuntimeParameterAnnotations {
+    visible: bool,
+    parameters: Vec<Vec<Annotation>>,
+}
+impl RuntimeParameterAnnotations {
+    pub fn new(visible: bool, parameters: Vec<Vec<Annotation>>) -> Self {
+        RuntimeParameterAnnotations { visible, parameters }
+    }
+}
+impl Attribute for RuntimeParameterAnnotations {
+    fn name(&self) -> &str {
+        if self.visible {
+            "RuntimeVisibleParameterAnnotations"
+        } else {
+            "RuntimeInvisibleParameterAnnotations"
+        }
+    }
+    fn write(&self, pool: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        assert!(self.parameters.len() <= u8::MAX as usize);
+        out.write_u8(self.parameters.len() as u8)?;
+        for annotations in &self.parameters {
+            write_annotations(pool, out, annotations)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_annotations(
+    pool: &mut PoolWriter,
+    out: &mut Cursor<Vec<u8>>,
+    annotations: &[Annotation],
+) -> Result<(), Error> {
+    assert!(annotations.len() <= u16::MAX as usize);
+    out.write_u16::<BE>(annotations.len() as u16)?;
+    for annotation in annotations {
+        write_annotation(pool, out, annotation)?;
+    }
+    Ok(())
+}
+
+fn write_annotation(
+    pool: &mut PoolWriter,
+    out: &mut Cursor<Vec<u8>>,
+    annotation: &Annotation,
+) -> Result<(), Error> {
+    let ty = Type::from(annotation.class.clone());
+    pool.utf8(&ty.display_jni().to_string()).write(&mut *out)?;
+    assert!(annotation.elements.len() <= u16::MAX as usize);
+    out.write_u16::<BE>(annotation.elements.len() as u16)?;
+    for (name, value) in &annotation.elements {
+        pool.utf8(name).write(&mut *out)?;
+        write_element_value(pool, out, value)?;
+    }
+    Ok(())
+}
+
+fn write_element_value(
+    pool: &mut PoolWriter,
+    out: &mut Cursor<Vec<u8>>,
+    value: &ElementValue,
+) -> Result<(), Error> {
+    match value {
+        ElementValue::Boolean(v) => {
+            out.write_u8(b'Z')?;
+            pool.integer(*v as i32).write(&mut *out)
+        }
+        ElementValue::Char(v) => {
+            out.write_u8(b'C')?;
+            pool.integer(*v as i32).write(&mut *out)
+        }
+        ElementValue::Int(v) => {
+            out.write_u8(b'I')?;
+            pool.integer(*v).write(&mut *out)
+        }
+        ElementValue::Long(v) => {
+            out.write_u8(b'J')?;
+            pool.long(*v).write(&mut *out)
+        }
+        ElementValue::Float(v) => {
+            out.write_u8(b'F')?;
+            pool.float(*v).write(&mut *out)
+        }
+        ElementValue::Double(v) => {
+            out.write_u8(b'D')?;
+            pool.double(*v).write(&mut *out)
+        }
+        ElementValue::String(v) => {
+            out.write_u8(b's')?;
+            pool.utf8(v).write(&mut *out)
+        }
+        ElementValue::Enum(class, name) => {
+            let ty = Type::from(class.clone());
+            out.write_u8(b'e')?;
+            pool.utf8(&ty.display_jni().to_string()).write(&mut *out)?;
+            pool.utf8(name).write(&mut *out)
+        }
+        ElementValue::Class(ty) => {
+            out.write_u8(b'c')?;
+            pool.utf8(&ty.display_jni().to_string()).write(&mut *out)
+        }
+        ElementValue::Annotation(annotation) => {
+            out.write_u8(b'@')?;
+            write_annotation(pool, out, annotation)
+        }
+        ElementValue::Array(values) => {
+            out.write_u8(b'[')?;
+            assert!(values.len() <= u16::MAX as usize);
+            out.write_u16::<BE>(values.len() as u16)?;
+            for value in values {
+                write_element_value(pool, out, value)?;
+            }
+            Ok(())
+        }
+    }
+}
@@ -1,22 +1,64 @@
 #![allow(unused)]
 
 use crate::{
-    classfile::{attributes::Attribute, PoolId, PoolWriter},
+    classfile::{
+        attributes::{Attribute, AttributeTable, LineNumberTable},
+        frames::{Frame, StackMapTable, VerificationType},
+        utils::type_stack_size,
+        Constant, Handle, PoolId, PoolWriter,
+    },
     signatures::{MethodSig, Type},
 };
 use byteorder::{WriteBytesExt, BE};
 use std::{
+    cmp::Reverse,
     collections::HashMap,
     io::{Cursor, Error, Write},
     sync::atomic::{AtomicUsize, Ordering},
 };
 
+/// A position in a method's code that can be the target of a branch instruction.
+///
+/// Labels are created with [`MethodWriter::new_label`], and placed with [`MethodWriter::label`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Label(usize);
+
+#[derive(Debug, Default)]
+struct LabelData {
+    placed: bool,
+    targeted: bool,
+    frame: Option<Frame>,
+}
+
+/// An entry in the exception table of a method.
+#[derive(Debug)]
+struct TryCatch {
+    start: Label,
+    end: Label,
+    handler: Label,
+    class: Option<String>,
+}
+
+/// Generates the code of a method.
+///
+/// The types of the local variables and the operand stack are tracked as instructions are added,
+/// and are used to generate the `StackMapTable` attribute required by the verifier. The frame at
+/// each label is fixed when it is placed, so backwards branches must be compatible with it.
+///
+/// Different object types reaching the same label are merged to `java/lang/Object`, as the class
+/// hierarchy is not known.
 #[derive(Debug, Default)]
 pub struct MethodWriter {
-    cur_stack: isize,
-    max_stack: isize,
+    class_name: String,
+    max_stack: u16,
     max_field: u16,
     instr: Vec<Instruction>,
+    labels: Vec<LabelData>,
+    initial_frame: Frame,
+    frame: Option<Frame>,
+    try_catch: Vec<TryCatch>,
+    active_try_catch: Vec<usize>,
+    line_numbers: Vec<(usize, u16)>,
 }
 impl Attribute for MethodWriter {
     fn name(&self) -> &str {
@@ -24,49 +66,279 @@ impl Attribute for MethodWriter {
     }
     fn write(&self, pool: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
         // write header
-        out.write_u16::<BE>(self.max_stack as u16)?;
+        out.write_u16::<BE>(self.max_stack)?;
         out.write_u16::<BE>(self.max_field)?;
 
         // write code
         let mut code = Cursor::new(Vec::<u8>::new());
-        for instr in &self.instr {
+        let mut offsets = Vec::new();
+        let mut label_offsets = vec![None; self.labels.len()];
+        let mut label_positions = vec![0; self.labels.len()];
+        let mut fixups = Vec::new();
+        for (i, instr) in self.instr.iter().enumerate() {
+            let offset = code.position() as u32;
+            offsets.push(offset);
             match instr {
-                _ => instr.write(pool, &mut code)?,
+                Instruction::label(label) => {
+                    label_offsets[label.0] = Some(offset);
+                    label_positions[label.0] = i;
+                }
+                _ => instr.write(pool, &mut code, &mut fixups)?,
+            }
+        }
+        let label_offset = |label: Label| -> u32 {
+            label_offsets[label.0].expect("Label is the target of a branch, but was never placed.")
+        };
+
+        // resolve branch offsets
+        let mut code = code.into_inner();
+        for fixup in fixups {
+            let delta = label_offset(fixup.target) as i64 - fixup.base as i64;
+            let pos = fixup.pos as usize;
+            if fixup.wide {
+                code[pos..pos + 4].copy_from_slice(&(delta as i32).to_be_bytes());
+            } else {
+                assert!(delta >= i16::MIN as i64 && delta <= i16::MAX as i64, "Branch is too far.");
+                code[pos..pos + 2].copy_from_slice(&(delta as i16).to_be_bytes());
             }
         }
-        let code = code.into_inner();
-        assert!(code.len() <= u32::MAX as usize);
+
+        assert!(code.len() <= u16::MAX as usize);
         out.write_u32::<BE>(code.len() as u32)?;
         out.write_all(&code)?;
 
-        // TODO: exception table
-        out.write_u16::<BE>(0)?;
-        // TODO: attributes table
-        out.write_u16::<BE>(0)?;
+        // write exception table
+        assert!(self.try_catch.len() <= u16::MAX as usize);
+        out.write_u16::<BE>(self.try_catch.len() as u16)?;
+        for entry in &self.try_catch {
+            let start = label_offset(entry.start);
+            let end = label_offset(entry.end);
+            assert!(start < end, "try_catch covers no instructions.");
+            out.write_u16::<BE>(start as u16)?;
+            out.write_u16::<BE>(end as u16)?;
+            out.write_u16::<BE>(label_offset(entry.handler) as u16)?;
+            match &entry.class {
+                Some(class) => pool.class(class).write(&mut *out)?,
+                None => out.write_u16::<BE>(0)?,
+            }
+        }
+
+        // write attributes table
+        let mut attributes = AttributeTable::default();
+        let mut frames = Vec::new();
+        for (i, data) in self.labels.iter().enumerate() {
+            if data.targeted {
+                let frame = data.frame.as_ref().unwrap();
+                frames.push((label_offset(Label(i)), label_positions[i], frame));
+            }
+        }
+        // labels placed at the same offset share a frame. each label is merged with the frame of
+        // the labels placed before it, so the frame of the last one placed is the most general.
+        frames.sort_by_key(|x| (x.0, Reverse(x.1)));
+        frames.dedup_by_key(|x| x.0);
+        let frames: Vec<_> = frames
+            .into_iter()
+            .map(|(offset, _, frame)| (offset, frame.resolve_offsets(&offsets)))
+            .collect();
+        if !frames.is_empty() {
+            attributes.push(StackMapTable::new(self.initial_frame.clone(), frames));
+        }
+        let lines: Vec<_> = self
+            .line_numbers
+            .iter()
+            .filter(|(index, _)| *index < self.instr.len())
+            .map(|(index, line)| (offsets[*index] as u16, *line))
+            .collect();
+        if !lines.is_empty() {
+            attributes.push(LineNumberTable::new(lines));
+        }
+        attributes.write(pool, out)?;
 
         Ok(())
     }
 }
 impl MethodWriter {
+    pub(crate) fn for_method(
+        class_name: &str,
+        method_name: &str,
+        sig: &str,
+        is_static: bool,
+    ) -> Self {
+        let sig = MethodSig::parse_jni(sig).unwrap();
+
+        let mut frame = Frame::default();
+        if !is_static {
+            let this = if method_name == "<init>" && class_name != "java/lang/Object" {
+                VerificationType::UninitializedThis
+            } else {
+                VerificationType::Object(class_name.to_string())
+            };
+            frame.locals.push(this);
+        }
+        for param in &sig.params {
+            frame.set_local(frame.locals.len() as u16, VerificationType::from_type(param));
+        }
+        assert!(frame.locals.len() <= u16::MAX as usize);
+
+        MethodWriter {
+            class_name: class_name.to_string(),
+            max_stack: 0,
+            max_field: frame.locals.len() as u16,
+            instr: Vec::new(),
+            labels: Vec::new(),
+            initial_frame: frame.clone(),
+            frame: Some(frame),
+            try_catch: Vec::new(),
+            active_try_catch: Vec::new(),
+            line_numbers: Vec::new(),
+        }
+    }
+
     #[inline(never)]
     fn push_instr(&mut self, instr: Instruction) -> &mut Self {
-        // adjust the stack size based on this instruction.
-        self.cur_stack += instr.stack_change();
-        assert!(self.cur_stack <= i16::MAX as isize);
-        assert!(self.cur_stack >= 0);
-        self.max_stack = self.max_stack.max(self.cur_stack);
+        // track the types on the stack and in local variables
+        let frame = match &mut self.frame {
+            Some(frame) => frame,
+            None => panic!("{instr:?} is unreachable. A label must be placed before it."),
+        };
+        let frame_before = frame.clone();
+        instr.simulate(frame, self.instr.len(), &self.class_name);
+        let stack_size = frame.stack_size();
+        assert!(stack_size <= u16::MAX as usize);
+        self.max_stack = self.max_stack.max(stack_size as u16);
+
+        // record the frames at the targets of this instruction
+        let frame = frame.clone();
+        for target in instr.targets() {
+            self.jump_to(target, &frame);
+        }
+        for i in self.active_try_catch.clone() {
+            let entry = &self.try_catch[i];
+            let handler = entry.handler;
+            let exception = VerificationType::Object(match &entry.class {
+                Some(class) => class.clone(),
+                None => "java/lang/Throwable".to_string(),
+            });
+            for locals in [&frame_before.locals, &frame.locals] {
+                let handler_frame =
+                    Frame { locals: locals.clone(), stack: vec![exception.clone()] };
+                self.jump_to(handler, &handler_frame);
+            }
+        }
+        if instr.ends_block() {
+            self.frame = None;
+        }
 
         // push the actual instruction itself
         self.instr.push(instr);
         self
     }
+    fn jump_to(&mut self, target: Label, frame: &Frame) {
+        let data = &mut self.labels[target.0];
+        data.targeted = true;
+        data.frame = Some(match &data.frame {
+            Some(existing) if data.placed => {
+                assert!(
+                    frame.is_assignable_to(existing),
+                    "Branch to {target:?} is not compatible with the frame at the label.\n\
+                     Branch: {frame:?}\nLabel: {existing:?}",
+                );
+                return;
+            }
+            Some(existing) => existing
+                .merge(frame)
+                .unwrap_or_else(|| incompatible_stacks(target, existing, frame)),
+            None => frame.clone(),
+        });
+    }
+
+    /// Creates a new label. It must be placed with [`MethodWriter::label`] before the code is
+    /// written if any instruction branches to it.
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(LabelData::default());
+        Label(self.labels.len() - 1)
+    }
+
+    /// Places a label before the next instruction.
+    ///
+    /// If the current position is not reachable from the previous instruction, the label must be
+    /// the target of a previous branch.
+    pub fn label(&mut self, label: Label) -> &mut Self {
+        let data = &mut self.labels[label.0];
+        assert!(!data.placed, "{label:?} was placed twice.");
+        data.placed = true;
+
+        let frame = match (self.frame.take(), data.frame.take()) {
+            (Some(current), Some(target)) => current
+                .merge(&target)
+                .unwrap_or_else(|| incompatible_stacks(label, &target, &current)),
+            (Some(current), None) => current,
+            (None, Some(target)) => target,
+            (None, None) => {
+                panic!("{label:?} is unreachable, as no previous instruction branches to it.")
+            }
+        };
+        data.frame = Some(frame.clone());
+        self.frame = Some(frame);
+
+        // update the active exception handlers
+        let try_catch = &self.try_catch;
+        self.active_try_catch.retain(|&i| try_catch[i].end != label);
+        for (i, entry) in self.try_catch.iter().enumerate() {
+            if entry.start == label {
+                self.active_try_catch.push(i);
+            }
+        }
+
+        self.instr.push(Instruction::label(label));
+        self
+    }
 
-    pub(crate) fn argc(&mut self, count: usize) -> &mut Self {
-        assert!(count <= u16::MAX as usize);
-        self.max_field = self.max_field.max(count as u16);
+    /// Marks the next instruction as the start of the code for a given line of the source file.
+    pub fn line_number(&mut self, line: u16) -> &mut Self {
+        self.line_numbers.push((self.instr.len(), line));
         self
     }
 
+    /// Adds an exception handler that covers the instructions between `start` and `end`.
+    ///
+    /// If `class` is `None`, the handler catches all exceptions, as is used for `finally` blocks.
+    /// Handlers are checked in the order they are added, so inner handlers must be added first.
+    ///
+    /// This must be called before `start` is placed. `end` must be placed after it.
+    pub fn try_catch(
+        &mut self,
+        start: Label,
+        end: Label,
+        handler: Label,
+        class: Option<&str>,
+    ) -> &mut Self {
+        assert!(
+            !self.labels[start.0].placed,
+            "try_catch must be called before its start label is placed."
+        );
+        assert!(!self.labels[end.0].placed, "The end label of try_catch was already placed.");
+        self.try_catch
+            .push(TryCatch { start, end, handler, class: class.map(|x| x.to_string()) });
+        self
+    }
+
+    #[inline(never)]
+    pub fn tableswitch(&mut self, low: i32, default: Label, targets: &[Label]) -> &mut Self {
+        assert!(!targets.is_empty());
+        assert!(low.checked_add(targets.len() as i32 - 1).is_some());
+        self.push_instr(Instruction::tableswitch(low, default, targets.to_vec()))
+    }
+    #[inline(never)]
+    pub fn lookupswitch(&mut self, default: Label, targets: &[(i32, Label)]) -> &mut Self {
+        let mut targets = targets.to_vec();
+        targets.sort_by_key(|x| x.0);
+        let len = targets.len();
+        targets.dedup_by_key(|x| x.0);
+        assert_eq!(targets.len(), len, "lookupswitch contains duplicate keys.");
+        self.push_instr(Instruction::lookupswitch(default, targets))
+    }
+
     #[inline(never)]
     pub fn invokeinterface(&mut self, class: &str, name: &str, sig: &str) -> &mut Self {
         self.push_instr(Instruction::invokeinterface(InvokeData::new(class, name, sig)))
@@ -84,8 +356,9 @@ impl MethodWriter {
         self.push_instr(Instruction::invokevirtual(InvokeData::new(class, name, sig)))
     }
 
+    /// Emits a `new` instruction.
     #[inline(never)]
-    pub fn new(&mut self, ty: &str) -> &mut Self {
+    pub fn new_object(&mut self, ty: &str) -> &mut Self {
         self.push_instr(Instruction::new(ty.to_string()))
     }
     #[inline(never)]
@@ -114,24 +387,83 @@ impl MethodWriter {
         self.push_instr(Instruction::putstatic(FieldData::new(class, name, ty)))
     }
 
+    #[inline(never)]
+    pub fn invokedynamic(
+        &mut self,
+        bootstrap: &Handle,
+        bootstrap_args: &[Constant],
+        name: &str,
+        sig: &str,
+    ) -> &mut Self {
+        let data = InvokeDynamicData {
+            bootstrap: bootstrap.clone(),
+            bootstrap_args: bootstrap_args.to_vec(),
+            name: name.to_string(),
+            desc: sig.to_string(),
+            sig: MethodSig::parse_jni(sig).unwrap(),
+        };
+        self.push_instr(Instruction::invokedynamic(data))
+    }
+
+    #[inline(never)]
+    pub fn instanceof(&mut self, ty: &str) -> &mut Self {
+        self.push_instr(Instruction::instanceof(ty.to_string()))
+    }
+    /// Creates a new array of a primitive type, given as a JNI descriptor such as `I`.
+    #[inline(never)]
+    pub fn newarray(&mut self, ty: &str) -> &mut Self {
+        let atype = match ty {
+            "Z" => 4,
+            "C" => 5,
+            "F" => 6,
+            "D" => 7,
+            "B" => 8,
+            "S" => 9,
+            "I" => 10,
+            "J" => 11,
+            _ => panic!("{ty:?} is not a primitive type."),
+        };
+        self.push_instr(Instruction::newarray(atype, ty.to_string()))
+    }
+    /// Creates a new multidimensional array. `ty` is the descriptor of the array type.
+    #[inline(never)]
+    pub fn multianewarray(&mut self, ty: &str, dims: u8) -> &mut Self {
+        assert!(dims >= 1);
+        assert!(ty.len() > dims as usize && ty[..dims as usize].bytes().all(|x| x == b'['));
+        self.push_instr(Instruction::multianewarray(ty.to_string(), dims))
+    }
+
+    /// Increments an `int` local variable.
+    #[inline(never)]
+    pub fn iinc(&mut self, id: u16, delta: i16) -> &mut Self {
+        self.max_field = self.max_field.max(id + 1);
+        self.push_instr(Instruction::iinc(id, delta))
+    }
+
+    /// Loads a constant from the constant pool.
+    #[inline(never)]
+    pub fn ldc(&mut self, constant: Constant) -> &mut Self {
+        self.push_instr(Instruction::ldc(constant))
+    }
     #[inline(never)]
     pub fn aconst_str(&mut self, str: &str) -> &mut Self {
-        self.push_instr(Instruction::aconst_str(str.to_string()))
+        self.ldc(Constant::String(str.to_string()))
     }
     #[inline(never)]
     pub fn aconst_class(&mut self, class: &str) -> &mut Self {
-        self.push_instr(Instruction::aconst_class(class.to_string()))
+        self.ldc(Constant::Class(class.to_string()))
     }
     #[inline(never)]
     pub fn fconst(&mut self, v: f32) -> &mut Self {
-        if v == 0.0 {
+        // this compares the bits to avoid using `fconst_0` for `-0.0`
+        if v.to_bits() == 0.0f32.to_bits() {
             self.push_instr(Instruction::Basic(BasicInstruction::fconst_0))
         } else if v == 1.0 {
             self.push_instr(Instruction::Basic(BasicInstruction::fconst_1))
         } else if v == 2.0 {
             self.push_instr(Instruction::Basic(BasicInstruction::fconst_2))
         } else {
-            self.push_instr(Instruction::fconst(v))
+            self.ldc(Constant::Float(v))
         }
     }
     #[inline(never)]
@@ -144,17 +476,23 @@ impl MethodWriter {
             3 => self.push_instr(Instruction::Basic(BasicInstruction::iconst_3)),
             4 => self.push_instr(Instruction::Basic(BasicInstruction::iconst_4)),
             5 => self.push_instr(Instruction::Basic(BasicInstruction::iconst_5)),
-            _ => self.push_instr(Instruction::iconst(v)),
+            _ if v >= i8::MIN as i32 && v <= i8::MAX as i32 => {
+                self.push_instr(Instruction::bipush(v as i8))
+            }
+            _ if v >= i16::MIN as i32 && v <= i16::MAX as i32 => {
+                self.push_instr(Instruction::sipush(v as i16))
+            }
+            _ => self.ldc(Constant::Integer(v)),
         }
     }
     #[inline(never)]
     pub fn dconst(&mut self, v: f64) -> &mut Self {
-        if v == 0.0 {
+        if v.to_bits() == 0.0f64.to_bits() {
             self.push_instr(Instruction::Basic(BasicInstruction::dconst_0))
         } else if v == 1.0 {
             self.push_instr(Instruction::Basic(BasicInstruction::dconst_1))
         } else {
-            self.push_instr(Instruction::dconst(v))
+            self.ldc(Constant::Double(v))
         }
     }
     #[inline(never)]
@@ -162,7 +500,7 @@ impl MethodWriter {
         match v {
             0 => self.push_instr(Instruction::Basic(BasicInstruction::lconst_0)),
             1 => self.push_instr(Instruction::Basic(BasicInstruction::lconst_1)),
-            _ => self.push_instr(Instruction::lconst(v)),
+            _ => self.ldc(Constant::Long(v)),
         }
     }
 }
@@ -172,20 +510,15 @@ struct FieldData {
     class: String,
     name: String,
     desc: String,
-    slots: usize,
+    ty: Type,
 }
 impl FieldData {
     fn new(class: &str, name: &str, ty: &str) -> Self {
-        let parsed_ty = Type::parse_jni(ty).unwrap();
         FieldData {
             class: class.to_string(),
             name: name.to_string(),
             desc: ty.to_string(),
-            slots: if parsed_ty == Type::Double || parsed_ty == Type::Long {
-                2
-            } else {
-                1
-            },
+            ty: Type::parse_jni(ty).unwrap(),
         }
     }
     fn make_ref(&self, pool: &mut PoolWriter) -> PoolId {
@@ -198,8 +531,9 @@ struct InvokeData {
     class: String,
     name: String,
     desc: String,
+    sig: MethodSig,
+    /// The number of stack slots used by the parameters.
     argc: usize,
-    retc: usize,
 }
 impl InvokeData {
     fn new(class: &str, name: &str, sig: &str) -> Self {
@@ -208,8 +542,12 @@ impl InvokeData {
             class: class.to_string(),
             name: name.to_string(),
             desc: sig.to_string(),
-            argc: parsed_sig.params.len(),
-            retc: if parsed_sig.ret_ty == Type::Void { 0 } else { 1 },
+            argc: parsed_sig
+                .params
+                .iter()
+                .map(|x| type_stack_size(x) as usize)
+                .sum(),
+            sig: parsed_sig,
         }
     }
     fn make_method_ref(&self, pool: &mut PoolWriter) -> PoolId {
@@ -220,6 +558,31 @@ impl InvokeData {
     }
 }
 
+#[derive(Debug)]
+struct InvokeDynamicData {
+    bootstrap: Handle,
+    bootstrap_args: Vec<Constant>,
+    name: String,
+    desc: String,
+    sig: MethodSig,
+}
+
+/// Panics because two frames reaching the same label cannot be merged.
+fn incompatible_stacks(label: Label, a: &Frame, b: &Frame) -> ! {
+    panic!(
+        "Branches to {label:?} have incompatible stacks.\nFirst: {a:?}\nSecond: {b:?}",
+    )
+}
+
+/// A branch offset that must be filled in once the position of all labels is known.
+#[derive(Debug)]
+struct Fixup {
+    pos: u64,
+    base: u32,
+    target: Label,
+    wide: bool,
+}
+
 #[derive(Debug)]
 #[allow(non_camel_case_types)]
 enum Instruction {
@@ -227,21 +590,23 @@ enum Instruction {
     invokespecial(InvokeData),
     invokestatic(InvokeData),
     invokevirtual(InvokeData),
+    invokedynamic(InvokeDynamicData),
 
     new(String),
     anewarray(String),
+    newarray(u8, String),
+    multianewarray(String, u8),
     checkcast(String),
+    instanceof(String),
     getfield(FieldData),
     getstatic(FieldData),
     putfield(FieldData),
     putstatic(FieldData),
 
-    aconst_str(String),
-    aconst_class(String),
-    fconst(f32),
-    iconst(i32),
-    dconst(f64),
-    lconst(i64),
+    ldc(Constant),
+    bipush(i8),
+    sipush(i16),
+    iinc(u16, i16),
 
     aload(u16),
     fload(u16),
@@ -254,96 +619,296 @@ enum Instruction {
     dstore(u16),
     lstore(u16),
 
+    label(Label),
+    Branch(BranchInstruction, Label),
+    tableswitch(i32, Label, Vec<Label>),
+    lookupswitch(Label, Vec<(i32, Label)>),
+
     Basic(BasicInstruction),
 }
 impl Instruction {
+    /// Updates the types in a frame to reflect the effects of this instruction.
     //noinspection SpellCheckingInspection
-    fn stack_change(&self) -> isize {
+    fn simulate(&self, frame: &mut Frame, index: usize, class_name: &str) {
         use BasicInstruction::*;
         use Instruction::*;
+        use VerificationType as VT;
+
+        fn pop_params(frame: &mut Frame, sig: &MethodSig) {
+            for _ in &sig.params {
+                frame.pop();
+            }
+        }
+        fn load(frame: &mut Frame, id: u16, ty: VT) {
+            let local = frame.local(id);
+            match (&local, &ty) {
+                (VT::Integer, VT::Integer)
+                | (VT::Float, VT::Float)
+                | (VT::Long, VT::Long)
+                | (VT::Double, VT::Double) => {}
+                (
+                    VT::Null | VT::UninitializedThis | VT::Object(_) | VT::Uninitialized(_),
+                    VT::Top,
+                ) => {}
+                _ => panic!("Local variable {id} contains {local:?}, not {ty:?}."),
+            }
+            frame.push(local);
+        }
+        fn store(frame: &mut Frame, id: u16) {
+            let value = frame.pop();
+            frame.set_local(id, value);
+        }
+        fn array_component(array: VT) -> VT {
+            match array {
+                VT::Null => VT::Null,
+                VT::Object(desc) if desc.starts_with("[L") => {
+                    VT::Object(desc[2..desc.len() - 1].to_string())
+                }
+                VT::Object(desc) if desc.starts_with("[[") => VT::Object(desc[1..].to_string()),
+                _ => panic!("{array:?} is not an array of references."),
+            }
+        }
+        fn unary(frame: &mut Frame, result: VT) {
+            frame.pop();
+            frame.push(result);
+        }
+        fn binary(frame: &mut Frame, result: VT) {
+            frame.pop();
+            frame.pop();
+            frame.push(result);
+        }
+        fn duplicate(frame: &mut Frame, dup_slots: usize, skip_slots: usize) {
+            let top = frame.pop_slots(dup_slots);
+            let skip = frame.pop_slots(skip_slots);
+            for ty in top.iter().chain(&skip).chain(&top) {
+                frame.push(ty.clone());
+            }
+        }
 
         match self {
-            invokeinterface(data) => -(data.argc as isize + 1) + data.retc as isize,
-            invokespecial(data) => -(data.argc as isize + 1) + data.retc as isize,
-            invokestatic(data) => -(data.argc as isize) + data.retc as isize,
-            invokevirtual(data) => -(data.argc as isize + 1) + data.retc as isize,
-            getfield(data) => data.slots as isize - 1,
-            getstatic(data) => data.slots as isize,
-            putfield(data) => -(data.slots as isize) - 1,
-            putstatic(data) => -(data.slots as isize),
-
-            new(_) => 1,
-            anewarray(_) => 0,
-            checkcast(_) => 0,
-
-            aconst_class(_) | aconst_str(_) | fconst(_) | iconst(_) => 1,
-            dconst(_) | lconst(_) => 2,
-
-            aload(_) | fload(_) | iload(_) => 1,
-            dload(_) | lload(_) => 2,
-            astore(_) | fstore(_) | istore(_) => -1,
-            dstore(_) | lstore(_) => -2,
-
-            Basic(instr) => {
-                match instr {
-                    // array load
-                    aaload | baload | caload | faload | iaload | saload => -1,
-                    aastore | bastore | castore | fastore | iastore | sastore => -3,
-                    daload | laload => 0,
-                    dastore | lastore => -4,
-
-                    // short-hand store
-                    aload_0 | aload_1 | aload_2 | aload_3 => 1,
-                    fload_0 | fload_1 | fload_2 | fload_3 => 1,
-                    iload_0 | iload_1 | iload_2 | iload_3 => 1,
-                    astore_0 | astore_1 | astore_2 | astore_3 => -1,
-                    fstore_0 | fstore_1 | fstore_2 | fstore_3 => -1,
-                    istore_0 | istore_1 | istore_2 | istore_3 => -1,
-                    dload_0 | dload_1 | dload_2 | dload_3 => 2,
-                    lload_0 | lload_1 | lload_2 | lload_3 => 2,
-                    dstore_0 | dstore_1 | dstore_2 | dstore_3 => -2,
-                    lstore_0 | lstore_1 | lstore_2 | lstore_3 => -2,
-
-                    // constant values
-                    aconst_null | fconst_0 | fconst_1 | fconst_2 | iconst_m1 | iconst_0
-                    | iconst_1 | iconst_2 | iconst_3 | iconst_4 | iconst_5 => 1,
-                    dconst_0 | dconst_1 | lconst_0 | lconst_1 => 2,
-
-                    // conversions
-                    d2f | d2i | l2f => -1,
-                    d2l | f2i | i2b | i2c | i2f | i2s | l2d | l2i => 0,
-                    f2d | f2l | i2d | i2l => 1,
-
-                    // mathematical operations
-                    dadd | dcmpg | dcmpl | ddiv | dmul | drem | dsub => -2,
-                    dneg => 0,
-                    fadd | fcmpg | fcmpl | fdiv | fmul | frem | fsub => -1,
-                    fneg => 0,
-                    iadd | idiv | imul | irem | isub => -1,
-                    iand | ior | ishl | ishr | iushr | ixor => -1,
-                    ineg => 0,
-                    ladd | ldiv | lmul | lrem | lsub => -2,
-                    land | lor | lshl | lshr | lushr | lxor => -2,
-                    lcmp => -3,
-                    lneg => 0,
-
-                    // stack manipulation command
-                    dup | dup_x1 | dup_x2 => 1,
-                    dup2 | dup2_x1 | dup2_x2 => 2,
-                    pop => -1,
-                    pop2 => -2,
-                    swap => 0,
-
-                    // misc commands
-                    vreturn => 0,
-                    areturn | freturn | ireturn => -1,
-                    dreturn | lreturn => -2,
-                    breakpoint | nop => 0,
-                    monitorenter | monitorexit => -1,
-                    arraylength => 0,
-                    athrow => -1,
+            invokeinterface(data) | invokevirtual(data) => {
+                pop_params(frame, &data.sig);
+                frame.pop();
+                frame.push_type(&data.sig.ret_ty);
+            }
+            invokestatic(data) => {
+                pop_params(frame, &data.sig);
+                frame.push_type(&data.sig.ret_ty);
+            }
+            invokespecial(data) => {
+                pop_params(frame, &data.sig);
+                let receiver = frame.pop();
+                if data.name == "<init>" {
+                    let initialized = match &receiver {
+                        VT::UninitializedThis => VT::Object(class_name.to_string()),
+                        VT::Uninitialized(_) => VT::Object(data.class.clone()),
+                        _ => panic!("<init> called on {receiver:?}, which is already initialized."),
+                    };
+                    frame.replace(&receiver, &initialized);
+                }
+                frame.push_type(&data.sig.ret_ty);
+            }
+            invokedynamic(data) => {
+                pop_params(frame, &data.sig);
+                frame.push_type(&data.sig.ret_ty);
+            }
+            getfield(data) => {
+                frame.pop();
+                frame.push_type(&data.ty);
+            }
+            getstatic(data) => frame.push_type(&data.ty),
+            putfield(data) => {
+                frame.pop();
+                frame.pop();
+            }
+            putstatic(data) => {
+                frame.pop();
+            }
+
+            new(_) => frame.push(VT::Uninitialized(index)),
+            anewarray(ty) if ty.starts_with('[') => unary(frame, VT::Object(format!("[{ty}"))),
+            anewarray(ty) => unary(frame, VT::Object(format!("[L{ty};"))),
+            newarray(_, ty) => unary(frame, VT::Object(format!("[{ty}"))),
+            multianewarray(ty, dims) => {
+                for _ in 0..*dims {
+                    frame.pop();
+                }
+                frame.push(VT::Object(ty.clone()));
+            }
+            checkcast(ty) => unary(frame, VT::Object(ty.clone())),
+            instanceof(_) => unary(frame, VT::Integer),
+
+            ldc(constant) => frame.push(match constant {
+                Constant::Integer(_) => VT::Integer,
+                Constant::Float(_) => VT::Float,
+                Constant::Long(_) => VT::Long,
+                Constant::Double(_) => VT::Double,
+                Constant::String(_) => VT::Object("java/lang/String".to_string()),
+                Constant::Class(_) => VT::Object("java/lang/Class".to_string()),
+                Constant::MethodType(_) => VT::Object("java/lang/invoke/MethodType".to_string()),
+                Constant::MethodHandle(_) => {
+                    VT::Object("java/lang/invoke/MethodHandle".to_string())
+                }
+            }),
+            bipush(_) | sipush(_) => frame.push(VT::Integer),
+            iinc(id, _) => {
+                let local = frame.local(*id);
+                assert_eq!(
+                    local,
+                    VT::Integer,
+                    "Local variable {id} contains {local:?}, not Integer."
+                );
+            }
+
+            aload(id) => load(frame, *id, VT::Top),
+            fload(id) => load(frame, *id, VT::Float),
+            iload(id) => load(frame, *id, VT::Integer),
+            dload(id) => load(frame, *id, VT::Double),
+            lload(id) => load(frame, *id, VT::Long),
+            astore(id) | fstore(id) | istore(id) | dstore(id) | lstore(id) => store(frame, *id),
+
+            label(_) => {}
+            Branch(instr, _) => {
+                for _ in 0..instr.pops() {
+                    frame.pop();
                 }
             }
+            tableswitch(..) | lookupswitch(..) => {
+                frame.pop();
+            }
+
+            Basic(instr) => match instr {
+                // array load
+                aaload => {
+                    frame.pop();
+                    let array = frame.pop();
+                    frame.push(array_component(array));
+                }
+                baload | caload | iaload | saload => binary(frame, VT::Integer),
+                faload => binary(frame, VT::Float),
+                daload => binary(frame, VT::Double),
+                laload => binary(frame, VT::Long),
+                aastore | bastore | castore | fastore | iastore | sastore | dastore | lastore => {
+                    frame.pop();
+                    frame.pop();
+                    frame.pop();
+                }
+
+                // short-hand load and store
+                aload_0 => load(frame, 0, VT::Top),
+                aload_1 => load(frame, 1, VT::Top),
+                aload_2 => load(frame, 2, VT::Top),
+                aload_3 => load(frame, 3, VT::Top),
+                fload_0 => load(frame, 0, VT::Float),
+                fload_1 => load(frame, 1, VT::Float),
+                fload_2 => load(frame, 2, VT::Float),
+                fload_3 => load(frame, 3, VT::Float),
+                iload_0 => load(frame, 0, VT::Integer),
+                iload_1 => load(frame, 1, VT::Integer),
+                iload_2 => load(frame, 2, VT::Integer),
+                iload_3 => load(frame, 3, VT::Integer),
+                dload_0 => load(frame, 0, VT::Double),
+                dload_1 => load(frame, 1, VT::Double),
+                dload_2 => load(frame, 2, VT::Double),
+                dload_3 => load(frame, 3, VT::Double),
+                lload_0 => load(frame, 0, VT::Long),
+                lload_1 => load(frame, 1, VT::Long),
+                lload_2 => load(frame, 2, VT::Long),
+                lload_3 => load(frame, 3, VT::Long),
+                astore_0 | fstore_0 | istore_0 | dstore_0 | lstore_0 => store(frame, 0),
+                astore_1 | fstore_1 | istore_1 | dstore_1 | lstore_1 => store(frame, 1),
+                astore_2 | fstore_2 | istore_2 | dstore_2 | lstore_2 => store(frame, 2),
+                astore_3 | fstore_3 | istore_3 | dstore_3 | lstore_3 => store(frame, 3),
+
+                // constant values
+                aconst_null => frame.push(VT::Null),
+                fconst_0 | fconst_1 | fconst_2 => frame.push(VT::Float),
+                iconst_m1 | iconst_0 | iconst_1 | iconst_2 | iconst_3 | iconst_4 | iconst_5 => {
+                    frame.push(VT::Integer)
+                }
+                dconst_0 | dconst_1 => frame.push(VT::Double),
+                lconst_0 | lconst_1 => frame.push(VT::Long),
+
+                // conversions
+                d2i | f2i | i2b | i2c | i2s | l2i => unary(frame, VT::Integer),
+                d2f | i2f | l2f => unary(frame, VT::Float),
+                d2l | f2l | i2l => unary(frame, VT::Long),
+                f2d | i2d | l2d => unary(frame, VT::Double),
+
+                // mathematical operations
+                dadd | ddiv | dmul | drem | dsub => binary(frame, VT::Double),
+                dneg => unary(frame, VT::Double),
+                fadd | fdiv | fmul | frem | fsub => binary(frame, VT::Float),
+                fneg => unary(frame, VT::Float),
+                iadd | idiv | imul | irem | isub => binary(frame, VT::Integer),
+                iand | ior | ishl | ishr | iushr | ixor => binary(frame, VT::Integer),
+                ineg => unary(frame, VT::Integer),
+                ladd | ldiv | lmul | lrem | lsub => binary(frame, VT::Long),
+                land | lor | lshl | lshr | lushr | lxor => binary(frame, VT::Long),
+                lneg => unary(frame, VT::Long),
+                dcmpg | dcmpl | fcmpg | fcmpl | lcmp => binary(frame, VT::Integer),
+
+                // stack manipulation command
+                dup => duplicate(frame, 1, 0),
+                dup_x1 => duplicate(frame, 1, 1),
+                dup_x2 => duplicate(frame, 1, 2),
+                dup2 => duplicate(frame, 2, 0),
+                dup2_x1 => duplicate(frame, 2, 1),
+                dup2_x2 => duplicate(frame, 2, 2),
+                pop => {
+                    frame.pop_slots(1);
+                }
+                pop2 => {
+                    frame.pop_slots(2);
+                }
+                swap => {
+                    let a = frame.pop_slots(1);
+                    let b = frame.pop_slots(1);
+                    frame.push(a[0].clone());
+                    frame.push(b[0].clone());
+                }
+
+                // misc commands
+                vreturn | breakpoint | nop => {}
+                areturn | freturn | ireturn | dreturn | lreturn => {
+                    frame.pop();
+                }
+                monitorenter | monitorexit | athrow => {
+                    frame.pop();
+                }
+                arraylength => unary(frame, VT::Integer),
+            },
+        }
+    }
+
+    /// Returns the labels this instruction may branch to.
+    fn targets(&self) -> Vec<Label> {
+        match self {
+            Instruction::Branch(_, target) => vec![*target],
+            Instruction::tableswitch(_, default, targets) => {
+                let mut vec = vec![*default];
+                vec.extend(targets);
+                vec
+            }
+            Instruction::lookupswitch(default, targets) => {
+                let mut vec = vec![*default];
+                vec.extend(targets.iter().map(|x| x.1));
+                vec
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns whether execution never continues to the next instruction.
+    fn ends_block(&self) -> bool {
+        use BasicInstruction::*;
+        match self {
+            Instruction::Branch(instr, _) => instr.is_unconditional(),
+            Instruction::tableswitch(..) | Instruction::lookupswitch(..) => true,
+            Instruction::Basic(
+                vreturn | areturn | freturn | ireturn | dreturn | lreturn | athrow,
+            ) => true,
+            _ => false,
         }
     }
 
@@ -358,12 +923,46 @@ impl Instruction {
         }
         Ok(())
     }
-    fn write(&self, pool: &mut PoolWriter, mut out: impl Write) -> Result<(), Error> {
+    fn write_target(
+        out: &mut Cursor<Vec<u8>>,
+        fixups: &mut Vec<Fixup>,
+        base: u32,
+        target: Label,
+        wide: bool,
+    ) -> Result<(), Error> {
+        fixups.push(Fixup { pos: out.position(), base, target, wide });
+        if wide {
+            out.write_i32::<BE>(0)
+        } else {
+            out.write_i16::<BE>(0)
+        }
+    }
+    fn write_switch_header(
+        out: &mut Cursor<Vec<u8>>,
+        fixups: &mut Vec<Fixup>,
+        opcode: u8,
+        default: Label,
+    ) -> Result<u32, Error> {
+        let base = out.position() as u32;
+        out.write_u8(opcode)?;
+        let padding = (4 - out.position() % 4) % 4;
+        for _ in 0..padding {
+            out.write_u8(0)?;
+        }
+        Self::write_target(out, fixups, base, default, true)?;
+        Ok(base)
+    }
+    fn write(
+        &self,
+        pool: &mut PoolWriter,
+        mut out: &mut Cursor<Vec<u8>>,
+        fixups: &mut Vec<Fixup>,
+    ) -> Result<(), Error> {
         match self {
             Instruction::invokeinterface(data) => {
                 out.write_u8(0xb9)?;
                 data.make_interface_method_ref(pool).write(&mut out)?;
-                out.write_u8((data.retc + 1) as u8)?;
+                out.write_u8((data.argc + 1) as u8)?;
                 out.write_u8(0)?;
             }
             Instruction::invokespecial(data) => {
@@ -378,6 +977,12 @@ impl Instruction {
                 out.write_u8(0xb6)?;
                 data.make_method_ref(pool).write(&mut out)?;
             }
+            Instruction::invokedynamic(data) => {
+                out.write_u8(0xba)?;
+                pool.invoke_dynamic(&data.bootstrap, &data.bootstrap_args, &data.name, &data.desc)
+                    .write(&mut out)?;
+                out.write_u16::<BE>(0)?;
+            }
             Instruction::getfield(data) => {
                 out.write_u8(0xb4)?;
                 data.make_ref(pool).write(&mut out)?;
@@ -402,39 +1007,60 @@ impl Instruction {
                 out.write_u8(0xbd)?;
                 pool.class(ty).write(&mut out)?;
             }
+            Instruction::newarray(atype, _) => {
+                out.write_u8(0xbc)?;
+                out.write_u8(*atype)?;
+            }
+            Instruction::multianewarray(ty, dims) => {
+                out.write_u8(0xc5)?;
+                pool.class(ty).write(&mut out)?;
+                out.write_u8(*dims)?;
+            }
             Instruction::checkcast(ty) => {
                 out.write_u8(0xc0)?;
                 pool.class(ty).write(&mut out)?;
             }
-            Instruction::aconst_class(str) => {
-                out.write_u8(0x13)?; // ldc_w
-                pool.class(str).write(&mut out)?;
+            Instruction::instanceof(ty) => {
+                out.write_u8(0xc1)?;
+                pool.class(ty).write(&mut out)?;
+            }
+            Instruction::ldc(constant) => {
+                let id = pool.constant(constant);
+                match constant {
+                    Constant::Long(_) | Constant::Double(_) => {
+                        out.write_u8(0x14)?; // ldc2_w
+                        id.write(&mut out)?;
+                    }
+                    _ if id.index() <= u8::MAX as u16 => {
+                        out.write_u8(0x12)?; // ldc
+                        out.write_u8(id.index() as u8)?;
+                    }
+                    _ => {
+                        out.write_u8(0x13)?; // ldc_w
+                        id.write(&mut out)?;
+                    }
+                }
             }
-            Instruction::aconst_str(str) => {
-                out.write_u8(0x13)?; // ldc_w
-                pool.string(str).write(&mut out)?;
+            Instruction::bipush(v) => {
+                out.write_u8(0x10)?;
+                out.write_i8(*v)?;
             }
-            Instruction::fconst(v) => {
-                out.write_u8(0x13)?; // ldc_w
-                pool.float(*v).write(&mut out)?;
+            Instruction::sipush(v) => {
+                out.write_u8(0x11)?;
+                out.write_i16::<BE>(*v)?;
             }
-            Instruction::iconst(v) => {
-                if *v <= i8::MAX as i32 && *v >= i8::MIN as i32 {
-                    out.write_u8(0x10)?; // bipush
-                    out.write_i8(*v as i8)?;
+            Instruction::iinc(id, delta) => {
+                if *id <= u8::MAX as u16 && *delta >= i8::MIN as i16 && *delta <= i8::MAX as i16 {
+                    out.write_u8(0x84)?;
+                    out.write_u8(*id as u8)?;
+                    out.write_i8(*delta as i8)?;
                 } else {
-                    out.write_u8(0x13)?; // ldc
-                    pool.integer(*v).write(&mut out)?;
+                    out.write_u8(0xc4)?; // wide prefix
+                    out.write_u8(0x84)?;
+                    out.write_u16::<BE>(*id)?;
+                    out.write_i16::<BE>(*delta)?;
                 }
             }
-            Instruction::dconst(v) => {
-                out.write_u8(0x14)?; // ldc2_w
-                pool.double(*v).write(&mut out)?;
-            }
-            Instruction::lconst(v) => {
-                out.write_u8(0x14)?; // ldc2_w
-                pool.long(*v).write(&mut out)?;
-            }
             Instruction::aload(id) => self.write_id(&mut out, 0x19, *id)?,
             Instruction::fload(id) => self.write_id(&mut out, 0x17, *id)?,
             Instruction::iload(id) => self.write_id(&mut out, 0x15, *id)?,
@@ -445,6 +1071,28 @@ impl Instruction {
             Instruction::istore(id) => self.write_id(&mut out, 0x36, *id)?,
             Instruction::dstore(id) => self.write_id(&mut out, 0x39, *id)?,
             Instruction::lstore(id) => self.write_id(&mut out, 0x37, *id)?,
+            Instruction::label(_) => {}
+            Instruction::Branch(instr, target) => {
+                let base = out.position() as u32;
+                out.write_u8(instr.opcode())?;
+                Self::write_target(out, fixups, base, *target, false)?;
+            }
+            Instruction::tableswitch(low, default, targets) => {
+                let base = Self::write_switch_header(out, fixups, 0xaa, *default)?;
+                out.write_i32::<BE>(*low)?;
+                out.write_i32::<BE>(*low + (targets.len() as i32 - 1))?;
+                for target in targets {
+                    Self::write_target(out, fixups, base, *target, true)?;
+                }
+            }
+            Instruction::lookupswitch(default, targets) => {
+                let base = Self::write_switch_header(out, fixups, 0xab, *default)?;
+                out.write_u32::<BE>(targets.len() as u32)?;
+                for (key, target) in targets {
+                    out.write_i32::<BE>(*key)?;
+                    Self::write_target(out, fixups, base, *target, true)?;
+                }
+            }
             Instruction::Basic(instr) => {
                 out.write_u8(instr.opcode())?;
             }
@@ -670,3 +1318,53 @@ proxy_loadstore! {
     (dstore dstore_0 dstore_1 dstore_2 dstore_3, 2)
     (lstore lstore_0 lstore_1 lstore_2 lstore_3, 2)
 }
+
+macro_rules! branch_instructions {
+    ($($hex:literal $name:ident $pops:literal,)*) => {
+        #[derive(Copy, Clone, Debug)]
+        #[allow(non_camel_case_types)]
+        enum BranchInstruction {
+            $($name,)*
+        }
+        impl BranchInstruction {
+            fn opcode(&self) -> u8 {
+                match *self {
+                    $(BranchInstruction::$name => $hex,)*
+                }
+            }
+            fn pops(&self) -> usize {
+                match *self {
+                    $(BranchInstruction::$name => $pops,)*
+                }
+            }
+            fn is_unconditional(&self) -> bool {
+                matches!(self, BranchInstruction::goto)
+            }
+        }
+        impl MethodWriter {$(
+            #[inline(never)]
+            pub fn $name(&mut self, target: Label) -> &mut Self {
+                self.push_instr(Instruction::Branch(BranchInstruction::$name, target))
+            }
+        )*}
+    };
+}
+branch_instructions! {
+    0xa5 if_acmpeq 2,
+    0xa6 if_acmpne 2,
+    0x9f if_icmpeq 2,
+    0xa2 if_icmpge 2,
+    0xa3 if_icmpgt 2,
+    0xa4 if_icmple 2,
+    0xa1 if_icmplt 2,
+    0xa0 if_icmpne 2,
+    0x99 ifeq 1,
+    0x9c ifge 1,
+    0x9d ifgt 1,
+    0x9e ifle 1,
+    0x9b iflt 1,
+    0x9a ifne 1,
+    0xc7 ifnonnull 1,
+    0xc6 ifnull 1,
+    0xa7 goto 0,
+}
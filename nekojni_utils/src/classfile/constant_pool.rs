@@ -1,3 +1,4 @@
+use crate::classfile::attributes::Attribute;
 use byteorder::{WriteBytesExt, BE};
 use std::{
     collections::HashMap,
@@ -7,6 +8,9 @@ use std::{
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct PoolId(u16);
 impl PoolId {
+    pub fn index(&self) -> u16 {
+        self.0
+    }
     pub fn write(&self, mut w: impl Write) -> Result<(), Error> {
         w.write_u16::<BE>(self.0)
     }
@@ -21,20 +25,100 @@ enum PoolEntry {
     Double(u64), // f64 bits
     Class(PoolId),
     String(PoolId),
-    FieldRef { class_index: PoolId, name_and_type_index: PoolId },
-    MethodRef { class_index: PoolId, name_and_type_index: PoolId },
-    InterfaceMethodRef { class_index: PoolId, name_and_type_index: PoolId },
-    NameAndType { name_index: PoolId, descriptor_index: PoolId },
+    FieldRef {
+        class_index: PoolId,
+        name_and_type_index: PoolId,
+    },
+    MethodRef {
+        class_index: PoolId,
+        name_and_type_index: PoolId,
+    },
+    InterfaceMethodRef {
+        class_index: PoolId,
+        name_and_type_index: PoolId,
+    },
+    NameAndType {
+        name_index: PoolId,
+        descriptor_index: PoolId,
+    },
+    MethodHandle {
+        reference_kind: u8,
+        reference_index: PoolId,
+    },
+    MethodType {
+        descriptor_index: PoolId,
+    },
+    InvokeDynamic {
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: PoolId,
+    },
+}
+
+/// The kind of a method handle constant.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum HandleKind {
+    GetField = 1,
+    GetStatic = 2,
+    PutField = 3,
+    PutStatic = 4,
+    InvokeVirtual = 5,
+    InvokeStatic = 6,
+    InvokeSpecial = 7,
+    NewInvokeSpecial = 8,
+    InvokeInterface = 9,
+}
+
+/// A method handle constant, referring to a field or method.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Handle {
+    pub kind: HandleKind,
+    pub class: String,
+    pub name: String,
+    pub desc: String,
+    /// Whether `class` is an interface. This only matters for `InvokeStatic` and `InvokeSpecial`.
+    pub interface: bool,
+}
+#[allow(dead_code)]
+impl Handle {
+    pub fn new(kind: HandleKind, class: &str, name: &str, desc: &str, interface: bool) -> Self {
+        Handle {
+            kind,
+            class: class.to_string(),
+            name: name.to_string(),
+            desc: desc.to_string(),
+            interface,
+        }
+    }
+}
+
+/// A loadable constant, as used by `ldc` and bootstrap method arguments.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub enum Constant {
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    String(String),
+    Class(String),
+    MethodType(String),
+    MethodHandle(Handle),
 }
 
 #[derive(Default, Debug)]
 pub struct PoolWriter {
     writer: Cursor<Vec<u8>>,
     cache: HashMap<PoolEntry, PoolId>,
+    slots: usize,
+    bootstrap_methods: Vec<(PoolId, Vec<PoolId>)>,
+    bootstrap_cache: HashMap<(PoolId, Vec<PoolId>), u16>,
 }
 impl PoolWriter {
+    /// Returns the number of slots used in the constant pool. `long` and `double` entries take up
+    /// two slots.
     pub fn len(&self) -> usize {
-        self.cache.len()
+        self.slots
     }
     pub fn contents(&self) -> &[u8] {
         self.writer.get_ref().as_slice()
@@ -94,6 +178,20 @@ impl PoolWriter {
                 self.writer.write_u16::<BE>(name_index.0)?;
                 self.writer.write_u16::<BE>(descriptor_index.0)?;
             }
+            PoolEntry::MethodHandle { reference_kind, reference_index } => {
+                self.writer.write_u8(15)?;
+                self.writer.write_u8(*reference_kind)?;
+                self.writer.write_u16::<BE>(reference_index.0)?;
+            }
+            PoolEntry::MethodType { descriptor_index } => {
+                self.writer.write_u8(16)?;
+                self.writer.write_u16::<BE>(descriptor_index.0)?;
+            }
+            PoolEntry::InvokeDynamic { bootstrap_method_attr_index, name_and_type_index } => {
+                self.writer.write_u8(18)?;
+                self.writer.write_u16::<BE>(*bootstrap_method_attr_index)?;
+                self.writer.write_u16::<BE>(name_and_type_index.0)?;
+            }
         }
         Ok(())
     }
@@ -101,8 +199,12 @@ impl PoolWriter {
         if let Some(x) = self.cache.get(&entry) {
             *x
         } else {
-            let raw_id = self.cache.len() + 1;
+            let raw_id = self.slots + 1;
             assert!(raw_id <= u16::MAX as usize);
+            self.slots += match entry {
+                PoolEntry::Long(_) | PoolEntry::Double(_) => 2,
+                _ => 1,
+            };
 
             self.write_entry(&entry)
                 .expect("Could not successfully write pool entry?");
@@ -134,7 +236,7 @@ impl PoolWriter {
         self.entry(PoolEntry::Double(v.to_bits()))
     }
     pub fn class(&mut self, v: &str) -> PoolId {
-        let contents = self.utf8(&v);
+        let contents = self.utf8(v);
         self.entry(PoolEntry::Class(contents))
     }
     pub fn string(&mut self, str: &str) -> PoolId {
@@ -143,20 +245,109 @@ impl PoolWriter {
     }
     pub fn field_ref(&mut self, cl: &str, name: &str, ty: &str) -> PoolId {
         let class = self.class(cl);
-        let name_and_type = self.name_and_type(name, &ty);
+        let name_and_type = self.name_and_type(name, ty);
         self.entry(PoolEntry::FieldRef { class_index: class, name_and_type_index: name_and_type })
     }
     pub fn method_ref(&mut self, cl: &str, name: &str, ty: &str) -> PoolId {
         let class = self.class(cl);
-        let name_and_type = self.name_and_type(name, &ty);
+        let name_and_type = self.name_and_type(name, ty);
         self.entry(PoolEntry::MethodRef { class_index: class, name_and_type_index: name_and_type })
     }
     pub fn interface_method_ref(&mut self, cl: &str, name: &str, ty: &str) -> PoolId {
         let class = self.class(cl);
-        let name_and_type = self.name_and_type(name, &ty);
+        let name_and_type = self.name_and_type(name, ty);
         self.entry(PoolEntry::InterfaceMethodRef {
             class_index: class,
             name_and_type_index: name_and_type,
         })
     }
+
+    pub fn method_handle(&mut self, handle: &Handle) -> PoolId {
+        let reference_index = match handle.kind {
+            HandleKind::GetField
+            | HandleKind::GetStatic
+            | HandleKind::PutField
+            | HandleKind::PutStatic => self.field_ref(&handle.class, &handle.name, &handle.desc),
+            HandleKind::InvokeInterface => {
+                self.interface_method_ref(&handle.class, &handle.name, &handle.desc)
+            }
+            HandleKind::InvokeStatic | HandleKind::InvokeSpecial if handle.interface => {
+                self.interface_method_ref(&handle.class, &handle.name, &handle.desc)
+            }
+            _ => self.method_ref(&handle.class, &handle.name, &handle.desc),
+        };
+        self.entry(PoolEntry::MethodHandle { reference_kind: handle.kind as u8, reference_index })
+    }
+    pub fn method_type(&mut self, desc: &str) -> PoolId {
+        let descriptor_index = self.utf8(desc);
+        self.entry(PoolEntry::MethodType { descriptor_index })
+    }
+    pub fn constant(&mut self, constant: &Constant) -> PoolId {
+        match constant {
+            Constant::Integer(v) => self.integer(*v),
+            Constant::Float(v) => self.float(*v),
+            Constant::Long(v) => self.long(*v),
+            Constant::Double(v) => self.double(*v),
+            Constant::String(v) => self.string(v),
+            Constant::Class(v) => self.class(v),
+            Constant::MethodType(v) => self.method_type(v),
+            Constant::MethodHandle(v) => self.method_handle(v),
+        }
+    }
+    pub fn invoke_dynamic(
+        &mut self,
+        bootstrap: &Handle,
+        bootstrap_args: &[Constant],
+        name: &str,
+        desc: &str,
+    ) -> PoolId {
+        let bootstrap_method_attr_index = self.bootstrap_method(bootstrap, bootstrap_args);
+        let name_and_type_index = self.name_and_type(name, desc);
+        self.entry(PoolEntry::InvokeDynamic { bootstrap_method_attr_index, name_and_type_index })
+    }
+
+    fn bootstrap_method(&mut self, bootstrap: &Handle, bootstrap_args: &[Constant]) -> u16 {
+        let handle = self.method_handle(bootstrap);
+        let args = bootstrap_args.iter().map(|x| self.constant(x)).collect();
+        let key = (handle, args);
+        if let Some(x) = self.bootstrap_cache.get(&key) {
+            *x
+        } else {
+            let index = self.bootstrap_methods.len();
+            assert!(index < u16::MAX as usize);
+            self.bootstrap_methods.push(key.clone());
+            self.bootstrap_cache.insert(key, index as u16);
+            index as u16
+        }
+    }
+
+    /// Returns the contents of the `BootstrapMethods` attribute, if any bootstrap methods are used.
+    pub fn bootstrap_methods(&self) -> Option<BootstrapMethods> {
+        if self.bootstrap_methods.is_empty() {
+            None
+        } else {
+            Some(BootstrapMethods(self.bootstrap_methods.clone()))
+        }
+    }
+}
+
+/// Represents the `BootstrapMethods` attribute of a Java class.
+#[derive(Debug)]
+pub struct BootstrapMethods(Vec<(PoolId, Vec<PoolId>)>);
+impl Attribute for BootstrapMethods {
+    fn name(&self) -> &str {
+        "BootstrapMethods"
+    }
+    fn write(&self, _: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        out.write_u16::<BE>(self.0.len() as u16)?;
+        for (handle, args) in &self.0 {
+            handle.write(&mut *out)?;
+            assert!(args.len() <= u16::MAX as usize);
+            out.write_u16::<BE>(args.len() as u16)?;
+            for arg in args {
+                arg.write(&mut *out)?;
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,317 @@
+use crate::{
+    classfile::{attributes::Attribute, PoolWriter},
+    signatures::{BasicType, Type},
+};
+use byteorder::{WriteBytesExt, BE};
+use std::io::{Cursor, Error, Write};
+
+/// A type used by the JVM's type checking verifier to describe the contents of a local variable or
+/// an operand stack entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    /// An initialized reference. This contains an internal class name or an array descriptor.
+    Object(String),
+    /// An object created by a `new` instruction whose constructor has not been called yet.
+    ///
+    /// While code is being generated, this contains the index of the `new` instruction. It is
+    /// replaced by the bytecode offset of the instruction when the frame is written.
+    Uninitialized(usize),
+}
+impl VerificationType {
+    /// Returns the verification type of a value with a given Java type.
+    pub fn from_type(ty: &Type) -> Self {
+        if ty.array_dim != 0 {
+            VerificationType::Object(ty.display_jni().to_string())
+        } else {
+            match &ty.basic_sig {
+                BasicType::Byte
+                | BasicType::Short
+                | BasicType::Int
+                | BasicType::Boolean
+                | BasicType::Char => VerificationType::Integer,
+                BasicType::Long => VerificationType::Long,
+                BasicType::Float => VerificationType::Float,
+                BasicType::Double => VerificationType::Double,
+                BasicType::Class(name) => VerificationType::Object(name.display_jni().to_string()),
+                BasicType::Void => panic!("Void is not a valid stack type."),
+            }
+        }
+    }
+
+    /// Returns the number of stack or local variable slots used by a value of this type.
+    pub fn size(&self) -> usize {
+        match self {
+            VerificationType::Long | VerificationType::Double => 2,
+            _ => 1,
+        }
+    }
+
+    fn is_initialized_ref(&self) -> bool {
+        matches!(self, VerificationType::Null | VerificationType::Object(_))
+    }
+
+    /// Returns a type that both `self` and `other` are assignable to, or `None` if there is none.
+    ///
+    /// As the class hierarchy is not known, two different object types are merged to
+    /// `java/lang/Object`, and must be cast back to a more specific type before use.
+    fn merge(&self, other: &VerificationType) -> Option<VerificationType> {
+        match (self, other) {
+            _ if self == other => Some(self.clone()),
+            (VerificationType::Null, other) | (other, VerificationType::Null)
+                if other.is_initialized_ref() =>
+            {
+                Some(other.clone())
+            }
+            (VerificationType::Object(_), VerificationType::Object(_)) => {
+                Some(VerificationType::Object("java/lang/Object".to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    fn is_assignable_to(&self, target: &VerificationType) -> bool {
+        match (self, target) {
+            _ if self == target => true,
+            (_, VerificationType::Top) => true,
+            (VerificationType::Null, VerificationType::Object(_)) => true,
+            (VerificationType::Object(_), VerificationType::Object(name)) => {
+                name == "java/lang/Object"
+            }
+            _ => false,
+        }
+    }
+
+    fn write(&self, pool: &mut PoolWriter, mut out: impl Write) -> Result<(), Error> {
+        match self {
+            VerificationType::Top => out.write_u8(0)?,
+            VerificationType::Integer => out.write_u8(1)?,
+            VerificationType::Float => out.write_u8(2)?,
+            VerificationType::Double => out.write_u8(3)?,
+            VerificationType::Long => out.write_u8(4)?,
+            VerificationType::Null => out.write_u8(5)?,
+            VerificationType::UninitializedThis => out.write_u8(6)?,
+            VerificationType::Object(name) => {
+                out.write_u8(7)?;
+                pool.class(name).write(&mut out)?;
+            }
+            VerificationType::Uninitialized(offset) => {
+                assert!(*offset <= u16::MAX as usize);
+                out.write_u8(8)?;
+                out.write_u16::<BE>(*offset as u16)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The types of the local variables and operand stack at a given point in a method.
+///
+/// Local variables are stored by slot, with the second slot of a `long` or `double` being `Top`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Frame {
+    pub locals: Vec<VerificationType>,
+    pub stack: Vec<VerificationType>,
+}
+impl Frame {
+    pub fn push(&mut self, ty: VerificationType) {
+        self.stack.push(ty);
+    }
+    pub fn push_type(&mut self, ty: &Type) {
+        if *ty != Type::Void {
+            self.push(VerificationType::from_type(ty));
+        }
+    }
+    pub fn pop(&mut self) -> VerificationType {
+        self.stack.pop().expect("Operand stack underflow.")
+    }
+
+    /// Pops values totalling exactly `slots` stack slots, returning them in stack order.
+    pub fn pop_slots(&mut self, slots: usize) -> Vec<VerificationType> {
+        let mut values = Vec::new();
+        let mut count = 0;
+        while count < slots {
+            let value = self.pop();
+            count += value.size();
+            values.push(value);
+        }
+        assert_eq!(count, slots, "Instruction would split a long or double value.");
+        values.reverse();
+        values
+    }
+
+    /// Returns the size of the operand stack in slots.
+    pub fn stack_size(&self) -> usize {
+        self.stack.iter().map(VerificationType::size).sum()
+    }
+
+    pub fn local(&self, id: u16) -> VerificationType {
+        self.locals
+            .get(id as usize)
+            .cloned()
+            .unwrap_or(VerificationType::Top)
+    }
+    pub fn set_local(&mut self, id: u16, ty: VerificationType) {
+        let id = id as usize;
+        let end = id + ty.size();
+        if self.locals.len() < end {
+            self.locals.resize(end, VerificationType::Top);
+        }
+
+        // overwriting the second half of a long or double invalidates the whole value
+        if id > 0 && self.locals[id - 1].size() == 2 {
+            self.locals[id - 1] = VerificationType::Top;
+        }
+
+        if ty.size() == 2 {
+            self.locals[id + 1] = VerificationType::Top;
+        }
+        self.locals[id] = ty;
+    }
+
+    /// Replaces all occurrences of a type. This is used when an object is initialized.
+    pub fn replace(&mut self, from: &VerificationType, to: &VerificationType) {
+        for ty in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if ty == from {
+                *ty = to.clone();
+            }
+        }
+    }
+
+    /// Returns a frame that both `self` and `other` are assignable to, or `None` if their operand
+    /// stacks are not compatible. Local variables that cannot be merged become unusable.
+    pub fn merge(&self, other: &Frame) -> Option<Frame> {
+        if self.stack.len() != other.stack.len() {
+            return None;
+        }
+
+        let mut stack = Vec::new();
+        for (a, b) in self.stack.iter().zip(&other.stack) {
+            stack.push(a.merge(b)?);
+        }
+
+        let len = self.locals.len().max(other.locals.len());
+        let locals = (0..len as u16)
+            .map(|i| {
+                self.local(i)
+                    .merge(&other.local(i))
+                    .unwrap_or(VerificationType::Top)
+            })
+            .collect();
+
+        Some(Frame { locals, stack })
+    }
+
+    pub fn is_assignable_to(&self, target: &Frame) -> bool {
+        let len = self.locals.len().max(target.locals.len());
+        self.stack.len() == target.stack.len()
+            && self
+                .stack
+                .iter()
+                .zip(&target.stack)
+                .all(|(a, b)| a.is_assignable_to(b))
+            && (0..len as u16).all(|i| self.local(i).is_assignable_to(&target.local(i)))
+    }
+
+    /// Replaces the instruction indexes in uninitialized types with bytecode offsets.
+    pub fn resolve_offsets(&self, offsets: &[u32]) -> Frame {
+        let resolve = |ty: &VerificationType| match ty {
+            VerificationType::Uninitialized(index) => {
+                VerificationType::Uninitialized(offsets[*index] as usize)
+            }
+            ty => ty.clone(),
+        };
+        Frame {
+            locals: self.locals.iter().map(resolve).collect(),
+            stack: self.stack.iter().map(resolve).collect(),
+        }
+    }
+
+    /// Returns the local variables in the format used by the `StackMapTable` attribute.
+    fn encoded_locals(&self) -> Vec<VerificationType> {
+        let mut locals = Vec::new();
+        let mut i = 0;
+        while i < self.locals.len() {
+            locals.push(self.locals[i].clone());
+            i += self.locals[i].size();
+        }
+        while locals.last() == Some(&VerificationType::Top) {
+            locals.pop();
+        }
+        locals
+    }
+}
+
+/// Represents the `StackMapTable` attribute of a method's code.
+#[derive(Debug)]
+pub struct StackMapTable {
+    initial: Frame,
+    frames: Vec<(u32, Frame)>,
+}
+impl StackMapTable {
+    /// Creates a new stack map table. `frames` must be sorted by offset, and contain no
+    /// duplicate offsets.
+    pub fn new(initial: Frame, frames: Vec<(u32, Frame)>) -> Self {
+        StackMapTable { initial, frames }
+    }
+}
+impl Attribute for StackMapTable {
+    fn name(&self) -> &str {
+        "StackMapTable"
+    }
+    fn write(&self, pool: &mut PoolWriter, out: &mut Cursor<Vec<u8>>) -> Result<(), Error> {
+        assert!(self.frames.len() <= u16::MAX as usize);
+        out.write_u16::<BE>(self.frames.len() as u16)?;
+
+        let mut last_locals = self.initial.encoded_locals();
+        let mut last_offset = None;
+        for (offset, frame) in &self.frames {
+            let delta = match last_offset {
+                Some(last_offset) => offset - last_offset - 1,
+                None => *offset,
+            };
+            assert!(delta <= u16::MAX as u32);
+            let delta = delta as u16;
+
+            let locals = frame.encoded_locals();
+            if locals == last_locals && frame.stack.is_empty() {
+                if delta < 64 {
+                    out.write_u8(delta as u8)?; // same_frame
+                } else {
+                    out.write_u8(251)?; // same_frame_extended
+                    out.write_u16::<BE>(delta)?;
+                }
+            } else if locals == last_locals && frame.stack.len() == 1 {
+                if delta < 64 {
+                    out.write_u8(64 + delta as u8)?; // same_locals_1_stack_item_frame
+                } else {
+                    out.write_u8(247)?; // same_locals_1_stack_item_frame_extended
+                    out.write_u16::<BE>(delta)?;
+                }
+                frame.stack[0].write(pool, &mut *out)?;
+            } else {
+                out.write_u8(255)?; // full_frame
+                out.write_u16::<BE>(delta)?;
+                out.write_u16::<BE>(locals.len() as u16)?;
+                for ty in &locals {
+                    ty.write(pool, &mut *out)?;
+                }
+                out.write_u16::<BE>(frame.stack.len() as u16)?;
+                for ty in &frame.stack {
+                    ty.write(pool, &mut *out)?;
+                }
+            }
+
+            last_locals = locals;
+            last_offset = Some(*offset);
+        }
+
+        Ok(())
+    }
+}
@@ -1,16 +1,23 @@
 mod attributes;
 mod code;
 mod constant_pool;
+mod frames;
 pub mod utils;
+mod verify;
 
 pub use code::MethodWriter;
+pub use constant_pool::{Constant, Handle, HandleKind};
+pub use verify::{verify_class, VerifyError};
 
 use crate::{
     classfile::{
-        attributes::{AttributeTable, SourceFile},
+        attributes::{
+            AttributeTable, Deprecated, MethodParameters, NestHost, NestMembers,
+            RuntimeAnnotations, RuntimeParameterAnnotations, Signature, SourceFile,
+        },
         constant_pool::{PoolId, PoolWriter},
     },
-    signatures::MethodSig,
+    signatures::{Annotation, MethodSig},
     CFlags, FFlags, MFlags,
 };
 use byteorder::{WriteBytesExt, BE};
@@ -20,12 +27,59 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
+/// The annotations applied to a class, field, method or parameter.
+#[derive(Debug, Default)]
+struct AnnotationSet {
+    visible: Vec<Annotation>,
+    invisible: Vec<Annotation>,
+}
+impl AnnotationSet {
+    fn push(&mut self, annotation: Annotation, visible: bool) {
+        if visible {
+            self.visible.push(annotation);
+        } else {
+            self.invisible.push(annotation);
+        }
+    }
+
+    fn write_attributes(&mut self, attributes: &mut AttributeTable) {
+        if !self.visible.is_empty() {
+            attributes.push(RuntimeAnnotations::new(true, std::mem::take(&mut self.visible)));
+        }
+        if !self.invisible.is_empty() {
+            attributes.push(RuntimeAnnotations::new(false, std::mem::take(&mut self.invisible)));
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct FieldData {
     access: EnumSet<FFlags>,
     name: String,
     jni_sig: String,
     attributes: AttributeTable,
+    annotations: AnnotationSet,
+}
+
+impl FieldData {
+    /// Sets the generic type of the field, in the format used by the `Signature` attribute.
+    pub fn signature(&mut self, signature: &str) -> &mut Self {
+        self.attributes.push(Signature::new(signature));
+        self
+    }
+
+    /// Adds an annotation to the field. Invisible annotations are stored in the class file, but
+    /// are not available through reflection.
+    pub fn annotation(&mut self, annotation: Annotation, visible: bool) -> &mut Self {
+        self.annotations.push(annotation, visible);
+        self
+    }
+
+    /// Marks the field as deprecated. This should be paired with the `@Deprecated` annotation.
+    pub fn deprecated(&mut self) -> &mut Self {
+        self.attributes.push(Deprecated);
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -34,17 +88,90 @@ pub struct MethodData {
     name: String,
     jni_sig: String,
     attributes: AttributeTable,
+    annotations: AnnotationSet,
+    parameter_annotations: Vec<AnnotationSet>,
 
     code_written: bool,
-    arg_stack_size: usize,
+    class_name: String,
 }
 impl MethodData {
     pub fn code(&mut self) -> MethodWriterGuard {
         assert!(!self.code_written);
-        let mut writer = MethodWriter::default();
-        writer.argc(self.arg_stack_size);
+        let writer = MethodWriter::for_method(
+            &self.class_name,
+            &self.name,
+            &self.jni_sig,
+            self.access.contains(MFlags::Static),
+        );
         MethodWriterGuard { data: self, writer }
     }
+
+    /// Sets the generic signature of the method, in the format used by the `Signature` attribute.
+    pub fn signature(&mut self, signature: &str) -> &mut Self {
+        self.attributes.push(Signature::new(signature));
+        self
+    }
+
+    /// Sets the names of the method's parameters. An empty name leaves that parameter unnamed.
+    pub fn parameters(&mut self, names: &[&str]) -> &mut Self {
+        let sig = MethodSig::parse_jni(&self.jni_sig).unwrap();
+        assert_eq!(names.len(), sig.params.len(), "Wrong number of parameter names.");
+        let names = names
+            .iter()
+            .map(|x| if x.is_empty() { None } else { Some(x.to_string()) })
+            .collect();
+        self.attributes.push(MethodParameters::new(names));
+        self
+    }
+
+    /// Adds an annotation to the method. Invisible annotations are stored in the class file, but
+    /// are not available through reflection.
+    pub fn annotation(&mut self, annotation: Annotation, visible: bool) -> &mut Self {
+        self.annotations.push(annotation, visible);
+        self
+    }
+
+    /// Marks the method as deprecated. This should be paired with the `@Deprecated` annotation.
+    pub fn deprecated(&mut self) -> &mut Self {
+        self.attributes.push(Deprecated);
+        self
+    }
+
+    /// Adds an annotation to one of the method's parameters.
+    pub fn parameter_annotation(
+        &mut self,
+        param: usize,
+        annotation: Annotation,
+        visible: bool,
+    ) -> &mut Self {
+        if self.parameter_annotations.is_empty() {
+            let sig = MethodSig::parse_jni(&self.jni_sig).unwrap();
+            self.parameter_annotations
+                .resize_with(sig.params.len(), Default::default);
+        }
+        assert!(param < self.parameter_annotations.len(), "Parameter index out of range.");
+        self.parameter_annotations[param].push(annotation, visible);
+        self
+    }
+
+    fn write_annotations(&mut self) {
+        self.annotations.write_attributes(&mut self.attributes);
+        if !self.parameter_annotations.is_empty() {
+            let (visible, invisible): (Vec<_>, Vec<_>) = self
+                .parameter_annotations
+                .drain(..)
+                .map(|x| (x.visible, x.invisible))
+                .unzip();
+            if visible.iter().any(|x| !x.is_empty()) {
+                self.attributes
+                    .push(RuntimeParameterAnnotations::new(true, visible));
+            }
+            if invisible.iter().any(|x| !x.is_empty()) {
+                self.attributes
+                    .push(RuntimeParameterAnnotations::new(false, invisible));
+            }
+        }
+    }
 }
 
 pub struct MethodWriterGuard<'a> {
@@ -67,13 +194,15 @@ impl<'a> Drop for MethodWriterGuard<'a> {
         self.data.code_written = true;
         self.data
             .attributes
-            .push(std::mem::replace(&mut self.writer, MethodWriter::default()));
+            .push(std::mem::take(&mut self.writer));
     }
 }
 
 #[derive(Debug)]
 pub struct ClassWriter {
     pool: PoolWriter,
+    major_version: u16,
+    name: String,
 
     access_flags: EnumSet<CFlags>,
     this_class: PoolId,
@@ -83,26 +212,40 @@ pub struct ClassWriter {
     fields: Vec<FieldData>,
     methods: Vec<MethodData>,
     attributes: AttributeTable,
+    annotations: AnnotationSet,
 
     source_file_written: bool,
+    nest_host: Option<String>,
+    nest_members: Vec<String>,
 }
 impl ClassWriter {
     pub fn new(access_flags: EnumSet<CFlags>, name: &str) -> Self {
         let mut pool = PoolWriter::default();
-        let name = pool.class(name);
+        let this_class = pool.class(name);
         ClassWriter {
             pool,
+            major_version: 52,
+            name: name.to_string(),
             access_flags,
-            this_class: name,
+            this_class,
             extends: None,
             implements: Vec::new(),
             fields: Vec::new(),
             methods: Vec::new(),
             attributes: Default::default(),
+            annotations: Default::default(),
             source_file_written: false,
+            nest_host: None,
+            nest_members: Vec::new(),
         }
     }
 
+    /// Sets the major version of the class file. This defaults to 52 (Java 8).
+    pub fn version(&mut self, major_version: u16) -> &mut Self {
+        self.major_version = major_version;
+        self
+    }
+
     pub fn extends(&mut self, name: &str) -> &mut Self {
         assert!(self.extends.is_none());
         self.extends = Some(self.pool.class(name));
@@ -119,6 +262,7 @@ impl ClassWriter {
             name: name.to_string(),
             jni_sig: ty.to_string(),
             attributes: Default::default(),
+            annotations: Default::default(),
         };
         self.fields.push(field);
         self.fields.last_mut().unwrap()
@@ -129,14 +273,10 @@ impl ClassWriter {
             name: name.to_string(),
             jni_sig: ty.to_string(),
             attributes: Default::default(),
+            annotations: Default::default(),
+            parameter_annotations: Vec::new(),
             code_written: false,
-            arg_stack_size: {
-                let mut count = if access.contains(MFlags::Static) { 0 } else { 1 };
-                for param in MethodSig::parse_jni(ty).unwrap().params {
-                    count += utils::type_stack_size(&param);
-                }
-                count as usize
-            },
+            class_name: self.name.clone(),
         };
         self.methods.push(method);
         self.methods.last_mut().unwrap()
@@ -149,12 +289,44 @@ impl ClassWriter {
         self
     }
 
+    /// Sets the generic signature of the class, in the format used by the `Signature` attribute.
+    pub fn signature(&mut self, signature: &str) -> &mut Self {
+        self.attributes.push(Signature::new(signature));
+        self
+    }
+
+    /// Adds an annotation to the class. Invisible annotations are stored in the class file, but
+    /// are not available through reflection.
+    pub fn annotation(&mut self, annotation: Annotation, visible: bool) -> &mut Self {
+        self.annotations.push(annotation, visible);
+        self
+    }
+
+    /// Marks the class as deprecated. This should be paired with the `@Deprecated` annotation.
+    pub fn deprecated(&mut self) -> &mut Self {
+        self.attributes.push(Deprecated);
+        self
+    }
+
+    /// Sets the host of the nest this class belongs to. This requires Java 11 or later.
+    pub fn nest_host(&mut self, name: &str) -> &mut Self {
+        assert!(self.nest_host.is_none() && self.nest_members.is_empty());
+        self.nest_host = Some(name.to_string());
+        self
+    }
+    /// Adds a member to the nest this class is the host of. This requires Java 11 or later.
+    pub fn nest_member(&mut self, name: &str) -> &mut Self {
+        assert!(self.nest_host.is_none());
+        self.nest_members.push(name.to_string());
+        self
+    }
+
     pub fn write(mut self, mut write: impl Write) -> Result<(), Error> {
         write.write_u32::<BE>(0xCAFEBABE)?;
 
-        // classfile version 52.0 (Java 8)
+        // classfile version
         write.write_u16::<BE>(0)?;
-        write.write_u16::<BE>(52)?;
+        write.write_u16::<BE>(self.major_version)?;
 
         // write classfile body
         let mut body = Cursor::new(Vec::<u8>::new());
@@ -174,7 +346,8 @@ impl ClassWriter {
         // write fields
         assert!(self.fields.len() <= u16::MAX as usize);
         body.write_u16::<BE>(self.fields.len() as u16)?;
-        for field in &self.fields {
+        for field in &mut self.fields {
+            field.annotations.write_attributes(&mut field.attributes);
             body.write_u16::<BE>(field.access.as_u16())?;
             self.pool.utf8(field.name.as_str()).write(&mut body)?;
             self.pool.utf8(field.jni_sig.as_str()).write(&mut body)?;
@@ -184,7 +357,8 @@ impl ClassWriter {
         // write methods
         assert!(self.methods.len() <= u16::MAX as usize);
         body.write_u16::<BE>(self.methods.len() as u16)?;
-        for method in &self.methods {
+        for method in &mut self.methods {
+            method.write_annotations();
             body.write_u16::<BE>(method.access.as_u16())?;
             self.pool.utf8(method.name.as_str()).write(&mut body)?;
             self.pool.utf8(method.jni_sig.as_str()).write(&mut body)?;
@@ -192,6 +366,20 @@ impl ClassWriter {
         }
 
         // write attributes
+        self.annotations.write_attributes(&mut self.attributes);
+        if self.nest_host.is_some() || !self.nest_members.is_empty() {
+            assert!(self.major_version >= 55, "Nestmates require Java 11 or later.");
+        }
+        if let Some(nest_host) = &self.nest_host {
+            self.attributes.push(NestHost::new(nest_host));
+        }
+        if !self.nest_members.is_empty() {
+            self.attributes
+                .push(NestMembers::new(std::mem::take(&mut self.nest_members)));
+        }
+        if let Some(bootstrap_methods) = self.pool.bootstrap_methods() {
+            self.attributes.push(bootstrap_methods);
+        }
         self.attributes.write(&mut self.pool, &mut body)?;
 
         // write constant pool (this is the last step because the constant pool contains so much.)
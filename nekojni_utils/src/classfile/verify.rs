@@ -0,0 +1,975 @@
+use crate::{
+    class_reader::{ClassFile, Code, ConstantPool, Instruction, MethodInfo, Operand, PoolEntry},
+    classfile::frames::{Frame, VerificationType},
+    signatures::{BasicType, ClassName, MethodSig, Type},
+    MFlags,
+};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+};
+
+/// An error found while verifying a generated class.
+#[derive(Clone, Debug)]
+pub struct VerifyError {
+    pub class: String,
+    /// The name and descriptor of the method the error was found in.
+    pub method: Option<(String, String)>,
+    /// The bytecode offset and mnemonic of the instruction the error was found at.
+    pub instruction: Option<(u32, &'static str)>,
+    /// The Rust function the method was generated for, if it is known.
+    pub origin: Option<String>,
+    pub message: String,
+}
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Generated class `{}` failed verification", self.class)?;
+        if let Some((name, desc)) = &self.method {
+            write!(f, " in method `{name}{desc}`")?;
+        }
+        if let Some(origin) = &self.origin {
+            write!(f, " (generated for `{origin}`)")?;
+        }
+        if let Some((offset, name)) = &self.instruction {
+            write!(f, " at offset {offset} (`{name}`)")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+impl std::error::Error for VerifyError {}
+
+type VResult<T> = Result<T, String>;
+/// An error found in a method, along with the offset and mnemonic of the instruction it was found
+/// at, if any.
+type MethodError = (Option<(u32, &'static str)>, String);
+
+macro_rules! fail {
+    ($($tt:tt)*) => {
+        return Err(format!($($tt)*))
+    };
+}
+
+fn check<T>(result: std::io::Result<T>) -> VResult<T> {
+    result.map_err(|e| e.to_string())
+}
+
+fn parse_field_type(desc: &str) -> VResult<Type> {
+    match Type::parse_jni(desc) {
+        Ok(ty) if ty.basic_sig != BasicType::Void => Ok(ty),
+        _ => fail!("`{desc}` is not a valid field descriptor."),
+    }
+}
+fn parse_method_sig(desc: &str) -> VResult<MethodSig> {
+    match MethodSig::parse_jni(desc) {
+        Ok(sig)
+            if sig.params.iter().all(|x| x.basic_sig != BasicType::Void)
+                && (sig.ret_ty.basic_sig != BasicType::Void || sig.ret_ty.array_dim == 0) =>
+        {
+            Ok(sig)
+        }
+        _ => fail!("`{desc}` is not a valid method descriptor."),
+    }
+}
+fn check_class_name(name: &str) -> VResult<()> {
+    if name.starts_with('[') {
+        parse_field_type(name)?;
+    } else if ClassName::parse_jni(name).is_err() {
+        fail!("`{name}` is not a valid class name.");
+    }
+    Ok(())
+}
+
+fn describe(ty: &VerificationType) -> String {
+    match ty {
+        VerificationType::Top => "an unusable value".to_string(),
+        VerificationType::Integer => "an int".to_string(),
+        VerificationType::Float => "a float".to_string(),
+        VerificationType::Long => "a long".to_string(),
+        VerificationType::Double => "a double".to_string(),
+        VerificationType::Null => "null".to_string(),
+        VerificationType::UninitializedThis => "an uninitialized `this`".to_string(),
+        VerificationType::Object(name) => format!("a reference to `{name}`"),
+        VerificationType::Uninitialized(offset) => {
+            format!("an object created at offset {offset} that is not yet initialized")
+        }
+    }
+}
+fn describe_stack(stack: &[VerificationType]) -> String {
+    stack.iter().map(describe).collect::<Vec<_>>().join(", ")
+}
+
+fn is_initialized_ref(ty: &VerificationType) -> bool {
+    matches!(ty, VerificationType::Null | VerificationType::Object(_))
+}
+fn is_ref(ty: &VerificationType) -> bool {
+    matches!(
+        ty,
+        VerificationType::Null
+            | VerificationType::Object(_)
+            | VerificationType::UninitializedThis
+            | VerificationType::Uninitialized(_)
+    )
+}
+
+/// Returns the type of values used by an instruction from the first letter of its mnemonic, or
+/// `None` for reference instructions.
+fn prefix_type(name: &str) -> Option<VerificationType> {
+    match name.as_bytes()[0] {
+        b'i' | b'b' | b'c' | b's' => Some(VerificationType::Integer),
+        b'l' => Some(VerificationType::Long),
+        b'f' => Some(VerificationType::Float),
+        b'd' => Some(VerificationType::Double),
+        _ => None,
+    }
+}
+
+fn pop(frame: &mut Frame) -> VResult<VerificationType> {
+    match frame.stack.pop() {
+        Some(ty) => Ok(ty),
+        None => fail!("Operand stack underflow."),
+    }
+}
+fn pop_expect(frame: &mut Frame, expected: &VerificationType) -> VResult<()> {
+    let ty = pop(frame)?;
+    if &ty != expected {
+        fail!("Expected {} on the operand stack, found {}.", describe(expected), describe(&ty));
+    }
+    Ok(())
+}
+fn pop_ref(frame: &mut Frame) -> VResult<VerificationType> {
+    let ty = pop(frame)?;
+    if !is_initialized_ref(&ty) {
+        fail!("Expected an object reference on the operand stack, found {}.", describe(&ty));
+    }
+    Ok(ty)
+}
+fn pop_type(frame: &mut Frame, ty: &Type) -> VResult<()> {
+    match VerificationType::from_type(ty) {
+        VerificationType::Object(_) => pop_ref(frame).map(|_| ()),
+        expected => pop_expect(frame, &expected),
+    }
+}
+fn pop_params(frame: &mut Frame, sig: &MethodSig) -> VResult<()> {
+    for param in sig.params.iter().rev() {
+        pop_type(frame, param)?;
+    }
+    Ok(())
+}
+/// Pops values totalling exactly `slots` stack slots, returning them in stack order.
+fn pop_slots(frame: &mut Frame, slots: usize) -> VResult<Vec<VerificationType>> {
+    let mut values = Vec::new();
+    let mut count = 0;
+    while count < slots {
+        let value = pop(frame)?;
+        count += value.size();
+        values.push(value);
+    }
+    if count != slots {
+        fail!("Instruction would split a long or double value on the operand stack.");
+    }
+    values.reverse();
+    Ok(values)
+}
+
+/// Returns the descriptor of the components of an array, or `None` if the array is `null`.
+fn array_component(array: &VerificationType) -> VResult<Option<&str>> {
+    match array {
+        VerificationType::Null => Ok(None),
+        VerificationType::Object(name) if name.starts_with('[') => Ok(Some(&name[1..])),
+        _ => fail!("Expected an array on the operand stack, found {}.", describe(array)),
+    }
+}
+
+struct MethodVerifier<'a> {
+    pool: &'a ConstantPool,
+    class_name: &'a str,
+    super_name: Option<&'a str>,
+    method_name: &'a str,
+    sig: MethodSig,
+    code: Code,
+    instructions: Vec<Instruction>,
+    index_of: HashMap<u32, usize>,
+    frames: Vec<Option<Frame>>,
+    queue: Vec<usize>,
+}
+impl<'a> MethodVerifier<'a> {
+    fn instruction_at(&self, offset: i64) -> VResult<usize> {
+        match self.index_of.get(&(offset as u32)) {
+            Some(index) if offset >= 0 => Ok(*index),
+            _ => fail!("Offset {offset} is not the start of an instruction."),
+        }
+    }
+
+    fn merge_into(&mut self, target: i64, frame: &Frame) -> VResult<()> {
+        let index = self.instruction_at(target)?;
+        match &self.frames[index] {
+            None => {
+                self.frames[index] = Some(frame.clone());
+                self.queue.push(index);
+            }
+            Some(existing) => match existing.merge(frame) {
+                Some(merged) => {
+                    if &merged != existing {
+                        self.frames[index] = Some(merged);
+                        self.queue.push(index);
+                    }
+                }
+                None => fail!(
+                    "The operand stack at offset {target} is inconsistent between branches: \
+                     [{}] and [{}].",
+                    describe_stack(&existing.stack),
+                    describe_stack(&frame.stack),
+                ),
+            },
+        }
+        Ok(())
+    }
+
+    fn merge_handlers(&mut self, offset: u32, locals: &[VerificationType]) -> VResult<()> {
+        for entry in self.code.exception_table.clone() {
+            if entry.start_pc as u32 <= offset && offset < entry.end_pc as u32 {
+                let class = if entry.catch_type == 0 {
+                    "java/lang/Throwable"
+                } else {
+                    check(self.pool.class_name(entry.catch_type))?
+                };
+                let frame = Frame {
+                    locals: locals.to_vec(),
+                    stack: vec![VerificationType::Object(class.to_string())],
+                };
+                self.merge_into(entry.handler_pc as i64, &frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_local(&self, id: u16, size: usize) -> VResult<()> {
+        if id as usize + size > self.code.max_locals as usize {
+            fail!(
+                "Local variable {id} is out of range, as max_locals is {}.",
+                self.code.max_locals
+            );
+        }
+        Ok(())
+    }
+    fn load(&self, frame: &mut Frame, id: u16, expected: Option<VerificationType>) -> VResult<()> {
+        let ty = frame.local(id);
+        match expected {
+            Some(expected) => {
+                self.check_local(id, expected.size())?;
+                if ty != expected {
+                    fail!(
+                        "Expected {} in local variable {id}, found {}.",
+                        describe(&expected),
+                        describe(&ty)
+                    );
+                }
+            }
+            None => {
+                self.check_local(id, 1)?;
+                if !is_ref(&ty) {
+                    fail!("Expected a reference in local variable {id}, found {}.", describe(&ty));
+                }
+            }
+        }
+        frame.push(ty);
+        Ok(())
+    }
+    fn store(&self, frame: &mut Frame, id: u16, expected: Option<VerificationType>) -> VResult<()> {
+        let ty = match expected {
+            Some(expected) => {
+                pop_expect(frame, &expected)?;
+                expected
+            }
+            None => {
+                let ty = pop(frame)?;
+                if !is_ref(&ty) {
+                    fail!("Expected a reference on the operand stack, found {}.", describe(&ty));
+                }
+                ty
+            }
+        };
+        self.check_local(id, ty.size())?;
+        frame.set_local(id, ty);
+        Ok(())
+    }
+
+    fn constant_type(&self, index: u16, wide: bool) -> VResult<VerificationType> {
+        let object = |name: &str| VerificationType::Object(name.to_string());
+        let ty = match check(self.pool.get(index))? {
+            PoolEntry::Integer(_) => VerificationType::Integer,
+            PoolEntry::Float(_) => VerificationType::Float,
+            PoolEntry::Long(_) => VerificationType::Long,
+            PoolEntry::Double(_) => VerificationType::Double,
+            PoolEntry::String(_) => object("java/lang/String"),
+            PoolEntry::Class(_) => object("java/lang/Class"),
+            PoolEntry::MethodType { .. } => object("java/lang/invoke/MethodType"),
+            PoolEntry::MethodHandle { .. } => object("java/lang/invoke/MethodHandle"),
+            PoolEntry::Dynamic { name_and_type_index, .. } => {
+                let (_, desc) = check(self.pool.name_and_type(*name_and_type_index))?;
+                VerificationType::from_type(&parse_field_type(desc)?)
+            }
+            entry => fail!("Constant pool entry {index} ({entry:?}) cannot be loaded."),
+        };
+        if wide != (ty.size() == 2) {
+            fail!("Constant pool entry {index} has the wrong size for this instruction.");
+        }
+        Ok(ty)
+    }
+
+    fn field(&self, index: u16) -> VResult<(&'a str, &'a str, Type)> {
+        if !matches!(check(self.pool.get(index))?, PoolEntry::FieldRef { .. }) {
+            fail!("Constant pool entry {index} is not a field reference.");
+        }
+        let (owner, name, desc) = check(self.pool.member_ref(index))?;
+        Ok((owner, name, parse_field_type(desc)?))
+    }
+
+    fn invoke(&self, frame: &mut Frame, insn: &Instruction) -> VResult<()> {
+        let name = insn.name();
+        let index = match insn.operand {
+            Operand::Pool(index) => index,
+            Operand::InvokeInterface { method, .. } => method,
+            _ => unreachable!(),
+        };
+        let entry = check(self.pool.get(index))?;
+        let kind_ok = match name {
+            "invokevirtual" => matches!(entry, PoolEntry::MethodRef { .. }),
+            "invokeinterface" => matches!(entry, PoolEntry::InterfaceMethodRef { .. }),
+            _ => {
+                matches!(entry, PoolEntry::MethodRef { .. } | PoolEntry::InterfaceMethodRef { .. })
+            }
+        };
+        if !kind_ok {
+            fail!("Constant pool entry {index} is not a valid method reference for `{name}`.");
+        }
+
+        let (owner, method_name, desc) = check(self.pool.member_ref(index))?;
+        let sig = parse_method_sig(desc)?;
+        if method_name == "<clinit>" || (method_name == "<init>" && name != "invokespecial") {
+            fail!("`{method_name}` cannot be called with `{name}`.");
+        }
+        if let Operand::InvokeInterface { count, .. } = insn.operand {
+            let slots: usize = sig
+                .params
+                .iter()
+                .map(|x| VerificationType::from_type(x).size())
+                .sum();
+            if count as usize != slots + 1 {
+                fail!("The argument count is {count}, but the method takes {} slots.", slots + 1);
+            }
+        }
+
+        pop_params(frame, &sig)?;
+        if name != "invokestatic" {
+            if method_name == "<init>" {
+                if sig.ret_ty != Type::Void {
+                    fail!("Constructors must return void.");
+                }
+                let receiver = pop(frame)?;
+                let initialized = match &receiver {
+                    VerificationType::UninitializedThis => {
+                        if owner != self.class_name && Some(owner) != self.super_name {
+                            fail!(
+                                "`this` must be initialized by a constructor of `{}` or its \
+                                 superclass, not `{owner}`.",
+                                self.class_name,
+                            );
+                        }
+                        self.class_name
+                    }
+                    VerificationType::Uninitialized(offset) => {
+                        let created = &self.instructions[self.instruction_at(*offset as i64)?];
+                        let created = match created.operand {
+                            Operand::Pool(index) if created.name() == "new" => {
+                                check(self.pool.class_name(index))?
+                            }
+                            _ => fail!("The instruction at offset {offset} is not `new`."),
+                        };
+                        if created != owner {
+                            fail!("Constructor of `{owner}` called on an instance of `{created}`.");
+                        }
+                        created
+                    }
+                    ty => fail!("Constructor called on {}.", describe(ty)),
+                };
+                frame.replace(&receiver, &VerificationType::Object(initialized.to_string()));
+            } else {
+                pop_ref(frame)?;
+            }
+        }
+        frame.push_type(&sig.ret_ty);
+        Ok(())
+    }
+
+    fn do_return(&self, frame: &mut Frame, name: &str) -> VResult<()> {
+        let ret_ty = &self.sig.ret_ty;
+        if name == "return" {
+            if *ret_ty != Type::Void {
+                fail!("`return` used in a method returning `{}`.", ret_ty.display_jni());
+            }
+        } else {
+            if *ret_ty == Type::Void {
+                fail!("`{name}` used in a void method.");
+            }
+            let expected = VerificationType::from_type(ret_ty);
+            let matches = match prefix_type(name) {
+                Some(ty) => ty == expected,
+                None => matches!(expected, VerificationType::Object(_)),
+            };
+            if !matches {
+                fail!("`{name}` used in a method returning `{}`.", ret_ty.display_jni());
+            }
+            pop_type(frame, ret_ty)?;
+        }
+        if self.method_name == "<init>"
+            && frame.locals.contains(&VerificationType::UninitializedThis)
+        {
+            fail!("Constructor returns before `this` is initialized.");
+        }
+        Ok(())
+    }
+
+    /// Simulates a single instruction, returning its branch targets and whether execution can
+    /// continue to the next instruction.
+    fn step(&self, frame: &mut Frame, insn: &Instruction) -> VResult<(Vec<i64>, bool)> {
+        use VerificationType as VT;
+
+        let name = insn.name();
+        let local = match insn.operand {
+            Operand::Local(id) => Some(id),
+            _ => name
+                .split_once('_')
+                .and_then(|(_, id)| id.parse::<u16>().ok()),
+        };
+        let pool_index = match insn.operand {
+            Operand::Pool(index) => index,
+            _ => 0,
+        };
+
+        let mut targets = Vec::new();
+        let mut falls_through = true;
+        match name {
+            "nop" => {}
+            "aconst_null" => frame.push(VT::Null),
+            "bipush" | "sipush" => frame.push(VT::Integer),
+            _ if name.len() > 6 && name[1..].starts_with("const_") => {
+                frame.push(prefix_type(name).unwrap())
+            }
+            "ldc" | "ldc_w" => frame.push(self.constant_type(pool_index, false)?),
+            "ldc2_w" => frame.push(self.constant_type(pool_index, true)?),
+
+            _ if name[1..].starts_with("load") => {
+                self.load(frame, local.unwrap(), prefix_type(name))?
+            }
+            _ if name[1..].starts_with("store") => {
+                self.store(frame, local.unwrap(), prefix_type(name))?
+            }
+            "iaload" | "laload" | "faload" | "daload" | "aaload" | "baload" | "caload"
+            | "saload" => {
+                pop_expect(frame, &VT::Integer)?;
+                let array = pop(frame)?;
+                let component = array_component(&array)?;
+                let ty = match component {
+                    Some(component) => {
+                        check_array_op(name, component)?;
+                        VT::from_type(&parse_field_type(component)?)
+                    }
+                    None if name == "aaload" => VT::Null,
+                    None => prefix_type(name).unwrap(),
+                };
+                frame.push(ty);
+            }
+            "iastore" | "lastore" | "fastore" | "dastore" | "aastore" | "bastore" | "castore"
+            | "sastore" => {
+                match prefix_type(name) {
+                    Some(ty) => pop_expect(frame, &ty)?,
+                    None => pop_ref(frame).map(|_| ())?,
+                }
+                pop_expect(frame, &VT::Integer)?;
+                let array = pop(frame)?;
+                if let Some(component) = array_component(&array)? {
+                    check_array_op(name, component)?;
+                }
+            }
+
+            "pop" => {
+                pop_slots(frame, 1)?;
+            }
+            "pop2" => {
+                pop_slots(frame, 2)?;
+            }
+            "dup" | "dup_x1" | "dup_x2" | "dup2" | "dup2_x1" | "dup2_x2" | "swap" => {
+                let (top, below) = match name {
+                    "dup" => (1, 0),
+                    "dup_x1" => (1, 1),
+                    "dup_x2" => (1, 2),
+                    "dup2" => (2, 0),
+                    "dup2_x1" => (2, 1),
+                    "dup2_x2" => (2, 2),
+                    _ => (1, 1),
+                };
+                let top_values = pop_slots(frame, top)?;
+                let below_values = pop_slots(frame, below)?;
+                if name == "swap" {
+                    frame.stack.extend(top_values);
+                    frame.stack.extend(below_values);
+                } else {
+                    frame.stack.extend(top_values.iter().cloned());
+                    frame.stack.extend(below_values);
+                    frame.stack.extend(top_values);
+                }
+            }
+
+            _ if ["add", "sub", "mul", "div", "rem", "and", "or", "xor"].contains(&&name[1..]) => {
+                let ty = prefix_type(name).unwrap();
+                pop_expect(frame, &ty)?;
+                pop_expect(frame, &ty)?;
+                frame.push(ty);
+            }
+            _ if ["shl", "shr", "ushr"].contains(&&name[1..]) => {
+                let ty = prefix_type(name).unwrap();
+                pop_expect(frame, &VT::Integer)?;
+                pop_expect(frame, &ty)?;
+                frame.push(ty);
+            }
+            _ if &name[1..] == "neg" => {
+                let ty = prefix_type(name).unwrap();
+                pop_expect(frame, &ty)?;
+                frame.push(ty);
+            }
+            _ if name.len() == 3 && &name[1..2] == "2" => {
+                pop_expect(frame, &prefix_type(name).unwrap())?;
+                frame.push(prefix_type(&name[2..]).unwrap());
+            }
+            "iinc" => match insn.operand {
+                Operand::Iinc { local, .. } => {
+                    self.check_local(local, 1)?;
+                    if frame.local(local) != VT::Integer {
+                        fail!(
+                            "Expected an int in local variable {local}, found {}.",
+                            describe(&frame.local(local)),
+                        );
+                    }
+                }
+                _ => unreachable!(),
+            },
+            "lcmp" | "fcmpl" | "fcmpg" | "dcmpl" | "dcmpg" => {
+                let ty = prefix_type(name).unwrap();
+                pop_expect(frame, &ty)?;
+                pop_expect(frame, &ty)?;
+                frame.push(VT::Integer);
+            }
+
+            "ifeq" | "ifne" | "iflt" | "ifge" | "ifgt" | "ifle" | "if_icmpeq" | "if_icmpne"
+            | "if_icmplt" | "if_icmpge" | "if_icmpgt" | "if_icmple" | "if_acmpeq" | "if_acmpne"
+            | "ifnull" | "ifnonnull" | "goto" | "goto_w" => {
+                let count = if name.starts_with("if_") { 2 } else { 1 };
+                if name.starts_with("goto") {
+                    falls_through = false;
+                } else if name.starts_with("if_a") || name == "ifnull" || name == "ifnonnull" {
+                    for _ in 0..count {
+                        let ty = pop(frame)?;
+                        if !is_ref(&ty) {
+                            fail!(
+                                "Expected a reference on the operand stack, found {}.",
+                                describe(&ty)
+                            );
+                        }
+                    }
+                } else {
+                    for _ in 0..count {
+                        pop_expect(frame, &VT::Integer)?;
+                    }
+                }
+                match insn.operand {
+                    Operand::Branch(target) => targets.push(target),
+                    _ => unreachable!(),
+                }
+            }
+            "tableswitch" | "lookupswitch" => {
+                pop_expect(frame, &VT::Integer)?;
+                match &insn.operand {
+                    Operand::TableSwitch { default, targets: switch_targets, .. } => {
+                        targets.push(*default);
+                        targets.extend(switch_targets);
+                    }
+                    Operand::LookupSwitch { default, pairs } => {
+                        if pairs.windows(2).any(|x| x[0].0 >= x[1].0) {
+                            fail!("The keys of `lookupswitch` are not sorted.");
+                        }
+                        targets.push(*default);
+                        targets.extend(pairs.iter().map(|x| x.1));
+                    }
+                    _ => unreachable!(),
+                }
+                falls_through = false;
+            }
+            "jsr" | "jsr_w" | "ret" => fail!("`{name}` is not allowed in Java 7 or later classes."),
+            "ireturn" | "lreturn" | "freturn" | "dreturn" | "areturn" | "return" => {
+                self.do_return(frame, name)?;
+                falls_through = false;
+            }
+
+            "getstatic" => frame.push_type(&self.field(pool_index)?.2),
+            "putstatic" => pop_type(frame, &self.field(pool_index)?.2)?,
+            "getfield" => {
+                let (_, _, ty) = self.field(pool_index)?;
+                pop_ref(frame)?;
+                frame.push_type(&ty);
+            }
+            "putfield" => {
+                let (owner, _, ty) = self.field(pool_index)?;
+                pop_type(frame, &ty)?;
+                let receiver = pop(frame)?;
+                let this_uninit = receiver == VT::UninitializedThis && owner == self.class_name;
+                if !this_uninit && !is_initialized_ref(&receiver) {
+                    fail!(
+                        "Expected an object reference on the operand stack, found {}.",
+                        describe(&receiver)
+                    );
+                }
+            }
+            "invokevirtual" | "invokespecial" | "invokestatic" | "invokeinterface" => {
+                self.invoke(frame, insn)?
+            }
+            "invokedynamic" => {
+                let name_and_type_index = match check(self.pool.get(pool_index))? {
+                    PoolEntry::InvokeDynamic { name_and_type_index, .. } => *name_and_type_index,
+                    _ => fail!("Constant pool entry {pool_index} is not an InvokeDynamic entry."),
+                };
+                let (_, desc) = check(self.pool.name_and_type(name_and_type_index))?;
+                let sig = parse_method_sig(desc)?;
+                pop_params(frame, &sig)?;
+                frame.push_type(&sig.ret_ty);
+            }
+
+            "new" => {
+                let class = check(self.pool.class_name(pool_index))?;
+                if class.starts_with('[') {
+                    fail!("`new` cannot create an array.");
+                }
+                frame.push(VT::Uninitialized(insn.offset as usize));
+            }
+            "newarray" => {
+                let component = match insn.operand {
+                    Operand::NewArray { atype: 4 } => "Z",
+                    Operand::NewArray { atype: 5 } => "C",
+                    Operand::NewArray { atype: 6 } => "F",
+                    Operand::NewArray { atype: 7 } => "D",
+                    Operand::NewArray { atype: 8 } => "B",
+                    Operand::NewArray { atype: 9 } => "S",
+                    Operand::NewArray { atype: 10 } => "I",
+                    Operand::NewArray { atype: 11 } => "J",
+                    _ => fail!("Invalid array type for `newarray`."),
+                };
+                pop_expect(frame, &VT::Integer)?;
+                frame.push(VT::Object(format!("[{component}")));
+            }
+            "anewarray" => {
+                let class = check(self.pool.class_name(pool_index))?;
+                pop_expect(frame, &VT::Integer)?;
+                if class.starts_with('[') {
+                    frame.push(VT::Object(format!("[{class}")));
+                } else {
+                    frame.push(VT::Object(format!("[L{class};")));
+                }
+            }
+            "multianewarray" => match insn.operand {
+                Operand::MultiANewArray { class, dims } => {
+                    let class = check(self.pool.class_name(class))?;
+                    let class_dims = class.chars().take_while(|x| *x == '[').count();
+                    if dims == 0 || dims as usize > class_dims {
+                        fail!("`{class}` cannot be created with {dims} dimensions.");
+                    }
+                    for _ in 0..dims {
+                        pop_expect(frame, &VT::Integer)?;
+                    }
+                    frame.push(VT::Object(class.to_string()));
+                }
+                _ => unreachable!(),
+            },
+            "arraylength" => {
+                let array = pop(frame)?;
+                array_component(&array)?;
+                frame.push(VT::Integer);
+            }
+            "athrow" => {
+                pop_ref(frame)?;
+                falls_through = false;
+            }
+            "checkcast" => {
+                let class = check(self.pool.class_name(pool_index))?;
+                pop_ref(frame)?;
+                frame.push(VT::Object(class.to_string()));
+            }
+            "instanceof" => {
+                check(self.pool.class_name(pool_index))?;
+                pop_ref(frame)?;
+                frame.push(VT::Integer);
+            }
+            "monitorenter" | "monitorexit" => {
+                pop_ref(frame)?;
+            }
+            _ => fail!("`{name}` is a reserved opcode."),
+        }
+
+        if frame.stack_size() > self.code.max_stack as usize {
+            fail!(
+                "The operand stack holds {} slots, but max_stack is {}.",
+                frame.stack_size(),
+                self.code.max_stack,
+            );
+        }
+        Ok((targets, falls_through))
+    }
+
+    fn verify(mut self, initial: Frame) -> Result<(), MethodError> {
+        let no_insn = |e| (None, e);
+
+        for entry in &self.code.exception_table {
+            let code_len = self.code.code.len() as i64;
+            self.instruction_at(entry.start_pc as i64)
+                .map_err(no_insn)?;
+            if entry.end_pc as i64 != code_len {
+                self.instruction_at(entry.end_pc as i64).map_err(no_insn)?;
+            }
+            self.instruction_at(entry.handler_pc as i64)
+                .map_err(no_insn)?;
+            if entry.end_pc <= entry.start_pc {
+                return Err((None, "Exception table entry has an empty range.".to_string()));
+            }
+            if entry.catch_type != 0 {
+                check(self.pool.class_name(entry.catch_type)).map_err(no_insn)?;
+            }
+        }
+
+        self.merge_into(0, &initial).map_err(no_insn)?;
+        while let Some(index) = self.queue.pop() {
+            let insn = self.instructions[index].clone();
+            let insn_err = |e| (Some((insn.offset, insn.name())), e);
+
+            let mut frame = self.frames[index].clone().unwrap();
+            self.merge_handlers(insn.offset, &frame.locals)
+                .map_err(insn_err)?;
+            let (targets, falls_through) = self.step(&mut frame, &insn).map_err(insn_err)?;
+            self.merge_handlers(insn.offset, &frame.locals)
+                .map_err(insn_err)?;
+            for target in targets {
+                self.merge_into(target, &frame).map_err(insn_err)?;
+            }
+            if falls_through {
+                match self.instructions.get(index + 1) {
+                    Some(next) => {
+                        let offset = next.offset as i64;
+                        self.merge_into(offset, &frame).map_err(insn_err)?;
+                    }
+                    None => {
+                        return Err(insn_err("Execution falls off the end of the code.".into()))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that an array load or store instruction matches the type of the array's components.
+fn check_array_op(name: &str, component: &str) -> VResult<()> {
+    let ok = match name.as_bytes()[0] {
+        b'a' => component.starts_with('L') || component.starts_with('['),
+        b'b' => component == "B" || component == "Z",
+        b'c' => component == "C",
+        b's' => component == "S",
+        b'i' => component == "I",
+        b'l' => component == "J",
+        b'f' => component == "F",
+        b'd' => component == "D",
+        _ => unreachable!(),
+    };
+    if !ok {
+        fail!("`{name}` cannot be used with an array of `{component}`.");
+    }
+    Ok(())
+}
+
+fn verify_pool_entry(pool: &ConstantPool, index: u16, entry: &PoolEntry) -> VResult<()> {
+    match entry {
+        PoolEntry::Class(name) => check_class_name(check(pool.utf8(*name))?)?,
+        PoolEntry::String(str) => {
+            check(pool.utf8(*str))?;
+        }
+        PoolEntry::FieldRef { .. } => {
+            let (class, _, desc) = check(pool.member_ref(index))?;
+            check_class_name(class)?;
+            parse_field_type(desc)?;
+        }
+        PoolEntry::MethodRef { .. } | PoolEntry::InterfaceMethodRef { .. } => {
+            let (class, _, desc) = check(pool.member_ref(index))?;
+            check_class_name(class)?;
+            parse_method_sig(desc)?;
+        }
+        PoolEntry::NameAndType { .. } => {
+            check(pool.name_and_type(index))?;
+        }
+        PoolEntry::MethodHandle { reference_kind, reference_index } => {
+            let target = check(pool.get(*reference_index))?;
+            let ok = match reference_kind {
+                1..=4 => matches!(target, PoolEntry::FieldRef { .. }),
+                5 | 8 => matches!(target, PoolEntry::MethodRef { .. }),
+                6 | 7 => matches!(
+                    target,
+                    PoolEntry::MethodRef { .. } | PoolEntry::InterfaceMethodRef { .. }
+                ),
+                9 => matches!(target, PoolEntry::InterfaceMethodRef { .. }),
+                _ => fail!("Invalid method handle kind {reference_kind}."),
+            };
+            if !ok {
+                fail!("Method handle of kind {reference_kind} refers to {target:?}.");
+            }
+            check(pool.member_ref(*reference_index))?;
+        }
+        PoolEntry::MethodType { descriptor_index } => {
+            parse_method_sig(check(pool.utf8(*descriptor_index))?)?;
+        }
+        PoolEntry::Dynamic { name_and_type_index, .. } => {
+            parse_field_type(check(pool.name_and_type(*name_and_type_index))?.1)?;
+        }
+        PoolEntry::InvokeDynamic { name_and_type_index, .. } => {
+            parse_method_sig(check(pool.name_and_type(*name_and_type_index))?.1)?;
+        }
+        PoolEntry::Module { name_index } | PoolEntry::Package { name_index } => {
+            check(pool.utf8(*name_index))?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn verify_header(class: &ClassFile, name: &str) -> VResult<()> {
+    let pool = &class.constant_pool;
+    for (index, entry) in pool.entries() {
+        if let Err(e) = verify_pool_entry(pool, index, entry) {
+            fail!("Constant pool entry {index} is invalid: {e}");
+        }
+    }
+
+    if check(class.name())? != name {
+        fail!("Class file contains `{}`.", check(class.name())?);
+    }
+    if class.super_class == 0 && name != "java/lang/Object" {
+        fail!("Class has no superclass.");
+    }
+    check(class.super_name())?;
+    check(class.interface_names())?;
+    for field in &class.fields {
+        let field_name = check(field.name(pool))?;
+        if let Err(e) = parse_field_type(check(field.descriptor(pool))?) {
+            fail!("Field `{field_name}` is invalid: {e}");
+        }
+    }
+    Ok(())
+}
+
+fn verify_method(class: &ClassFile, method: &MethodInfo) -> Result<(), MethodError> {
+    let no_insn = |e| (None, e);
+    let pool = &class.constant_pool;
+    let class_name = check(class.name()).map_err(no_insn)?;
+    let name = check(method.name(pool)).map_err(no_insn)?;
+    let sig =
+        parse_method_sig(check(method.descriptor(pool)).map_err(no_insn)?).map_err(no_insn)?;
+    let flags = method.flags();
+    let is_static = flags.contains(MFlags::Static);
+
+    if name == "<init>" && (is_static || sig.ret_ty != Type::Void) {
+        return Err((None, "Constructors must not be static, and must return void.".into()));
+    }
+    if name == "<clinit>" && !is_static {
+        return Err((None, "Static initializers must be static.".into()));
+    }
+
+    let code = check(method.code(pool)).map_err(no_insn)?;
+    let code = match code {
+        Some(code) => code,
+        None if flags.contains(MFlags::Native) || flags.contains(MFlags::Abstract) => return Ok(()),
+        None => return Err((None, "Method has no `Code` attribute.".into())),
+    };
+    if flags.contains(MFlags::Native) || flags.contains(MFlags::Abstract) {
+        return Err((None, "Native and abstract methods must not have code.".into()));
+    }
+    if code.code.is_empty() || code.code.len() > u16::MAX as usize {
+        return Err((None, format!("Method has an invalid code length of {}.", code.code.len())));
+    }
+
+    // set up the initial frame from the method descriptor
+    let mut initial = Frame::default();
+    let mut local = 0;
+    if !is_static {
+        if name == "<init>" && class_name != "java/lang/Object" {
+            initial.set_local(0, VerificationType::UninitializedThis);
+        } else {
+            initial.set_local(0, VerificationType::Object(class_name.to_string()));
+        }
+        local += 1;
+    }
+    for param in &sig.params {
+        let ty = VerificationType::from_type(param);
+        let size = ty.size();
+        initial.set_local(local, ty);
+        local += size as u16;
+    }
+    if local > code.max_locals {
+        return Err((
+            None,
+            format!("Parameters use {local} local slots, but max_locals is {}.", code.max_locals),
+        ));
+    }
+
+    let instructions = check(code.instructions()).map_err(no_insn)?;
+    let index_of = instructions
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (x.offset, i))
+        .collect();
+    let verifier = MethodVerifier {
+        pool,
+        class_name,
+        super_name: check(class.super_name()).map_err(no_insn)?,
+        method_name: name,
+        sig,
+        code,
+        frames: vec![None; instructions.len()],
+        instructions,
+        index_of,
+        queue: Vec::new(),
+    };
+    verifier.verify(initial)
+}
+
+/// Checks the structure of a class file, and the type safety of the code in its methods.
+///
+/// As the class hierarchy is not available, object types are only checked to be references.
+pub fn verify_class(name: &str, data: &[u8]) -> Result<(), Box<VerifyError>> {
+    let error = |method: Option<(String, String)>, instruction, message| {
+        Box::new(VerifyError {
+            class: name.to_string(),
+            method,
+            instruction,
+            origin: None,
+            message,
+        })
+    };
+
+    let class = ClassFile::parse(data).map_err(|e| error(None, None, e.to_string()))?;
+    verify_header(&class, name).map_err(|e| error(None, None, e))?;
+
+    let pool = &class.constant_pool;
+    for method in &class.methods {
+        if let Err((instruction, message)) = verify_method(&class, method) {
+            let method_name = method.name(pool).unwrap_or("<invalid>").to_string();
+            let desc = method.descriptor(pool).unwrap_or("<invalid>").to_string();
+            return Err(error(Some((method_name, desc)), instruction, message));
+        }
+    }
+    Ok(())
+}
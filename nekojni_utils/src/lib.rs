@@ -2,8 +2,10 @@
 
 extern crate core;
 
+mod annotations;
 #[cfg(feature = "classfile")]
 mod class_data;
+pub mod class_reader;
 #[cfg(feature = "classfile")]
 mod classfile;
 pub mod constcat;
@@ -16,11 +18,16 @@ mod native_class_wrapper;
 #[cfg(feature = "signature")]
 pub mod signatures;
 
+pub use annotations::AnnotationList;
 #[cfg(feature = "classfile")]
 pub use class_data::*;
+#[cfg(feature = "classfile")]
+pub use classfile::{
+    verify_class, ClassWriter, Constant, Handle, HandleKind, MethodWriter, VerifyError,
+};
 pub use flags::{CFlags, FFlags, MFlags};
 pub use generate_precompiled::generate_shutdown_hook;
 #[cfg(feature = "hash")]
 pub use hash_util::Hasher;
 #[cfg(feature = "classfile")]
-pub use native_class_wrapper::NativeClassWrapper;
+pub use native_class_wrapper::{ExportedMethodMeta, NativeClassWrapper};
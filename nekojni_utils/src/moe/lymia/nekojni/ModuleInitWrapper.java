@@ -1,5 +1,10 @@
 package moe.lymia.nekojni;
 
+import java.util.Collections;
+import java.util.LinkedHashMap;
+import java.util.Map;
+import java.util.TreeMap;
+
 public final class ModuleInitWrapper {
     private ModuleInitWrapper() {}
 
@@ -30,4 +35,30 @@ public final class ModuleInitWrapper {
             checkInit();
         }
     }
+
+    private static native String nativeObjectStats();
+
+    /**
+     * Returns statistics about the native objects owned by each class exported by this library.
+     *
+     * The returned map is keyed by class name, and each entry contains the number of objects that
+     * are currently allocated ("live"), the largest number of objects allocated at once
+     * ("highWaterMark"), and the total number of objects allocated and freed ("allocations" and
+     * "frees").
+     */
+    public static Map<String, Map<String, Long>> objectStats() {
+        init();
+        Map<String, Map<String, Long>> stats = new TreeMap<>();
+        for (String line : nativeObjectStats().split("\n")) {
+            if (line.isEmpty()) continue;
+            String[] fields = line.split(" ");
+            Map<String, Long> classStats = new LinkedHashMap<>();
+            classStats.put("live", Long.parseLong(fields[1]));
+            classStats.put("highWaterMark", Long.parseLong(fields[2]));
+            classStats.put("allocations", Long.parseLong(fields[3]));
+            classStats.put("frees", Long.parseLong(fields[4]));
+            stats.put(fields[0], Collections.unmodifiableMap(classStats));
+        }
+        return Collections.unmodifiableMap(stats);
+    }
 }
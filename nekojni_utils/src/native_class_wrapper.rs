@@ -1,31 +1,64 @@
 use crate::{
     classfile::{
         utils::{push_param, return_param},
-        ClassWriter,
+        ClassWriter, MethodData, MethodWriter,
     },
-    signatures::{BasicType, ClassName, MethodSig, Type},
-    CFlags, ClassData, FFlags, MFlags,
+    signatures::{
+        Annotation, BasicType, ClassName, GenericClassSig, GenericClassType, GenericMethodSig,
+        GenericType, MethodSig, Type,
+    },
+    AnnotationList, CFlags, ClassData, FFlags, JavaVersion, MFlags, VerifyError,
 };
 use enumset::EnumSet;
 use std::collections::HashMap;
 
+/// Describes an exported method to Java code, and to error messages about its generated code.
+#[derive(Copy, Clone, Debug)]
+pub struct ExportedMethodMeta<'a> {
+    pub access: EnumSet<MFlags>,
+    /// The generic signature of the method. This is the same as its descriptor if it has none.
+    pub generic_sig: &'a str,
+    /// The Rust function the method was generated for.
+    pub rust_name: &'a str,
+    /// The line of the Rust function in its source file, or 0 if it is not known.
+    pub line: u32,
+    pub param_names: &'a [&'a str],
+    pub annotations: &'a AnnotationList<'a>,
+    pub param_annotations: &'a [AnnotationList<'a>],
+}
+
 pub struct NativeClassWrapper {
     name: String,
     class: ClassWriter,
     extends: String,
     id_param: String,
+    interfaces: Vec<String>,
+    signature: Option<GenericClassSig>,
     supporting: HashMap<String, Vec<u8>>,
+    nest_members: Vec<String>,
+    origins: Vec<(String, String, String, String)>,
+    errors: Vec<VerifyError>,
+    uses_deprecation_note: bool,
     constructor_generated: bool,
+    cleaner_holder: Option<String>,
+    target_version: JavaVersion,
 }
 impl NativeClassWrapper {
-    pub fn new(access: EnumSet<CFlags>, name: &str, extends: &str, id_param: &str) -> Self {
+    pub fn new(
+        access: EnumSet<CFlags>,
+        name: &str,
+        extends: &str,
+        id_param: &str,
+        target_version: JavaVersion,
+    ) -> Self {
         let mut class = ClassWriter::new(access, name);
 
+        class.version(target_version.major_version());
         class.extends(extends);
         class.field(
             FFlags::Private | FFlags::Synthetic | FFlags::Final | FFlags::Transient,
             id_param,
-            "I",
+            "J",
         );
 
         NativeClassWrapper {
@@ -33,16 +66,57 @@ impl NativeClassWrapper {
             class,
             extends: extends.to_string(),
             id_param: id_param.to_string(),
+            interfaces: Vec::new(),
+            signature: None,
             supporting: HashMap::new(),
+            nest_members: Vec::new(),
+            origins: Vec::new(),
+            errors: Vec::new(),
+            uses_deprecation_note: false,
             constructor_generated: false,
+            cleaner_holder: None,
+            target_version,
         }
     }
     pub fn implements(&mut self, implement: &str) {
         self.class.implements(implement);
+        self.interfaces.push(implement.to_string());
+    }
+    /// Sets the generic signature of the class. Interfaces implemented by the class that are not
+    /// part of the signature are added to it without type arguments.
+    ///
+    /// An invalid signature is reported by [`ClassData::verify`].
+    pub fn generic_signature(&mut self, signature: &str) {
+        let message = match GenericClassSig::parse_generic(signature) {
+            Err(_) => format!("`{signature}` is not a valid generic class signature."),
+            Ok(parsed) if !parsed.type_params.is_empty() => {
+                format!(
+                    "`{signature}` declares type parameters, which exported classes cannot have."
+                )
+            }
+            Ok(parsed) if parsed.super_class.erase().display_jni().to_string() != self.extends => {
+                format!("`{signature}` does not extend `{}`.", self.extends)
+            }
+            Ok(parsed) => {
+                self.signature = Some(parsed);
+                return;
+            }
+        };
+        self.errors
+            .push(signature_error(&self.name, None, None, message));
     }
     pub fn source_file(&mut self, source_file: &str) {
         self.class.source_file(source_file);
     }
+    pub fn annotate(&mut self, annotations: &AnnotationList) {
+        let uses_note = &mut self.uses_deprecation_note;
+        let deprecated = add_annotations(annotations, uses_note, |annotation, visible| {
+            self.class.annotation(annotation, visible);
+        });
+        if deprecated {
+            self.class.deprecated();
+        }
+    }
 
     pub fn generate_init(&mut self, init_class: &str, static_init: &[&str]) {
         let method = self.class.method(MFlags::Static.into(), "<clinit>", "()V");
@@ -60,14 +134,16 @@ impl NativeClassWrapper {
 
     pub fn export_constructor(
         &mut self,
-        access: EnumSet<MFlags>,
         sig_str: &str,
         native_name: &str,
         native_sig_str: &str,
         super_sig_str: &str,
         instance_init: &[&'static str],
+        meta: &ExportedMethodMeta,
     ) {
         self.constructor_generated = true;
+        self.origins
+            .push(method_origin(&self.name, "<init>", sig_str, meta.rust_name));
 
         // parse the signatures passed in
         let sig = MethodSig::parse_jni(sig_str).unwrap();
@@ -76,7 +152,7 @@ impl NativeClassWrapper {
 
         // parse out the intermediate type.
         let intermediate_name = match &native_sig.ret_ty {
-            Type { basic_sig: BasicType::Int, .. } => None,
+            Type { basic_sig: BasicType::Long, .. } => None,
             Type { basic_sig: BasicType::Class(name), .. } => {
                 let name_parsed = ClassName::parse_jni(&self.name).unwrap();
                 assert_eq!(name_parsed.package, name.package);
@@ -90,8 +166,14 @@ impl NativeClassWrapper {
         assert_eq!(super_sig.ret_ty, Type::Void);
         assert_eq!(sig.params, native_sig.params);
 
-        let method = self.class.method(access, "<init>", sig_str);
+        let method = self.class.method(meta.access, "<init>", sig_str);
+        if let Err(message) = method_meta(method, sig_str, meta, &mut self.uses_deprecation_note) {
+            let method = Some(("<init>", sig_str));
+            self.errors
+                .push(signature_error(&self.name, method, Some(meta.rust_name), message));
+        }
         let mut code = method.code();
+        source_line(&mut code, meta.line);
 
         // call the actual native initialization function
         let mut param_id = 1;
@@ -105,32 +187,43 @@ impl NativeClassWrapper {
         match intermediate_name {
             None => {
                 // just call the superclass' default constructor
-                code.istore(var_native_ret)
+                code.lstore(var_native_ret)
                     .aload(0)
                     .invokespecial(&self.extends, "<init>", "()V")
                     .aload(0)
-                    .iload(var_native_ret)
-                    .putfield(&self.name, &self.id_param, "I");
+                    .lload(var_native_ret)
+                    .putfield(&self.name, &self.id_param, "J");
             }
             Some(support_name) => {
                 // create the parameters of the supporting class constructor
                 let mut vec = Vec::new();
-                vec.push(Type::Int);
+                vec.push(Type::Long);
                 for param in super_sig.params.as_slice() {
                     vec.push(param.clone());
                 }
                 let support_ctor_sig = MethodSig::new(Type::Void, vec).display_jni().to_string();
+                self.origins.push(method_origin(
+                    &support_name,
+                    "<init>",
+                    &support_ctor_sig,
+                    meta.rust_name,
+                ));
 
                 // generate a supporting class
                 {
-                    let mut supporting =
-                        ClassWriter::new(CFlags::Final | CFlags::Synthetic, &support_name);
+                    let mut supporting = supporting_class(
+                        &self.name,
+                        self.target_version,
+                        &mut self.nest_members,
+                        &support_name,
+                    );
+                    let (field_access, method_access) = nest_private(self.target_version);
 
                     // creating a constructor
                     {
                         // create the initalizer method itself
-                        let mut method = supporting.method(
-                            MFlags::Synthetic.into(),
+                        let method = supporting.method(
+                            MFlags::Synthetic | method_access,
                             "<init>",
                             &support_ctor_sig,
                         );
@@ -140,11 +233,11 @@ impl NativeClassWrapper {
                         code.aload(0)
                             .invokespecial("java/lang/Object", "<init>", "()V")
                             .aload(0)
-                            .iload(1)
-                            .putfield(&support_name, "id", "I");
+                            .lload(1)
+                            .putfield(&support_name, "id", "J");
 
                         // write the rest of the parameters
-                        let mut param_id = 2;
+                        let mut param_id = 3;
                         for (id, param) in super_sig.params.as_slice().iter().enumerate() {
                             let field_name = format!("param_{}", id);
                             let param_ty = param.display_jni().to_string();
@@ -157,15 +250,17 @@ impl NativeClassWrapper {
                     }
 
                     // write the id field
-                    supporting.field(FFlags::Final | FFlags::Synthetic, "id", "I");
+                    supporting.field(FFlags::Final | FFlags::Synthetic | field_access, "id", "J");
 
                     // write the rest of the parameters in the supporting class
-                    let mut id = 0;
-                    for param in super_sig.params.as_slice() {
+                    for (id, param) in super_sig.params.as_slice().iter().enumerate() {
                         let field_name = format!("param_{}", id);
                         let param_ty = param.display_jni().to_string();
-                        supporting.field(FFlags::Final | FFlags::Synthetic, &field_name, &param_ty);
-                        id += 1;
+                        supporting.field(
+                            FFlags::Final | FFlags::Synthetic | field_access,
+                            &field_name,
+                            &param_ty,
+                        );
                     }
 
                     // write the class data
@@ -184,11 +279,16 @@ impl NativeClassWrapper {
                 code.invokespecial(&self.extends, "<init>", super_sig_str)
                     .aload(0)
                     .aload(var_native_ret)
-                    .getfield(&support_name, "id", "I")
-                    .putfield(&self.name, &self.id_param, "I");
+                    .getfield(&support_name, "id", "J")
+                    .putfield(&self.name, &self.id_param, "J");
             }
         }
 
+        // register the object with the cleaner
+        if let Some(holder) = &self.cleaner_holder {
+            register_cleaner(&mut code, &self.name, &self.id_param, holder);
+        }
+
         // call all instance initializer functions
         for func in instance_init {
             code.aload(0).invokevirtual(&self.name, func, "()V");
@@ -198,8 +298,73 @@ impl NativeClassWrapper {
         code.vreturn();
     }
 
-    pub fn export_field(&mut self, access: EnumSet<FFlags>, name: &str, ty: &str) {
-        self.class.field(access, name, ty);
+    /// Generates the constructor used to create objects wrapping a value that was created in Rust
+    /// code. This calls the superclass' constructor without parameters, stores the ID of the value,
+    /// registers the object with the cleaner and runs the instance initializers.
+    ///
+    /// The constructor takes a per-class marker type, so it cannot conflict with the constructors
+    /// exported to Java.
+    pub fn from_rust_constructor(&mut self, instance_init: &[&'static str]) {
+        let marker_name = from_rust_marker_name(&self.name);
+        let sig = format!("(JL{marker_name};)V");
+
+        let method = self
+            .class
+            .method(MFlags::Private | MFlags::Synthetic, "<init>", &sig);
+        let mut code = method.code();
+        code.aload(0)
+            .invokespecial(&self.extends, "<init>", "()V")
+            .aload(0)
+            .lload(1)
+            .putfield(&self.name, &self.id_param, "J");
+        if let Some(holder) = &self.cleaner_holder {
+            register_cleaner(&mut code, &self.name, &self.id_param, holder);
+        }
+        for func in instance_init {
+            code.aload(0).invokevirtual(&self.name, func, "()V");
+        }
+        code.vreturn();
+
+        let marker =
+            supporting_class(&self.name, self.target_version, &mut self.nest_members, &marker_name);
+        self.supporting.insert(marker_name, marker.into_vec());
+    }
+
+    pub fn export_field(
+        &mut self,
+        access: EnumSet<FFlags>,
+        name: &str,
+        ty: &str,
+        generic_ty: &str,
+        annotations: &AnnotationList,
+    ) {
+        let field = self.class.field(access, name, ty);
+        if generic_ty != ty {
+            match GenericType::parse_generic(generic_ty) {
+                Ok(generic) if generic.erase() == Type::parse_jni(ty).unwrap() => {
+                    field.signature(generic_ty);
+                }
+                Ok(_) => {
+                    let message =
+                        format!("`{generic_ty}` of field `{name}` does not match its type `{ty}`.");
+                    self.errors
+                        .push(signature_error(&self.name, None, None, message));
+                }
+                Err(_) => {
+                    let message =
+                        format!("`{generic_ty}` of field `{name}` is not a valid generic type.");
+                    self.errors
+                        .push(signature_error(&self.name, None, None, message));
+                }
+            }
+        }
+        let uses_note = &mut self.uses_deprecation_note;
+        let deprecated = add_annotations(annotations, uses_note, |annotation, visible| {
+            field.annotation(annotation, visible);
+        });
+        if deprecated {
+            field.deprecated();
+        }
     }
 
     pub fn export_native(&mut self, name: &str, sig: &str, is_static: bool) {
@@ -214,28 +379,45 @@ impl NativeClassWrapper {
         access: EnumSet<MFlags>,
         name: &str,
         sig: &str,
-        is_static: bool,
+        generic_sig: &str,
+        annotations: &AnnotationList,
+        param_annotations: &[AnnotationList],
     ) {
-        assert_eq!(access.contains(MFlags::Static), is_static);
-        self.class.method(access | MFlags::Native, name, sig);
+        let method = self.class.method(access | MFlags::Native, name, sig);
+        if let Err(message) = method_signature(method, sig, generic_sig) {
+            self.errors
+                .push(signature_error(&self.name, Some((name, sig)), None, message));
+        }
+        let uses_note = &mut self.uses_deprecation_note;
+        method_annotations(method, annotations, param_annotations, uses_note);
     }
 
     pub fn export_native_wrapper(
         &mut self,
-        access: EnumSet<MFlags>,
         name: &str,
         sig_str: &str,
         native_name: &str,
         native_sig_str: &str,
         has_id_param: bool,
+        meta: &ExportedMethodMeta,
     ) {
+        let access = meta.access;
+        self.origins
+            .push(method_origin(&self.name, name, sig_str, meta.rust_name));
+
         // parse the signatures passed in
         let sig = MethodSig::parse_jni(sig_str).unwrap();
         let native_sig = MethodSig::parse_jni(native_sig_str).unwrap();
 
         // begin generating the method
-        let mut method = self.class.method(access, name, sig_str);
+        let method = self.class.method(access, name, sig_str);
+        if let Err(message) = method_meta(method, sig_str, meta, &mut self.uses_deprecation_note) {
+            let method = Some((name, sig_str));
+            self.errors
+                .push(signature_error(&self.name, method, Some(meta.rust_name), message));
+        }
         let mut code = method.code();
+        source_line(&mut code, meta.line);
 
         // retrieve parameters
         let native_sig_params = if has_id_param {
@@ -246,7 +428,7 @@ impl NativeClassWrapper {
 
         // validate parameters
         if has_id_param {
-            assert_eq!(native_sig.params[0], Type::Int);
+            assert_eq!(native_sig.params[0], Type::Long);
         }
         assert_eq!(sig.ret_ty, native_sig.ret_ty);
         assert_eq!(native_sig_params, sig.params.as_slice());
@@ -259,10 +441,10 @@ impl NativeClassWrapper {
             0
         };
         if has_id_param {
-            code.dup().getfield(&self.name, &self.id_param, "I");
+            code.dup().getfield(&self.name, &self.id_param, "J");
         }
         for param in native_sig_params {
-            param_id += push_param(&mut code, param_id, &param);
+            param_id += push_param(&mut code, param_id, param);
         }
         if access.contains(MFlags::Static) {
             code.invokestatic(&self.name, native_name, native_sig_str);
@@ -272,14 +454,16 @@ impl NativeClassWrapper {
         return_param(&mut code, &sig.ret_ty);
     }
 
-    pub fn dispose_funcs(&mut self, free_fn: &str, is_auto_closable: bool) {
+    /// Generates functions that free the object when it is finalized, or when the method named
+    /// `close_method` is called.
+    pub fn dispose_funcs(&mut self, free_fn: &str, close_method: Option<&str>) {
         self.class.field(
             FFlags::Private | FFlags::Synthetic | FFlags::Volatile | FFlags::Transient,
             "njni$$triedClose",
             "Z",
         );
         {
-            let mut method = self.class.method(
+            let method = self.class.method(
                 MFlags::Public | MFlags::Final | MFlags::Synchronized,
                 "finalize",
                 "()V",
@@ -287,30 +471,116 @@ impl NativeClassWrapper {
             let mut code = method.code();
             code.aload(0)
                 .dup()
-                .getfield(&self.name, &self.id_param, "I")
+                .getfield(&self.name, &self.id_param, "J")
                 .aload(0)
                 .getfield(&self.name, "njni$$triedClose", "Z")
                 .aload(0)
                 .iconst(1)
                 .putfield(&self.name, "njni$$triedClose", "Z")
-                .invokevirtual(&self.name, free_fn, "(IZ)V")
+                .invokestatic(&self.name, free_fn, "(JZ)V")
                 .vreturn();
         }
 
-        if is_auto_closable {
-            self.class.implements("java/lang/AutoCloseable");
+        if let Some(close_method) = close_method {
+            self.implements_closeable(close_method);
             {
-                let mut method = self.class.method(
+                let method = self.class.method(
                     MFlags::Public | MFlags::Final | MFlags::Synchronized,
-                    "close",
+                    close_method,
                     "()V",
                 );
                 let mut code = method.code();
                 code.aload(0)
-                    .invokestatic(&self.name, "finalize", "()V")
+                    .invokevirtual(&self.name, "finalize", "()V")
+                    .vreturn();
+            }
+        }
+    }
+
+    /// Generates functions that free the object using a shared `java.lang.ref.Cleaner`, rather than
+    /// using `finalize`. This requires targeting Java 9 or later.
+    pub fn cleaner_funcs(&mut self, free_fn: &str, init_class: &str, close_method: Option<&str>) {
+        assert!(!self.constructor_generated);
+        assert!(self.target_version >= JavaVersion::JAVA_9);
+
+        let holder_name = cleaner_holder_name(init_class);
+        let action_name = cleanup_action_name(&self.name);
+        let nestmates = self.target_version.supports_nestmates();
+        self.class.field(
+            FFlags::Private | FFlags::Synthetic | FFlags::Transient,
+            "njni$$cleanable",
+            "Ljava/lang/ref/Cleaner$Cleanable;",
+        );
+
+        // without nestmates, the cleaning action can't call the private native method directly
+        if !nestmates {
+            let method =
+                self.class
+                    .method(MFlags::Static | MFlags::Synthetic, "njni$$cleanup", "(J)V");
+            let mut code = method.code();
+            code.lload(0)
+                .iconst(0)
+                .invokestatic(&self.name, free_fn, "(JZ)V")
+                .vreturn();
+        }
+
+        // generate the cleaning action, which must not hold a reference to the object
+        {
+            let mut action = supporting_class(
+                &self.name,
+                self.target_version,
+                &mut self.nest_members,
+                &action_name,
+            );
+            let (_, method_access) = nest_private(self.target_version);
+            action.implements("java/lang/Runnable");
+            action.field(FFlags::Private | FFlags::Final | FFlags::Synthetic, "id", "J");
+            {
+                let method = action.method(MFlags::Synthetic | method_access, "<init>", "(J)V");
+                let mut code = method.code();
+                code.aload(0)
+                    .invokespecial("java/lang/Object", "<init>", "()V")
+                    .aload(0)
+                    .lload(1)
+                    .putfield(&action_name, "id", "J")
+                    .vreturn();
+            }
+            {
+                let method = action.method(MFlags::Public.into(), "run", "()V");
+                let mut code = method.code();
+                code.aload(0).getfield(&action_name, "id", "J");
+                if nestmates {
+                    code.iconst(0).invokestatic(&self.name, free_fn, "(JZ)V");
+                } else {
+                    code.invokestatic(&self.name, "njni$$cleanup", "(J)V");
+                }
+                code.vreturn();
+            }
+            self.supporting.insert(action_name, action.into_vec());
+        }
+
+        if let Some(close_method) = close_method {
+            self.implements_closeable(close_method);
+            {
+                let method = self
+                    .class
+                    .method(MFlags::Public | MFlags::Final, close_method, "()V");
+                let mut code = method.code();
+                code.aload(0)
+                    .getfield(&self.name, "njni$$cleanable", "Ljava/lang/ref/Cleaner$Cleanable;")
+                    .invokeinterface("java/lang/ref/Cleaner$Cleanable", "clean", "()V")
                     .vreturn();
             }
         }
+
+        self.cleaner_holder = Some(holder_name);
+    }
+
+    /// Implements `AutoCloseable` if the method used to close the object is named `close`.
+    fn implements_closeable(&mut self, close_method: &str) {
+        if close_method == "close" {
+            self.implements("java/lang/AutoCloseable");
+        }
     }
 
     pub(crate) fn add_to_jar(mut self, data: &mut ClassData) {
@@ -323,10 +593,264 @@ impl NativeClassWrapper {
                 .vreturn();
         }
 
+        // generate the generic signature, if one was set
+        if let Some(mut signature) = self.signature.take() {
+            let (_, declared) = signature.erase();
+            for interface in &self.interfaces {
+                let name = ClassName::parse_jni(interface).unwrap();
+                if !declared.contains(&name) {
+                    signature.interfaces.push(GenericClassType::from(name));
+                }
+            }
+            for name in &declared {
+                let name = name.display_jni().to_string();
+                if !self.interfaces.contains(&name) {
+                    let message = format!(
+                        "The generic signature declares `{name}`, which is not implemented by the \
+                         class."
+                    );
+                    self.errors
+                        .push(signature_error(&self.name, None, None, message));
+                }
+            }
+            self.class
+                .signature(&signature.display_generic().to_string());
+        }
+
         // generate code
+        for member in &self.nest_members {
+            self.class.nest_member(member);
+        }
         data.add_class(&self.name, self.class.into_vec());
         for (name, class_data) in self.supporting {
             data.add_class(&name, class_data);
         }
+        for (class, name, sig, origin) in &self.origins {
+            data.add_method_origin(class, name, sig, origin);
+        }
+        for error in self.errors {
+            data.add_error(error);
+        }
+        if self.uses_deprecation_note {
+            let note = generate_deprecation_note(self.target_version);
+            data.add_class(DEPRECATION_NOTE, note);
+        }
+    }
+}
+
+fn register_cleaner(code: &mut MethodWriter, class: &str, id_param: &str, holder: &str) {
+    let action_name = cleanup_action_name(class);
+    code.aload(0)
+        .getstatic(holder, "CLEANER", "Ljava/lang/ref/Cleaner;")
+        .aload(0)
+        .new_object(&action_name)
+        .dup()
+        .aload(0)
+        .getfield(class, id_param, "J")
+        .invokespecial(&action_name, "<init>", "(J)V")
+        .invokevirtual(
+            "java/lang/ref/Cleaner",
+            "register",
+            "(Ljava/lang/Object;Ljava/lang/Runnable;)Ljava/lang/ref/Cleaner$Cleanable;",
+        )
+        .putfield(class, "njni$$cleanable", "Ljava/lang/ref/Cleaner$Cleanable;");
+}
+
+/// The annotation used to carry the note of a Rust `#[deprecated]` attribute to Java, as
+/// `@Deprecated` has no element for it.
+const DEPRECATION_NOTE: &str = "moe/lymia/nekojni/DeprecationNote";
+
+pub(crate) fn cleaner_holder_name(init_class: &str) -> String {
+    format!("{init_class}$NJni$$Cleaner")
+}
+fn cleanup_action_name(class: &str) -> String {
+    format!("{class}$NJni$$Cleanup")
+}
+fn from_rust_marker_name(class: &str) -> String {
+    format!("{class}$NJni$$FromRust")
+}
+
+/// Adds a `Signature` attribute to a method if its generic signature is different from its
+/// descriptor.
+fn method_signature(method: &mut MethodData, sig: &str, generic_sig: &str) -> Result<(), String> {
+    if generic_sig != sig {
+        match GenericMethodSig::parse_generic(generic_sig) {
+            Ok(generic) if generic.erase() == MethodSig::parse_jni(sig).unwrap() => {
+                method.signature(generic_sig);
+            }
+            Ok(_) => return Err(format!("`{generic_sig}` does not match the descriptor.")),
+            Err(_) => return Err(format!("`{generic_sig}` is not a valid generic signature.")),
+        }
+    }
+    Ok(())
+}
+
+/// Adds the generic signature, parameter names and annotations of an exported method.
+///
+/// The parameter names and annotations are added even if the generic signature is invalid.
+fn method_meta(
+    method: &mut MethodData,
+    sig: &str,
+    meta: &ExportedMethodMeta,
+    uses_note: &mut bool,
+) -> Result<(), String> {
+    let result = method_signature(method, sig, meta.generic_sig);
+    if !meta.param_names.is_empty() {
+        method.parameters(meta.param_names);
+    }
+    method_annotations(method, meta.annotations, meta.param_annotations, uses_note);
+    result
+}
+
+/// Creates an error for invalid information passed in for a class, to be reported when the
+/// class data is verified.
+fn signature_error(
+    class: &str,
+    method: Option<(&str, &str)>,
+    origin: Option<&str>,
+    message: String,
+) -> VerifyError {
+    VerifyError {
+        class: class.to_string(),
+        method: method.map(|(name, sig)| (name.to_string(), sig.to_string())),
+        instruction: None,
+        origin: origin.map(str::to_string),
+        message,
+    }
+}
+
+/// Marks the code that follows as generated for a given line of the Rust source file.
+fn source_line(code: &mut MethodWriter, line: u32) {
+    // line numbers are unavailable in some contexts, and are reported as 0
+    if line != 0 && line <= u16::MAX as u32 {
+        code.line_number(line as u16);
+    }
+}
+
+/// Parses a list of annotations, and passes each of them to `add` along with its visibility.
+///
+/// Returns whether the list contains `@Deprecated`, which is paired with the `Deprecated`
+/// attribute as `javac` does. `uses_note` is set if the list contains the annotation carrying the
+/// note of a Rust `#[deprecated]` attribute, whose class must then be added to the jar.
+fn add_annotations(
+    annotations: &AnnotationList,
+    uses_note: &mut bool,
+    mut add: impl FnMut(Annotation, bool),
+) -> bool {
+    let deprecated = ClassName::new(["java", "lang"].map(String::from), "Deprecated");
+    let note = ClassName::parse_jni(DEPRECATION_NOTE).unwrap();
+    let mut is_deprecated = false;
+    for (list, visible) in [(annotations.visible, true), (annotations.invisible, false)] {
+        for annotation in list {
+            let annotation = Annotation::parse_java(annotation).unwrap();
+            is_deprecated |= annotation.class == deprecated;
+            *uses_note |= annotation.class == note;
+            add(annotation, visible);
+        }
+    }
+    is_deprecated
+}
+
+/// Adds annotations to a method and its parameters.
+fn method_annotations(
+    method: &mut MethodData,
+    annotations: &AnnotationList,
+    param_annotations: &[AnnotationList],
+    uses_note: &mut bool,
+) {
+    let deprecated = add_annotations(annotations, uses_note, |annotation, visible| {
+        method.annotation(annotation, visible);
+    });
+    if deprecated {
+        method.deprecated();
+    }
+    for (i, annotations) in param_annotations.iter().enumerate() {
+        add_annotations(annotations, uses_note, |annotation, visible| {
+            method.parameter_annotation(i, annotation, visible);
+        });
     }
 }
+
+/// Records the Rust function an exported method was generated for, to be named in verification
+/// errors.
+fn method_origin(
+    class: &str,
+    name: &str,
+    sig: &str,
+    origin: &str,
+) -> (String, String, String, String) {
+    (class.to_string(), name.to_string(), sig.to_string(), origin.to_string())
+}
+
+/// Creates a synthetic class used to implement `host`. If nestmates are supported, it is added to
+/// the nest of `host`, so they may access each other's private members.
+fn supporting_class(
+    host: &str,
+    target_version: JavaVersion,
+    nest_members: &mut Vec<String>,
+    name: &str,
+) -> ClassWriter {
+    let mut class = ClassWriter::new(CFlags::Final | CFlags::Synthetic, name);
+    class.version(target_version.major_version());
+    if target_version.supports_nestmates() {
+        class.nest_host(host);
+        nest_members.push(name.to_string());
+    }
+    class
+}
+
+/// Returns the access used for the field and method members of supporting classes, which are
+/// private if nestmates are supported, and package-private otherwise.
+fn nest_private(target_version: JavaVersion) -> (EnumSet<FFlags>, EnumSet<MFlags>) {
+    if target_version.supports_nestmates() {
+        (FFlags::Private.into(), MFlags::Private.into())
+    } else {
+        (EnumSet::empty(), EnumSet::empty())
+    }
+}
+
+/// Generates a class holding the `java.lang.ref.Cleaner` shared by all classes in a module.
+///
+/// The class and its field are public, as exported classes may be in other packages than the
+/// module's init class.
+pub(crate) fn generate_cleaner_holder(name: &str, target_version: JavaVersion) -> Vec<u8> {
+    let mut class = ClassWriter::new(CFlags::Public | CFlags::Final | CFlags::Synthetic, name);
+    class.version(target_version.major_version());
+    class.field(
+        FFlags::Public | FFlags::Static | FFlags::Final | FFlags::Synthetic,
+        "CLEANER",
+        "Ljava/lang/ref/Cleaner;",
+    );
+    {
+        let method = class.method(MFlags::Static.into(), "<clinit>", "()V");
+        let mut code = method.code();
+        code.invokestatic("java/lang/ref/Cleaner", "create", "()Ljava/lang/ref/Cleaner;")
+            .putstatic(name, "CLEANER", "Ljava/lang/ref/Cleaner;")
+            .vreturn();
+    }
+    {
+        let method = class.method(MFlags::Private.into(), "<init>", "()V");
+        let mut code = method.code();
+        code.aload(0)
+            .invokespecial("java/lang/Object", "<init>", "()V")
+            .vreturn();
+    }
+    class.into_vec()
+}
+
+/// Generates the annotation type for [`DEPRECATION_NOTE`]. It is added to every jar that uses it,
+/// as `javac` warns about annotations with elements whose class cannot be found.
+///
+/// The annotation has the default `CLASS` retention, and is marked `@Documented` so the note is
+/// shown in the documentation of the deprecated member.
+fn generate_deprecation_note(target_version: JavaVersion) -> Vec<u8> {
+    let access = CFlags::Public | CFlags::Interface | CFlags::Abstract | CFlags::Annotation;
+    let mut class = ClassWriter::new(access, DEPRECATION_NOTE);
+    class.version(target_version.major_version());
+    class.extends("java/lang/Object");
+    class.implements("java/lang/annotation/Annotation");
+    let documented = ClassName::parse_jni("java/lang/annotation/Documented").unwrap();
+    class.annotation(Annotation::new(documented), true);
+    class.method(MFlags::Public | MFlags::Abstract, "value", "()Ljava/lang/String;");
+    class.into_vec()
+}
@@ -0,0 +1,456 @@
+use crate::signatures::*;
+use pest::error::*;
+use pest_consume::{match_nodes, Parser};
+use std::{
+    fmt::{Display, Formatter, Write},
+    ops::Deref,
+};
+
+#[derive(Parser)]
+#[grammar = "signatures/generic_signature.pest"]
+struct GenericParser;
+type Result<T> = std::result::Result<T, Error<Rule>>;
+type Node<'i> = pest_consume::Node<'i, Rule, ()>;
+
+// pest_consume requires the parser methods to return its error type unboxed
+#[allow(clippy::result_large_err)]
+#[pest_consume::parser]
+impl GenericParser {
+    fn ident(input: Node) -> Result<String> {
+        Ok(input.as_str().to_string())
+    }
+
+    fn path(input: Node) -> Result<ClassName> {
+        Ok(match_nodes!(input.children();
+            [ident(names)..] => {
+                let mut vec: Vec<_> = names.collect();
+                let name = match vec.pop() {
+                    Some(x) => x,
+                    None => return Err(input.error("ClassName has no components??")),
+                };
+                ClassName::new(vec, name)
+            },
+        ))
+    }
+
+    fn ty(input: Node) -> Result<GenericType> {
+        let (basic, braces) = match_nodes!(input.children();
+            [ty_array_head(braces).., ty_prim(prim)] => (prim, braces.count()),
+            [ty_array_head(braces).., ty_class(class)] =>
+                (GenericBasicType::Class(class), braces.count()),
+            [ty_array_head(braces).., ty_var(name)] =>
+                (GenericBasicType::TypeVariable(name), braces.count()),
+        );
+        Ok(GenericType::new(basic).array_dim(braces))
+    }
+    fn ty_prim(input: Node) -> Result<GenericBasicType> {
+        Ok(match input.as_str() {
+            "B" => GenericBasicType::Byte,
+            "S" => GenericBasicType::Short,
+            "I" => GenericBasicType::Int,
+            "J" => GenericBasicType::Long,
+            "F" => GenericBasicType::Float,
+            "D" => GenericBasicType::Double,
+            "Z" => GenericBasicType::Boolean,
+            "C" => GenericBasicType::Char,
+            _ => unreachable!(),
+        })
+    }
+    fn ty_void(_input: Node) -> Result<()> {
+        Ok(())
+    }
+    fn ty_class(input: Node) -> Result<GenericClassType> {
+        Ok(match_nodes!(input.children();
+            [path(name), ty_inner(inner)..] =>
+                GenericClassType { name, args: Vec::new(), inner: inner.collect() },
+            [path(name), type_args(args), ty_inner(inner)..] =>
+                GenericClassType { name, args, inner: inner.collect() },
+        ))
+    }
+    fn ty_inner(input: Node) -> Result<(String, Vec<TypeArgument>)> {
+        Ok(match_nodes!(input.children();
+            [ident(name)] => (name, Vec::new()),
+            [ident(name), type_args(args)] => (name, args),
+        ))
+    }
+    fn ty_var(input: Node) -> Result<String> {
+        Ok(match_nodes!(input.children();
+            [ident(name)] => name,
+        ))
+    }
+    fn ty_array_head(_input: Node) -> Result<()> {
+        Ok(())
+    }
+
+    fn type_args(input: Node) -> Result<Vec<TypeArgument>> {
+        Ok(match_nodes!(input.children();
+            [type_arg(args)..] => args.collect(),
+        ))
+    }
+    fn type_arg(input: Node) -> Result<TypeArgument> {
+        let span = input.as_span();
+        let arg = match_nodes!(input.children();
+            [type_arg_any(_)] => TypeArgument::Any,
+            [type_arg_extends(ty)] => TypeArgument::Extends(ty),
+            [type_arg_super(ty)] => TypeArgument::Super(ty),
+            [ty(ty)] => TypeArgument::Exact(ty),
+        );
+        match &arg {
+            TypeArgument::Exact(ty) | TypeArgument::Extends(ty) | TypeArgument::Super(ty)
+                if !ty.is_reference() =>
+            {
+                Err(Error::new_from_span(
+                    ErrorVariant::CustomError {
+                        message: "Type arguments must be reference types.".to_string(),
+                    },
+                    span,
+                ))
+            }
+            _ => Ok(arg),
+        }
+    }
+    fn type_arg_any(_input: Node) -> Result<()> {
+        Ok(())
+    }
+    fn type_arg_extends(input: Node) -> Result<GenericType> {
+        Ok(match_nodes!(input.children();
+            [ty(ty)] => ty,
+        ))
+    }
+    fn type_arg_super(input: Node) -> Result<GenericType> {
+        Ok(match_nodes!(input.children();
+            [ty(ty)] => ty,
+        ))
+    }
+
+    fn type_params(input: Node) -> Result<Vec<TypeParameter>> {
+        Ok(match_nodes!(input.children();
+            [type_param(params)..] => params.collect(),
+        ))
+    }
+    fn type_param(input: Node) -> Result<TypeParameter> {
+        Ok(match_nodes!(input.children();
+            [ident(name), class_bound(class_bound), interface_bound(bounds)..] =>
+                TypeParameter { name, class_bound, interface_bounds: bounds.collect() },
+        ))
+    }
+    fn class_bound(input: Node) -> Result<Option<GenericType>> {
+        Ok(match_nodes!(input.children();
+            [] => None,
+            [ty(ty)] => Some(ty),
+        ))
+    }
+    fn interface_bound(input: Node) -> Result<GenericType> {
+        Ok(match_nodes!(input.children();
+            [ty(ty)] => ty,
+        ))
+    }
+
+    fn sig(input: Node) -> Result<GenericMethodSig> {
+        let (type_params, params, ret_ty, throws) = match_nodes!(input.children();
+            [sig_params(params), sig_ret(ret_ty), sig_throws(throws)..] =>
+                (Vec::new(), params, ret_ty, throws.collect()),
+            [type_params(type_params), sig_params(params), sig_ret(ret_ty), sig_throws(throws)..] =>
+                (type_params, params, ret_ty, throws.collect()),
+        );
+        Ok(GenericMethodSig { type_params, ret_ty, params, throws })
+    }
+    fn sig_params(input: Node) -> Result<Vec<GenericType>> {
+        Ok(match_nodes!(input.children();
+            [ty(params)..] => params.collect(),
+        ))
+    }
+    fn sig_ret(input: Node) -> Result<GenericType> {
+        Ok(match_nodes!(input.children();
+            [ty(ty)] => ty,
+            [ty_void(_)] => GenericType::new(GenericBasicType::Void),
+        ))
+    }
+    fn sig_throws(input: Node) -> Result<GenericType> {
+        Ok(match_nodes!(input.children();
+            [ty_class(class)] => GenericType::new(GenericBasicType::Class(class)),
+            [ty_var(name)] => GenericType::new(GenericBasicType::TypeVariable(name)),
+        ))
+    }
+
+    fn class_sig(input: Node) -> Result<GenericClassSig> {
+        let (type_params, mut classes) = match_nodes!(input.children();
+            [ty_class(classes)..] => (Vec::new(), classes),
+            [type_params(type_params), ty_class(classes)..] => (type_params, classes),
+        );
+        let super_class = classes.next().unwrap();
+        Ok(GenericClassSig { type_params, super_class, interfaces: classes.collect() })
+    }
+
+    fn full_ty(input: Node) -> Result<GenericType> {
+        Ok(match_nodes!(input.children();
+            [ty(ty), EOI(_)] => ty,
+        ))
+    }
+    fn full_sig(input: Node) -> Result<GenericMethodSig> {
+        Ok(match_nodes!(input.children();
+            [sig(sig), EOI(_)] => sig,
+        ))
+    }
+    fn full_class_sig(input: Node) -> Result<GenericClassSig> {
+        Ok(match_nodes!(input.children();
+            [class_sig(sig), EOI(_)] => sig,
+        ))
+    }
+    fn EOI(_input: Node) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Parses a full signature with the given rule. The error is boxed, as it is large.
+fn parse_with<T>(
+    rule: Rule,
+    source: &str,
+    parse: fn(Node) -> Result<T>,
+) -> std::result::Result<T, Box<Error<Rule>>> {
+    let inputs = GenericParser::parse(rule, source)?;
+    let input = inputs.single()?;
+    Ok(parse(input)?)
+}
+
+impl GenericMethodSig {
+    /// Parses a method signature from the format used by the `Signature` attribute.
+    pub fn parse_generic(source: &str) -> std::result::Result<Self, Box<Error<Rule>>> {
+        parse_with(Rule::full_sig, source, GenericParser::full_sig)
+    }
+}
+impl GenericClassSig {
+    /// Parses a class signature from the format used by the `Signature` attribute.
+    pub fn parse_generic(source: &str) -> std::result::Result<Self, Box<Error<Rule>>> {
+        parse_with(Rule::full_class_sig, source, GenericParser::full_class_sig)
+    }
+}
+impl GenericType {
+    /// Parses a type from the format used by the `Signature` attribute.
+    pub fn parse_generic(source: &str) -> std::result::Result<Self, Box<Error<Rule>>> {
+        parse_with(Rule::full_ty, source, GenericParser::full_ty)
+    }
+}
+
+impl GenericMethodSig {
+    /// Returns the method descriptor this signature erases to.
+    ///
+    /// Type variables declared by the method erase to their first bound. Any other type variables
+    /// are assumed to be declared by the class without a bound, and erase to `java.lang.Object`.
+    pub fn erase(&self) -> MethodSig {
+        let erase = |ty: &GenericType| ty.erase_with(&self.type_params);
+        MethodSig::new(erase(&self.ret_ty), self.params.iter().map(erase).collect::<Vec<_>>())
+    }
+}
+impl GenericClassSig {
+    /// Returns the names of the superclass and interfaces of this class.
+    pub fn erase(&self) -> (ClassName, Vec<ClassName>) {
+        (
+            self.super_class.erase(),
+            self.interfaces
+                .iter()
+                .map(GenericClassType::erase)
+                .collect(),
+        )
+    }
+}
+impl GenericType {
+    /// Returns whether this is a reference type.
+    pub fn is_reference(&self) -> bool {
+        self.array_dim != 0
+            || matches!(
+                self.basic_sig,
+                GenericBasicType::Class(_) | GenericBasicType::TypeVariable(_)
+            )
+    }
+
+    /// Returns the type this type erases to. Type variables erase to `java.lang.Object`.
+    pub fn erase(&self) -> Type {
+        self.erase_with(&[])
+    }
+
+    fn erase_with(&self, type_params: &[TypeParameter]) -> Type {
+        let basic = match &self.basic_sig {
+            GenericBasicType::Byte => BasicType::Byte,
+            GenericBasicType::Short => BasicType::Short,
+            GenericBasicType::Int => BasicType::Int,
+            GenericBasicType::Long => BasicType::Long,
+            GenericBasicType::Float => BasicType::Float,
+            GenericBasicType::Double => BasicType::Double,
+            GenericBasicType::Boolean => BasicType::Boolean,
+            GenericBasicType::Char => BasicType::Char,
+            GenericBasicType::Void => BasicType::Void,
+            GenericBasicType::Class(class) => BasicType::Class(class.erase()),
+            GenericBasicType::TypeVariable(name) => {
+                let param = type_params.iter().find(|x| &x.name == name);
+                let bound =
+                    param.and_then(|x| x.class_bound.iter().chain(&x.interface_bounds).next());
+                match bound {
+                    // bounds cannot refer to their own type variable, so this cannot recurse forever
+                    Some(bound) => {
+                        let remaining: Vec<_> = type_params
+                            .iter()
+                            .filter(|x| &x.name != name)
+                            .cloned()
+                            .collect();
+                        return bound.erase_with(&remaining).array_dim(self.array_dim);
+                    }
+                    None => BasicType::Class(ClassName::new(
+                        ["java", "lang"].map(String::from),
+                        "Object",
+                    )),
+                }
+            }
+        };
+        Type::new(basic).array_dim(self.array_dim)
+    }
+}
+impl GenericClassType {
+    /// Returns the name of the class this type refers to.
+    pub fn erase(&self) -> ClassName {
+        let mut name = self.name.clone();
+        for (inner, _) in &self.inner {
+            name.name.push('$');
+            name.name.push_str(inner);
+        }
+        name
+    }
+}
+
+struct DisplayMethodSignatureGeneric<'a>(&'a GenericMethodSig);
+impl<'a> Display for DisplayMethodSignatureGeneric<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        display_type_params(&self.0.type_params, f)?;
+        f.write_char('(')?;
+        for param in self.0.params.deref() {
+            Display::fmt(&param.display_generic(), f)?;
+        }
+        f.write_str(")")?;
+        Display::fmt(&self.0.ret_ty.display_generic(), f)?;
+        for throws in self.0.throws.deref() {
+            f.write_char('^')?;
+            Display::fmt(&throws.display_generic(), f)?;
+        }
+        Ok(())
+    }
+}
+impl GenericMethodSig {
+    /// Displays this object in the format used by the `Signature` attribute.
+    pub fn display_generic<'a>(&'a self) -> impl Display + 'a {
+        DisplayMethodSignatureGeneric(self)
+    }
+}
+
+struct DisplayClassSignatureGeneric<'a>(&'a GenericClassSig);
+impl<'a> Display for DisplayClassSignatureGeneric<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        display_type_params(&self.0.type_params, f)?;
+        Display::fmt(&self.0.super_class.display_generic(), f)?;
+        for interface in self.0.interfaces.deref() {
+            Display::fmt(&interface.display_generic(), f)?;
+        }
+        Ok(())
+    }
+}
+impl GenericClassSig {
+    /// Displays this object in the format used by the `Signature` attribute.
+    pub fn display_generic<'a>(&'a self) -> impl Display + 'a {
+        DisplayClassSignatureGeneric(self)
+    }
+}
+
+fn display_type_params(params: &[TypeParameter], f: &mut Formatter<'_>) -> std::fmt::Result {
+    if !params.is_empty() {
+        f.write_char('<')?;
+        for param in params {
+            f.write_str(&param.name)?;
+            f.write_char(':')?;
+            if let Some(bound) = &param.class_bound {
+                Display::fmt(&bound.display_generic(), f)?;
+            }
+            for bound in param.interface_bounds.deref() {
+                f.write_char(':')?;
+                Display::fmt(&bound.display_generic(), f)?;
+            }
+        }
+        f.write_char('>')?;
+    }
+    Ok(())
+}
+
+struct DisplayTypeGeneric<'a>(&'a GenericType);
+impl<'a> Display for DisplayTypeGeneric<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for _ in 0..self.0.array_dim {
+            f.write_str("[")?;
+        }
+        match &self.0.basic_sig {
+            GenericBasicType::Byte => f.write_str("B"),
+            GenericBasicType::Short => f.write_str("S"),
+            GenericBasicType::Int => f.write_str("I"),
+            GenericBasicType::Long => f.write_str("J"),
+            GenericBasicType::Float => f.write_str("F"),
+            GenericBasicType::Double => f.write_str("D"),
+            GenericBasicType::Boolean => f.write_str("Z"),
+            GenericBasicType::Char => f.write_str("C"),
+            GenericBasicType::Void => f.write_str("V"),
+            GenericBasicType::Class(class) => Display::fmt(&class.display_generic(), f),
+            GenericBasicType::TypeVariable(name) => {
+                f.write_char('T')?;
+                f.write_str(name)?;
+                f.write_char(';')?;
+                Ok(())
+            }
+        }
+    }
+}
+impl GenericType {
+    /// Displays this object in the format used by the `Signature` attribute.
+    pub fn display_generic<'a>(&'a self) -> impl Display + 'a {
+        DisplayTypeGeneric(self)
+    }
+}
+
+struct DisplayClassTypeGeneric<'a>(&'a GenericClassType);
+impl<'a> Display for DisplayClassTypeGeneric<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_char('L')?;
+        Display::fmt(&self.0.name.display_jni(), f)?;
+        display_type_args(&self.0.args, f)?;
+        for (name, args) in self.0.inner.deref() {
+            f.write_char('.')?;
+            f.write_str(name)?;
+            display_type_args(args, f)?;
+        }
+        f.write_char(';')?;
+        Ok(())
+    }
+}
+impl GenericClassType {
+    /// Displays this object in the format used by the `Signature` attribute.
+    pub fn display_generic<'a>(&'a self) -> impl Display + 'a {
+        DisplayClassTypeGeneric(self)
+    }
+}
+
+fn display_type_args(args: &[TypeArgument], f: &mut Formatter<'_>) -> std::fmt::Result {
+    if !args.is_empty() {
+        f.write_char('<')?;
+        for arg in args {
+            match arg {
+                TypeArgument::Exact(ty) => Display::fmt(&ty.display_generic(), f)?,
+                TypeArgument::Extends(ty) => {
+                    f.write_char('+')?;
+                    Display::fmt(&ty.display_generic(), f)?;
+                }
+                TypeArgument::Super(ty) => {
+                    f.write_char('-')?;
+                    Display::fmt(&ty.display_generic(), f)?;
+                }
+                TypeArgument::Any => f.write_char('*')?,
+            }
+        }
+        f.write_char('>')?;
+    }
+    Ok(())
+}
@@ -35,25 +35,9 @@ impl JavaParser {
         let (name, braces) = match_nodes!(input.children();
             [path(name), ty_array_braces(braces)..] => (name, braces),
         );
-        let base = if name.package.is_empty() {
-            match name.name.as_str() {
-                "byte" | "short" | "int" | "long" | "float" | "double" | "boolean" | "char" => {
-                    match name.name.as_str() {
-                        "byte" => Type::Byte,
-                        "short" => Type::Short,
-                        "int" => Type::Int,
-                        "long" => Type::Long,
-                        "float" => Type::Float,
-                        "double" => Type::Double,
-                        "boolean" => Type::Boolean,
-                        "char" => Type::Char,
-                        _ => unreachable!(),
-                    }
-                }
-                _ => Type::new(BasicType::Class(name)),
-            }
-        } else {
-            Type::new(BasicType::Class(name))
+        let base = match primitive_type(&name) {
+            Some(ty) => ty,
+            None => Type::new(BasicType::Class(name)),
         };
         Ok(base.array_dim(braces.count()))
     }
@@ -61,6 +45,57 @@ impl JavaParser {
         Ok(())
     }
 
+    fn generic_ty(input: Node) -> Result<GenericType> {
+        let span = input.as_span();
+        let (name, args, braces) = match_nodes!(input.children();
+            [path(name), ty_array_braces(braces)..] => (name, Vec::new(), braces),
+            [path(name), generic_args(args), ty_array_braces(braces)..] => (name, args, braces),
+        );
+        let base: GenericType = match primitive_type(&name) {
+            Some(_) if !args.is_empty() => {
+                return Err(custom_error("Primitive types cannot have type arguments.", span))
+            }
+            Some(ty) => ty.into(),
+            None => GenericClassType::new(name, args).into(),
+        };
+        Ok(base.array_dim(braces.count()))
+    }
+    fn generic_args(input: Node) -> Result<Vec<TypeArgument>> {
+        Ok(match_nodes!(input.children();
+            [generic_arg(args)..] => args.collect(),
+        ))
+    }
+    fn generic_arg(input: Node) -> Result<TypeArgument> {
+        let span = input.as_span();
+        let arg = match_nodes!(input.children();
+            [generic_arg_extends(ty)] => TypeArgument::Extends(ty),
+            [generic_arg_super(ty)] => TypeArgument::Super(ty),
+            [generic_arg_any(_)] => TypeArgument::Any,
+            [generic_ty(ty)] => TypeArgument::Exact(ty),
+        );
+        match &arg {
+            TypeArgument::Exact(ty) | TypeArgument::Extends(ty) | TypeArgument::Super(ty)
+                if !ty.is_reference() =>
+            {
+                Err(custom_error("Type arguments must be reference types.", span))
+            }
+            _ => Ok(arg),
+        }
+    }
+    fn generic_arg_extends(input: Node) -> Result<GenericType> {
+        Ok(match_nodes!(input.children();
+            [generic_ty(ty)] => ty,
+        ))
+    }
+    fn generic_arg_super(input: Node) -> Result<GenericType> {
+        Ok(match_nodes!(input.children();
+            [generic_ty(ty)] => ty,
+        ))
+    }
+    fn generic_arg_any(_input: Node) -> Result<()> {
+        Ok(())
+    }
+
     fn sig(input: Node) -> Result<MethodSig> {
         Ok(match_nodes!(input.children();
             [sig_param_list(params)] => {
@@ -77,11 +112,160 @@ impl JavaParser {
         ))
     }
 
+    fn keyword_class(_input: Node) -> Result<()> {
+        Ok(())
+    }
+
+    fn annotation(input: Node) -> Result<Annotation> {
+        let span = input.as_span();
+        let (class, elements) = match_nodes!(input.children();
+            [path(class)] => (class, Vec::new()),
+            [path(class), annotation_args(elements)] => (class, elements),
+        );
+        for (i, (name, _)) in elements.iter().enumerate() {
+            if elements[..i].iter().any(|(x, _)| x == name) {
+                return Err(custom_error(&format!("Duplicate annotation element `{name}`."), span));
+            }
+        }
+        Ok(Annotation { class, elements })
+    }
+    fn annotation_args(input: Node) -> Result<Vec<(String, ElementValue)>> {
+        Ok(match_nodes!(input.children();
+            [annotation_pair(pairs)..] => pairs.collect(),
+            [element_value(value)] => vec![("value".to_string(), value)],
+        ))
+    }
+    fn annotation_pair(input: Node) -> Result<(String, ElementValue)> {
+        Ok(match_nodes!(input.children();
+            [ident(name), element_value(value)] => (name, value),
+        ))
+    }
+
+    fn element_value(input: Node) -> Result<ElementValue> {
+        Ok(match_nodes!(input.children();
+            [annotation(annotation)] => ElementValue::Annotation(annotation),
+            [element_array(values)] => ElementValue::Array(values),
+            [element_class(ty)] => ElementValue::Class(ty),
+            [literal_bool(value)] => ElementValue::Boolean(value),
+            [literal_float(value)] => value,
+            [literal_int(value)] => value,
+            [literal_char(value)] => ElementValue::Char(value),
+            [literal_string(value)] => ElementValue::String(value),
+            [element_enum(value)] => value,
+        ))
+    }
+    fn element_array(input: Node) -> Result<Vec<ElementValue>> {
+        Ok(match_nodes!(input.children();
+            [element_value(values)..] => values.collect(),
+        ))
+    }
+    fn element_class(input: Node) -> Result<Type> {
+        let span = input.as_span();
+        let ty = match_nodes!(input.children();
+            [ty(ty), keyword_class(_)] => ty,
+        );
+        match ty {
+            Type { basic_sig: BasicType::Class(name), array_dim }
+                if name.package.is_empty() && name.name == "void" =>
+            {
+                if array_dim != 0 {
+                    return Err(custom_error("`void` cannot be used in an array.", span));
+                }
+                Ok(Type::Void)
+            }
+            ty => Ok(ty),
+        }
+    }
+    fn element_enum(input: Node) -> Result<ElementValue> {
+        let span = input.as_span();
+        let mut path = match_nodes!(input.children();
+            [path(path)] => path,
+        );
+        match path.package.pop() {
+            Some(class) => Ok(ElementValue::Enum(ClassName::new(path.package, class), path.name)),
+            None => Err(custom_error("Enum constants must include the name of the enum.", span)),
+        }
+    }
+
+    fn literal_bool(input: Node) -> Result<bool> {
+        Ok(input.as_str() == "true")
+    }
+    fn literal_int(input: Node) -> Result<ElementValue> {
+        let span = input.as_span();
+        let (is_negative, str) = match input.as_str().strip_prefix('-') {
+            Some(str) => (true, str),
+            None => (false, input.as_str()),
+        };
+        let (is_long, str) = match str.strip_suffix(|x| x == 'l' || x == 'L') {
+            Some(str) => (true, str),
+            None => (false, str),
+        };
+        let (radix, digits) = match str.strip_prefix("0x").or(str.strip_prefix("0X")) {
+            Some(digits) => (16, digits),
+            None => (10, str),
+        };
+
+        // hexadecimal literals may set the sign bit, while decimal literals may not
+        let magnitude = u64::from_str_radix(digits, radix).ok();
+        let limit = match (is_long, radix) {
+            (false, 16) => u32::MAX as u64,
+            (false, _) => i32::MAX as u64 + is_negative as u64,
+            (true, 16) => u64::MAX,
+            (true, _) => i64::MAX as u64 + is_negative as u64,
+        };
+        let value = match magnitude {
+            Some(value) if value <= limit => value,
+            _ => return Err(custom_error("Integer literal is out of range.", span)),
+        };
+        let value = if is_negative { value.wrapping_neg() } else { value };
+        Ok(if is_long {
+            ElementValue::Long(value as i64)
+        } else {
+            ElementValue::Int(value as u32 as i32)
+        })
+    }
+    fn literal_float(input: Node) -> Result<ElementValue> {
+        let span = input.as_span();
+        let str = input.as_str();
+        let value = match str.strip_suffix(['f', 'F']) {
+            Some(str) => str.parse::<f32>().ok().map(ElementValue::Float),
+            None => str
+                .trim_end_matches(['d', 'D'])
+                .parse::<f64>()
+                .ok()
+                .map(ElementValue::Double),
+        };
+        match value {
+            Some(ElementValue::Float(x)) if x.is_finite() => Ok(ElementValue::Float(x)),
+            Some(ElementValue::Double(x)) if x.is_finite() => Ok(ElementValue::Double(x)),
+            _ => Err(custom_error("Floating point literal is out of range.", span)),
+        }
+    }
+    fn literal_char(input: Node) -> Result<char> {
+        let span = input.as_span();
+        let str = input.as_str();
+        let value = unescape(&str[1..str.len() - 1], span)?;
+        let mut chars = value.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) if (ch as u32) <= u16::MAX as u32 => Ok(ch),
+            _ => Err(custom_error("Character literals must contain a single UTF-16 unit.", span)),
+        }
+    }
+    fn literal_string(input: Node) -> Result<String> {
+        let str = input.as_str();
+        unescape(&str[1..str.len() - 1], input.as_span())
+    }
+
     fn full_ty(input: Node) -> Result<Type> {
         Ok(match_nodes!(input.children();
             [ty(ty), EOI(_)] => ty,
         ))
     }
+    fn full_generic_ty(input: Node) -> Result<GenericType> {
+        Ok(match_nodes!(input.children();
+            [generic_ty(ty), EOI(_)] => ty,
+        ))
+    }
     fn full_sig(input: Node) -> Result<MethodSig> {
         Ok(match_nodes!(input.children();
             [sig(sig), EOI(_)] => sig,
@@ -92,6 +276,11 @@ impl JavaParser {
             [path(path), EOI(_)] => path,
         ))
     }
+    fn full_annotation(input: Node) -> Result<Annotation> {
+        Ok(match_nodes!(input.children();
+            [annotation(annotation), EOI(_)] => annotation,
+        ))
+    }
     fn EOI(_input: Node) -> Result<()> {
         Ok(())
     }
@@ -115,6 +304,16 @@ impl Type {
         JavaParser::full_ty(input)
     }
 }
+impl GenericType {
+    /// Parses a type that may contain type arguments from a Java-like format.
+    ///
+    /// Type variables are not supported, as names without a package are parsed as class names.
+    pub fn parse_java(source: &str) -> Result<Self> {
+        let inputs = JavaParser::parse(Rule::full_generic_ty, source)?;
+        let input = inputs.single()?;
+        JavaParser::full_generic_ty(input)
+    }
+}
 impl ClassName {
     /// Parses a class name from a Java-like format.
     pub fn parse_java(source: &str) -> Result<Self> {
@@ -124,6 +323,107 @@ impl ClassName {
     }
 }
 
+impl Annotation {
+    /// Parses an annotation from Java syntax, such as `@java.lang.Deprecated(since = "1.2")`.
+    ///
+    /// Annotation names must be fully qualified. As the definition of the annotation is not
+    /// available, integer literals are always parsed as `int` or `long` values.
+    pub fn parse_java(source: &str) -> Result<Self> {
+        let inputs = JavaParser::parse(Rule::full_annotation, source)?;
+        let input = inputs.single()?;
+        JavaParser::full_annotation(input)
+    }
+}
+
+fn primitive_type(name: &ClassName) -> Option<Type> {
+    if !name.package.is_empty() {
+        return None;
+    }
+    match name.name.as_str() {
+        "byte" => Some(Type::Byte),
+        "short" => Some(Type::Short),
+        "int" => Some(Type::Int),
+        "long" => Some(Type::Long),
+        "float" => Some(Type::Float),
+        "double" => Some(Type::Double),
+        "boolean" => Some(Type::Boolean),
+        "char" => Some(Type::Char),
+        _ => None,
+    }
+}
+
+fn custom_error(message: &str, span: pest::Span) -> Error<Rule> {
+    Error::new_from_span(ErrorVariant::CustomError { message: message.to_string() }, span)
+}
+
+/// Decodes the escape sequences in a Java string or character literal.
+fn unescape(str: &str, span: pest::Span) -> Result<String> {
+    let mut out = String::new();
+    let mut chars = str.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('u') => {
+                while chars.peek() == Some(&'u') {
+                    chars.next();
+                }
+                let digits: String = chars.by_ref().take(4).collect();
+                let value = u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32);
+                match value {
+                    Some(ch) => ch,
+                    None => return Err(custom_error("Unsupported unicode escape.", span)),
+                }
+            }
+            Some(digit @ '0'..='7') => {
+                let mut value = digit.to_digit(8).unwrap();
+                let max_len = if digit <= '3' { 2 } else { 1 };
+                for _ in 0..max_len {
+                    match chars.peek().and_then(|x| x.to_digit(8)) {
+                        Some(digit) => value = value * 8 + digit,
+                        None => break,
+                    }
+                    chars.next();
+                }
+                char::from_u32(value).unwrap()
+            }
+            Some(ch @ ('"' | '\'' | '\\')) => ch,
+            _ => return Err(custom_error("Unknown escape sequence.", span)),
+        };
+        out.push(escaped);
+    }
+    Ok(out)
+}
+
+/// Writes a string as the contents of a Java string or character literal.
+fn write_escaped(str: &str, quote: char, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_char(quote)?;
+    for ch in str.chars() {
+        match ch {
+            '\t' => f.write_str("\\t")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\\' => f.write_str("\\\\")?,
+            ch if ch == quote => {
+                f.write_char('\\')?;
+                f.write_char(ch)?;
+            }
+            ch if ch.is_control() => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => f.write_char(ch)?,
+        }
+    }
+    f.write_char(quote)
+}
+
 fn display_params(sig: &MethodSig, f: &mut Formatter<'_>) -> std::fmt::Result {
     f.write_char('(')?;
     let mut is_first = true;
@@ -214,3 +514,71 @@ impl ClassName {
         DisplayClassNameJava(self)
     }
 }
+
+struct DisplayAnnotationJava<'a>(&'a Annotation);
+impl<'a> Display for DisplayAnnotationJava<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_char('@')?;
+        Display::fmt(&self.0.class.display_java(), f)?;
+        if let [(name, value)] = self.0.elements.as_slice() {
+            if name == "value" {
+                return write!(f, "({})", value.display_java());
+            }
+        }
+        if !self.0.elements.is_empty() {
+            f.write_char('(')?;
+            let mut is_first = true;
+            for (name, value) in &self.0.elements {
+                if !is_first {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{name} = {}", value.display_java())?;
+                is_first = false;
+            }
+            f.write_char(')')?;
+        }
+        Ok(())
+    }
+}
+impl Annotation {
+    /// Displays this object in Java syntax.
+    pub fn display_java<'a>(&'a self) -> impl Display + 'a {
+        DisplayAnnotationJava(self)
+    }
+}
+
+struct DisplayElementValueJava<'a>(&'a ElementValue);
+impl<'a> Display for DisplayElementValueJava<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            ElementValue::Boolean(v) => write!(f, "{v}"),
+            ElementValue::Char(v) => write_escaped(v.encode_utf8(&mut [0; 4]), '\'', f),
+            ElementValue::Int(v) => write!(f, "{v}"),
+            ElementValue::Long(v) => write!(f, "{v}L"),
+            ElementValue::Float(v) => write!(f, "{v:?}f"),
+            ElementValue::Double(v) => write!(f, "{v:?}"),
+            ElementValue::String(v) => write_escaped(v, '"', f),
+            ElementValue::Enum(class, name) => write!(f, "{}.{name}", class.display_java()),
+            ElementValue::Class(ty) => write!(f, "{}.class", ty.display_java()),
+            ElementValue::Annotation(annotation) => Display::fmt(&annotation.display_java(), f),
+            ElementValue::Array(values) => {
+                f.write_char('{')?;
+                let mut is_first = true;
+                for value in values {
+                    if !is_first {
+                        f.write_str(", ")?;
+                    }
+                    Display::fmt(&value.display_java(), f)?;
+                    is_first = false;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+impl ElementValue {
+    /// Displays this object in Java syntax.
+    pub fn display_java<'a>(&'a self) -> impl Display + 'a {
+        DisplayElementValueJava(self)
+    }
+}
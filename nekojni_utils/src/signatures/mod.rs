@@ -1,3 +1,4 @@
+mod generic_sigs;
 mod java_sigs;
 mod jni_exports;
 mod jni_sigs;
@@ -104,3 +105,187 @@ impl MethodName {
         MethodName { class, name: name.into() }
     }
 }
+
+/// The generic signature of a method, as stored in the `Signature` attribute of a class file.
+#[derive(Debug, Clone, Hash, Ord, PartialOrd, Eq, PartialEq)]
+pub struct GenericMethodSig {
+    pub type_params: Vec<TypeParameter>,
+    pub ret_ty: GenericType,
+    pub params: Vec<GenericType>,
+    pub throws: Vec<GenericType>,
+}
+impl GenericMethodSig {
+    /// Creates a new generic method signature with no type parameters.
+    pub fn new(ret_ty: GenericType, params: impl Into<Vec<GenericType>>) -> Self {
+        GenericMethodSig {
+            type_params: Vec::new(),
+            ret_ty,
+            params: params.into(),
+            throws: Vec::new(),
+        }
+    }
+}
+
+/// The generic signature of a class, as stored in the `Signature` attribute of a class file.
+#[derive(Debug, Clone, Hash, Ord, PartialOrd, Eq, PartialEq)]
+pub struct GenericClassSig {
+    pub type_params: Vec<TypeParameter>,
+    pub super_class: GenericClassType,
+    pub interfaces: Vec<GenericClassType>,
+}
+impl GenericClassSig {
+    /// Creates a new generic class signature with no type parameters.
+    pub fn new(
+        super_class: GenericClassType,
+        interfaces: impl Into<Vec<GenericClassType>>,
+    ) -> Self {
+        GenericClassSig { type_params: Vec::new(), super_class, interfaces: interfaces.into() }
+    }
+}
+
+/// A type parameter declared by a generic class or method.
+#[derive(Debug, Clone, Hash, Ord, PartialOrd, Eq, PartialEq)]
+pub struct TypeParameter {
+    pub name: String,
+    pub class_bound: Option<GenericType>,
+    pub interface_bounds: Vec<GenericType>,
+}
+
+/// A Java type that may contain type arguments or type variables.
+#[derive(Debug, Clone, Hash, Ord, PartialOrd, Eq, PartialEq)]
+pub struct GenericType {
+    pub basic_sig: GenericBasicType,
+    pub array_dim: usize,
+}
+impl GenericType {
+    /// Create a new type for a given basic type.
+    pub const fn new(ty: GenericBasicType) -> Self {
+        GenericType { basic_sig: ty, array_dim: 0 }
+    }
+
+    /// Create a new type for an array.
+    pub fn array(mut self) -> Self {
+        self.array_dim += 1;
+        self
+    }
+
+    /// Create a new type for a multidimensional array.
+    pub fn array_dim(mut self, dims: usize) -> Self {
+        self.array_dim += dims;
+        self
+    }
+}
+impl From<Type> for GenericType {
+    fn from(ty: Type) -> Self {
+        let basic_sig = match ty.basic_sig {
+            BasicType::Byte => GenericBasicType::Byte,
+            BasicType::Short => GenericBasicType::Short,
+            BasicType::Int => GenericBasicType::Int,
+            BasicType::Long => GenericBasicType::Long,
+            BasicType::Float => GenericBasicType::Float,
+            BasicType::Double => GenericBasicType::Double,
+            BasicType::Boolean => GenericBasicType::Boolean,
+            BasicType::Char => GenericBasicType::Char,
+            BasicType::Void => GenericBasicType::Void,
+            BasicType::Class(name) => GenericBasicType::Class(name.into()),
+        };
+        GenericType { basic_sig, array_dim: ty.array_dim }
+    }
+}
+impl From<GenericClassType> for GenericType {
+    fn from(class: GenericClassType) -> Self {
+        GenericType::new(GenericBasicType::Class(class))
+    }
+}
+
+/// A basic Java type that may contain type arguments, or a type variable.
+///
+/// As with [`BasicType`], array dimensionality is represented by [`GenericType`].
+#[derive(Debug, Clone, Hash, Ord, PartialOrd, Eq, PartialEq)]
+pub enum GenericBasicType {
+    Byte,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    Boolean,
+    Char,
+    Void,
+    Class(GenericClassType),
+    TypeVariable(String),
+}
+
+/// A reference to a class, along with its type arguments.
+///
+/// Inner classes of parameterized classes are stored in `inner`, along with their own type
+/// arguments. For example, `Outer<String>.Inner<Integer>` has a `name` of `Outer`.
+#[derive(Debug, Clone, Hash, Ord, PartialOrd, Eq, PartialEq)]
+pub struct GenericClassType {
+    pub name: ClassName,
+    pub args: Vec<TypeArgument>,
+    pub inner: Vec<(String, Vec<TypeArgument>)>,
+}
+impl GenericClassType {
+    /// Creates a reference to a class with the given type arguments.
+    pub fn new(name: ClassName, args: impl Into<Vec<TypeArgument>>) -> Self {
+        GenericClassType { name, args: args.into(), inner: Vec::new() }
+    }
+
+    /// Returns whether this class has any type arguments.
+    pub fn is_parameterized(&self) -> bool {
+        !self.args.is_empty() || self.inner.iter().any(|(_, args)| !args.is_empty())
+    }
+}
+impl From<ClassName> for GenericClassType {
+    fn from(name: ClassName) -> Self {
+        GenericClassType::new(name, Vec::new())
+    }
+}
+
+/// A type argument of a parameterized class.
+#[derive(Debug, Clone, Hash, Ord, PartialOrd, Eq, PartialEq)]
+pub enum TypeArgument {
+    /// A specific type, such as `String` in `List<String>`.
+    Exact(GenericType),
+    /// A bounded wildcard, such as `? extends String`.
+    Extends(GenericType),
+    /// A bounded wildcard, such as `? super String`.
+    Super(GenericType),
+    /// An unbounded wildcard, `?`.
+    Any,
+}
+
+/// An annotation applied to a class, method, field or parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub class: ClassName,
+    pub elements: Vec<(String, ElementValue)>,
+}
+impl Annotation {
+    /// Creates a new annotation with no elements.
+    pub fn new(class: ClassName) -> Self {
+        Annotation { class, elements: Vec::new() }
+    }
+}
+
+/// The value of an element of an annotation.
+///
+/// `byte` and `short` elements are not supported, as they cannot be distinguished from `int`
+/// elements without the definition of the annotation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElementValue {
+    Boolean(bool),
+    Char(char),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    /// An enum constant, such as `java.lang.annotation.RetentionPolicy.RUNTIME`.
+    Enum(ClassName, String),
+    /// A class literal, such as `java.lang.String.class`.
+    Class(Type),
+    Annotation(Annotation),
+    Array(Vec<ElementValue>),
+}
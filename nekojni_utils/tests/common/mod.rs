@@ -0,0 +1,68 @@
+use nekojni_utils::{
+    class_reader::ClassFile, AnnotationList, CFlags, ClassData, ExportedMethodMeta,
+    GenerationOptions, JavaVersion, MFlags, NativeClassWrapper,
+};
+
+pub const CLASS_NAME: &str = "test/Example";
+pub const RUST_NAME: &str = "test::Example::get_value";
+
+/// Generates the classes used by the tests: a loader, an init class, an exception class, and an
+/// exported class with a native wrapper method `getValue`.
+pub fn make_class_data(options: GenerationOptions) -> ClassData {
+    let mut data = ClassData::with_options(options);
+    data.add_resource_loader("test/Loader", "test_crate", "1.2.3", "test/images");
+    data.add_module_loader("test/Init");
+    data.add_module_exception("test/TestException");
+
+    let mut class = NativeClassWrapper::new(
+        CFlags::Public.into(),
+        CLASS_NAME,
+        "java/lang/Object",
+        "njni$$i",
+        options.target_version,
+    );
+    class.generate_init("test/Init", &[]);
+    class.source_file("example.rs");
+    if options.use_cleaner {
+        class.cleaner_funcs("njni$$free", "test/Init", Some("close"));
+    } else {
+        class.dispose_funcs("njni$$free", Some("close"));
+    }
+    class.export_native("njni$$free", "(JZ)V", true);
+    class.export_native("njni$$combine", "(Ltest/Example;)Ltest/Example;", false);
+    class.export_native("njni$$getValue", "(JI)I", false);
+    class.export_native_wrapper(
+        "getValue",
+        "(I)I",
+        "njni$$getValue",
+        "(JI)I",
+        true,
+        &ExportedMethodMeta {
+            access: MFlags::Public.into(),
+            generic_sig: "(I)I",
+            rust_name: RUST_NAME,
+            line: 10,
+            param_names: &["x"],
+            annotations: &AnnotationList::EMPTY,
+            param_annotations: &[],
+        },
+    );
+    data.add_exported_class(class);
+
+    data.verify().unwrap();
+    data
+}
+
+pub fn all_options() -> Vec<GenerationOptions> {
+    vec![
+        GenerationOptions { use_cleaner: false, target_version: JavaVersion::JAVA_8 },
+        GenerationOptions { use_cleaner: true, target_version: JavaVersion::JAVA_9 },
+        GenerationOptions { use_cleaner: true, target_version: JavaVersion::JAVA_11 },
+        GenerationOptions { use_cleaner: true, target_version: JavaVersion::JAVA_17 },
+    ]
+}
+
+pub fn get_class(data: &ClassData, name: &str) -> ClassFile {
+    let (_, bytes) = data.classes().find(|(x, _)| *x == name).unwrap();
+    ClassFile::parse(bytes).unwrap()
+}
@@ -0,0 +1,404 @@
+mod common;
+
+use common::*;
+use nekojni_utils::{
+    class_reader::*,
+    signatures::{Annotation, BasicType, ClassName, ElementValue, Type},
+    AnnotationList, CFlags, FFlags, GenerationOptions, JavaVersion, MFlags, NativeClassWrapper,
+};
+use std::io::ErrorKind;
+
+fn utf8_entries(class: &ClassFile) -> Vec<&str> {
+    class
+        .constant_pool
+        .entries()
+        .filter_map(|(_, entry)| match entry {
+            PoolEntry::Utf8(str) => Some(str.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn find_method<'a>(class: &'a ClassFile, name: &str, desc: &str) -> &'a MethodInfo {
+    let pool = &class.constant_pool;
+    class
+        .methods
+        .iter()
+        .find(|x| x.name(pool).unwrap() == name && x.descriptor(pool).unwrap() == desc)
+        .unwrap()
+}
+
+/// Decodes the contents of the annotation attributes of a class file.
+struct AnnotationReader<'a> {
+    pool: &'a ConstantPool,
+    data: &'a [u8],
+}
+impl<'a> AnnotationReader<'a> {
+    fn u8(&mut self) -> u8 {
+        let (value, rest) = self.data.split_first().unwrap();
+        self.data = rest;
+        *value
+    }
+    fn u16(&mut self) -> u16 {
+        u16::from_be_bytes([self.u8(), self.u8()])
+    }
+    fn constant(&mut self) -> &'a PoolEntry {
+        let index = self.u16();
+        self.pool.get(index).unwrap()
+    }
+    fn utf8(&mut self) -> &'a str {
+        let index = self.u16();
+        self.pool.utf8(index).unwrap()
+    }
+    fn class_name(&mut self) -> ClassName {
+        match Type::parse_jni(self.utf8()).unwrap() {
+            Type { basic_sig: BasicType::Class(class), array_dim: 0 } => class,
+            ty => panic!("{ty:?} is not a class type"),
+        }
+    }
+
+    fn parameter_annotations(&mut self) -> Vec<Vec<Annotation>> {
+        (0..self.u8()).map(|_| self.annotations()).collect()
+    }
+    fn annotations(&mut self) -> Vec<Annotation> {
+        (0..self.u16()).map(|_| self.annotation()).collect()
+    }
+    fn annotation(&mut self) -> Annotation {
+        let class = self.class_name();
+        let elements = (0..self.u16())
+            .map(|_| (self.utf8().to_string(), self.element_value()))
+            .collect();
+        Annotation { class, elements }
+    }
+    fn element_value(&mut self) -> ElementValue {
+        match self.u8() {
+            b'e' => ElementValue::Enum(self.class_name(), self.utf8().to_string()),
+            b'c' => ElementValue::Class(Type::parse_jni(self.utf8()).unwrap()),
+            b'@' => ElementValue::Annotation(self.annotation()),
+            b'[' => ElementValue::Array((0..self.u16()).map(|_| self.element_value()).collect()),
+            tag => match (tag, self.constant()) {
+                (b'Z', PoolEntry::Integer(v)) => ElementValue::Boolean(*v != 0),
+                (b'C', PoolEntry::Integer(v)) => {
+                    ElementValue::Char(char::from_u32(*v as u32).unwrap())
+                }
+                (b'I', PoolEntry::Integer(v)) => ElementValue::Int(*v),
+                (b'J', PoolEntry::Long(v)) => ElementValue::Long(*v),
+                (b'F', PoolEntry::Float(v)) => ElementValue::Float(*v),
+                (b'D', PoolEntry::Double(v)) => ElementValue::Double(*v),
+                (b's', PoolEntry::Utf8(v)) => ElementValue::String(v.clone()),
+                (tag, entry) => panic!("unexpected element value {} for {entry:?}", tag as char),
+            },
+        }
+    }
+}
+
+/// Decodes the attribute named `name` from a list of attributes, checking that all of its
+/// contents were read.
+fn read_attribute<T>(
+    pool: &ConstantPool,
+    attributes: &[AttributeInfo],
+    name: &str,
+    read: impl FnOnce(&mut AnnotationReader) -> T,
+) -> T {
+    let attribute = attributes
+        .iter()
+        .find(|x| x.name(pool).unwrap() == name)
+        .unwrap_or_else(|| panic!("{name} is missing"));
+    let mut reader = AnnotationReader { pool, data: &attribute.data };
+    let value = read(&mut reader);
+    assert!(reader.data.is_empty(), "{name} has trailing data");
+    value
+}
+
+#[test]
+fn test_round_trip() {
+    for options in all_options() {
+        let data = make_class_data(options);
+        for (name, bytes) in data.classes() {
+            let class = ClassFile::parse(bytes).unwrap();
+            assert_eq!(class.name().unwrap(), name);
+            assert_eq!(class.clone().into_vec(), bytes, "{name} changed when rewritten");
+            assert_eq!(ClassFile::parse(&class.into_vec()).unwrap().name().unwrap(), name);
+        }
+    }
+}
+
+#[test]
+fn test_read_generated_class() {
+    for options in all_options() {
+        let data = make_class_data(options);
+        let class = get_class(&data, CLASS_NAME);
+        let pool = &class.constant_pool;
+
+        assert_eq!(class.major_version, options.target_version.major_version());
+        assert!(class.flags().contains(CFlags::Public));
+        assert_eq!(class.super_name().unwrap(), Some("java/lang/Object"));
+        assert_eq!(class.interface_names().unwrap(), vec!["java/lang/AutoCloseable"]);
+
+        let id_field = class
+            .fields
+            .iter()
+            .find(|x| x.name(pool).unwrap() == "njni$$i")
+            .unwrap();
+        assert_eq!(id_field.descriptor(pool).unwrap(), "J");
+        assert_eq!(
+            id_field.flags(),
+            FFlags::Private | FFlags::Synthetic | FFlags::Final | FFlags::Transient
+        );
+
+        let free = find_method(&class, "njni$$free", "(JZ)V");
+        assert!(free.flags().is_superset(MFlags::Native | MFlags::Static));
+        assert_eq!(free.code(pool).unwrap(), None);
+
+        // `close` must call into the free function, directly or through `finalize`
+        let close = find_method(&class, "close", "()V");
+        let code = close.code(pool).unwrap().unwrap();
+        let calls: Vec<_> = code
+            .instructions()
+            .unwrap()
+            .into_iter()
+            .filter_map(|instr| match instr.operand {
+                Operand::Pool(index) if instr.name().starts_with("invoke") => {
+                    Some(pool.member_ref(index).unwrap())
+                }
+                Operand::InvokeInterface { method, .. } => Some(pool.member_ref(method).unwrap()),
+                _ => None,
+            })
+            .collect();
+        assert!(!calls.is_empty());
+        for (class_name, _, _) in &calls {
+            assert!(
+                *class_name == CLASS_NAME || class_name.starts_with("java/lang/ref/"),
+                "unexpected call to {class_name}",
+            );
+        }
+    }
+}
+
+#[test]
+fn test_rename_class() {
+    let options = GenerationOptions { use_cleaner: false, target_version: JavaVersion::JAVA_8 };
+    let data = make_class_data(options);
+    let mut class = get_class(&data, CLASS_NAME);
+
+    let count = class.constant_pool.rename_class(CLASS_NAME, "test/Renamed");
+    assert_ne!(count, 0);
+    assert_eq!(class.name().unwrap(), "test/Renamed");
+    for str in utf8_entries(&class) {
+        assert!(!str.contains(CLASS_NAME), "{str:?} still refers to the old name");
+    }
+
+    let class = ClassFile::parse(&class.into_vec()).unwrap();
+    find_method(&class, "njni$$combine", "(Ltest/Renamed;)Ltest/Renamed;");
+    assert_eq!(
+        class
+            .constant_pool
+            .clone()
+            .rename_class(CLASS_NAME, "test/Other"),
+        0
+    );
+}
+
+#[test]
+fn test_replace_utf8() {
+    let options = GenerationOptions::default();
+    let data = make_class_data(options);
+    let mut class = get_class(&data, CLASS_NAME);
+
+    assert_eq!(class.constant_pool.replace_utf8("example.rs", "other.rs"), 1);
+    assert_eq!(class.constant_pool.replace_utf8("example.rs", "other.rs"), 0);
+    // only exact matches are replaced
+    assert_eq!(class.constant_pool.replace_utf8("test", "other"), 0);
+    assert!(utf8_entries(&class).contains(&"other.rs"));
+}
+
+#[test]
+fn test_rewrite_precompiled() {
+    let data = make_class_data(GenerationOptions::default());
+
+    let loader = get_class(&data, "test/Loader");
+    assert_eq!(loader.name().unwrap(), "test/Loader");
+    let strings = utf8_entries(&loader);
+    for expected in ["test_crate", "1.2.3", "test/images"] {
+        assert!(strings.contains(&expected), "{expected:?} is missing from the loader");
+    }
+    for str in strings {
+        assert!(!str.contains("moe/lymia/nekojni/"), "{str:?} was not rewritten");
+        assert!(
+            !str.starts_with("[LIBRARY_") && !str.starts_with("[IMAGE_"),
+            "{str:?} was not replaced"
+        );
+    }
+
+    let init = get_class(&data, "test/Init");
+    let classes: Vec<_> = init
+        .constant_pool
+        .entries()
+        .filter(|(_, entry)| matches!(entry, PoolEntry::Class(_)))
+        .map(|(index, _)| init.constant_pool.class_name(index).unwrap())
+        .collect();
+    assert!(classes.contains(&"test/Init"));
+    assert!(classes.contains(&"test/Loader"));
+    assert!(classes.iter().all(|x| !x.starts_with("moe/lymia/nekojni/")));
+
+    let exception = get_class(&data, "test/TestException");
+    assert_eq!(exception.name().unwrap(), "test/TestException");
+}
+
+#[test]
+fn test_invalid_class() {
+    let data = make_class_data(GenerationOptions::default());
+    let (_, bytes) = data.classes().find(|(x, _)| *x == CLASS_NAME).unwrap();
+
+    let err = ClassFile::parse(&bytes[..bytes.len() - 1]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let mut trailing = bytes.to_vec();
+    trailing.push(0);
+    assert_eq!(ClassFile::parse(&trailing).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    let mut bad_magic = bytes.to_vec();
+    bad_magic[0] = 0;
+    assert_eq!(ClassFile::parse(&bad_magic).unwrap_err().kind(), ErrorKind::InvalidData);
+
+    let class = ClassFile::parse(bytes).unwrap();
+    let index = class.constant_pool.len() as u16;
+    assert!(class.constant_pool.get(index).is_err());
+    assert!(class.constant_pool.get(0).is_err());
+    assert!(class.constant_pool.utf8(class.this_class).is_err());
+}
+
+#[test]
+fn test_deprecation_note() {
+    let mut data = make_class_data(GenerationOptions::default());
+    assert!(data
+        .classes()
+        .all(|(x, _)| x != "moe/lymia/nekojni/DeprecationNote"));
+
+    let mut class = NativeClassWrapper::new(
+        CFlags::Public.into(),
+        "test/Other",
+        "java/lang/Object",
+        "njni$$i",
+        JavaVersion::JAVA_8,
+    );
+    let annotations = AnnotationList {
+        visible: &["@java.lang.Deprecated"],
+        invisible: &["@moe.lymia.nekojni.DeprecationNote(\"Use `other` instead.\")"],
+    };
+    class.export_native_direct(MFlags::Public.into(), "old", "()V", "()V", &annotations, &[]);
+    data.add_exported_class(class);
+    data.verify().unwrap();
+
+    let note = get_class(&data, "moe/lymia/nekojni/DeprecationNote");
+    assert!(note
+        .flags()
+        .is_superset(CFlags::Interface | CFlags::Abstract | CFlags::Annotation));
+    assert_eq!(note.interface_names().unwrap(), vec!["java/lang/annotation/Annotation"]);
+    find_method(&note, "value", "()Ljava/lang/String;");
+
+    let class = get_class(&data, "test/Other");
+    let pool = &class.constant_pool;
+    let attributes = &find_method(&class, "old", "()V").attributes;
+    let invisible =
+        read_attribute(pool, attributes, "RuntimeInvisibleAnnotations", |x| x.annotations());
+    assert_eq!(invisible, vec![Annotation {
+        class: ClassName::parse_jni("moe/lymia/nekojni/DeprecationNote").unwrap(),
+        elements: vec![(
+            "value".to_string(),
+            ElementValue::String("Use `other` instead.".to_string())
+        )],
+    }]);
+    assert!(attributes
+        .iter()
+        .any(|x| x.name(pool).unwrap() == "Deprecated"));
+}
+
+#[test]
+fn test_annotation_attributes() {
+    let mut data = make_class_data(GenerationOptions::default());
+    let mut class = NativeClassWrapper::new(
+        CFlags::Public.into(),
+        "test/Annotated",
+        "java/lang/Object",
+        "njni$$i",
+        JavaVersion::JAVA_8,
+    );
+    class.annotate(&AnnotationList {
+        visible: &["@test.Marker(name = \"x\", count = -3, flag = true, letter = 'c', big = 5L, \
+                    ratio = 1.5f, precise = 2.5, \
+                    policy = java.lang.annotation.RetentionPolicy.RUNTIME, \
+                    type = java.lang.String[].class, inner = @test.Inner(\"y\"), values = {1, 2})"],
+        invisible: &["@test.Hidden"],
+    });
+    let not_null = AnnotationList { visible: &["@test.NotNull"], invisible: &[] };
+    let named = AnnotationList { visible: &[], invisible: &["@test.Named(\"b\")"] };
+    class.export_native_direct(
+        MFlags::Public.into(),
+        "run",
+        "(ILjava/lang/String;)V",
+        "(ILjava/lang/String;)V",
+        &AnnotationList { visible: &["@test.Nullable"], invisible: &[] },
+        &[not_null, named],
+    );
+    data.add_exported_class(class);
+    data.verify().unwrap();
+
+    let class = get_class(&data, "test/Annotated");
+    let pool = &class.constant_pool;
+    let name = |name: &str| ClassName::parse_jni(name).unwrap();
+    let annotation = |class: &str, elements: Vec<(&str, ElementValue)>| Annotation {
+        class: name(class),
+        elements: elements
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    };
+
+    let visible =
+        read_attribute(pool, &class.attributes, "RuntimeVisibleAnnotations", |x| x.annotations());
+    let mut string_array = Type::from(name("java/lang/String"));
+    string_array.array_dim = 1;
+    assert_eq!(visible, vec![annotation("test/Marker", vec![
+        ("name", ElementValue::String("x".to_string())),
+        ("count", ElementValue::Int(-3)),
+        ("flag", ElementValue::Boolean(true)),
+        ("letter", ElementValue::Char('c')),
+        ("big", ElementValue::Long(5)),
+        ("ratio", ElementValue::Float(1.5)),
+        ("precise", ElementValue::Double(2.5)),
+        (
+            "policy",
+            ElementValue::Enum(name("java/lang/annotation/RetentionPolicy"), "RUNTIME".to_string()),
+        ),
+        ("type", ElementValue::Class(string_array)),
+        (
+            "inner",
+            ElementValue::Annotation(annotation("test/Inner", vec![(
+                "value",
+                ElementValue::String("y".to_string()),
+            )])),
+        ),
+        ("values", ElementValue::Array(vec![ElementValue::Int(1), ElementValue::Int(2)])),
+    ])]);
+    let invisible =
+        read_attribute(pool, &class.attributes, "RuntimeInvisibleAnnotations", |x| x.annotations());
+    assert_eq!(invisible, vec![annotation("test/Hidden", vec![])]);
+
+    let method = find_method(&class, "run", "(ILjava/lang/String;)V");
+    let attributes = &method.attributes;
+    let visible =
+        read_attribute(pool, attributes, "RuntimeVisibleAnnotations", |x| x.annotations());
+    assert_eq!(visible, vec![annotation("test/Nullable", vec![])]);
+    let visible = read_attribute(pool, attributes, "RuntimeVisibleParameterAnnotations", |x| {
+        x.parameter_annotations()
+    });
+    assert_eq!(visible, vec![vec![annotation("test/NotNull", vec![])], vec![]]);
+    let invisible = read_attribute(pool, attributes, "RuntimeInvisibleParameterAnnotations", |x| {
+        x.parameter_annotations()
+    });
+    assert_eq!(invisible, vec![vec![], vec![annotation("test/Named", vec![(
+        "value",
+        ElementValue::String("b".to_string()),
+    )])]]);
+}
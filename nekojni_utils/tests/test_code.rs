@@ -0,0 +1,648 @@
+use nekojni_utils::{
+    class_reader::*, verify_class, CFlags, ClassWriter, Constant, Handle, HandleKind, MFlags,
+    MethodWriter,
+};
+use std::{path::PathBuf, process::Command};
+
+const CLASS_NAME: &str = "test/Code";
+
+fn new_class() -> ClassWriter {
+    ClassWriter::new(CFlags::Public.into(), CLASS_NAME)
+}
+
+/// Prints the value pushed by `push` with `System.out.println`. `desc` is the descriptor of the
+/// `println` overload to call.
+fn println(code: &mut MethodWriter, desc: &str, push: impl FnOnce(&mut MethodWriter)) {
+    code.getstatic("java/lang/System", "out", "Ljava/io/PrintStream;");
+    push(code);
+    code.invokevirtual("java/io/PrintStream", "println", &format!("({desc})V"));
+}
+
+/// Checks that every branch, switch and exception handler in the class targets the start of an
+/// instruction, and returns the decoded code of each method.
+fn check_targets(class: &ClassFile) -> Vec<(String, Code)> {
+    let pool = &class.constant_pool;
+    let mut methods = Vec::new();
+    for method in &class.methods {
+        let name = method.name(pool).unwrap().to_string();
+        let code = match method.code(pool).unwrap() {
+            Some(code) => code,
+            None => continue,
+        };
+        let instructions = code.instructions().unwrap();
+        let is_start = |offset: i64| instructions.iter().any(|x| x.offset as i64 == offset);
+        for instr in &instructions {
+            let targets = match &instr.operand {
+                Operand::Branch(target) => vec![*target],
+                Operand::TableSwitch { default, targets, .. } => {
+                    let mut vec = vec![*default];
+                    vec.extend(targets);
+                    vec
+                }
+                Operand::LookupSwitch { default, pairs } => {
+                    let mut vec = vec![*default];
+                    vec.extend(pairs.iter().map(|x| x.1));
+                    vec
+                }
+                _ => continue,
+            };
+            for target in targets {
+                assert!(is_start(target), "{name}: {} branches to {target}", instr.name());
+            }
+        }
+        for entry in &code.exception_table {
+            assert!(is_start(entry.start_pc as i64) && is_start(entry.handler_pc as i64));
+            assert!(entry.end_pc as usize <= code.code.len());
+        }
+        methods.push((name, code));
+    }
+    methods
+}
+
+/// Adds a `main` method generated by `main` to the class, and checks the class with the project's
+/// verifier. If a JVM is installed, the class is also run with `java -Xverify:all`, and the lines
+/// it prints are returned.
+fn run(
+    test: &str,
+    mut class: ClassWriter,
+    main: impl FnOnce(&mut MethodWriter),
+) -> (Vec<(String, Code)>, Option<Vec<String>>) {
+    {
+        let method =
+            class.method(MFlags::Public | MFlags::Static, "main", "([Ljava/lang/String;)V");
+        let mut code = method.code();
+        main(&mut code);
+        code.vreturn();
+    }
+    let bytes = class.into_vec();
+    verify_class(CLASS_NAME, &bytes).unwrap();
+    let methods = check_targets(&ClassFile::parse(&bytes).unwrap());
+
+    let java = match std::env::var_os("JAVA_HOME") {
+        Some(home) => PathBuf::from(home).join("bin/java"),
+        None => PathBuf::from("java"),
+    };
+    let dir = std::env::temp_dir().join(format!("nekojni_test_code_{}_{test}", std::process::id()));
+    let path = dir.join(format!("{CLASS_NAME}.class"));
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, &bytes).unwrap();
+    let output = Command::new(java)
+        .arg("-Xverify:all")
+        .arg("-cp")
+        .arg(&dir)
+        .arg(CLASS_NAME.replace('/', "."))
+        .output();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let output = match output {
+        Ok(output) => output,
+        Err(_) => {
+            eprintln!("`java` could not be run, only the project's verifier was used.");
+            return (methods, None);
+        }
+    };
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "java failed:\n{stderr}");
+    let lines = String::from_utf8(output.stdout).unwrap();
+    (methods, Some(lines.lines().map(str::to_string).collect()))
+}
+
+fn find_code<'a>(methods: &'a [(String, Code)], name: &str) -> &'a Code {
+    &methods.iter().find(|x| x.0 == name).unwrap().1
+}
+
+fn find_instructions(code: &Code, name: &str) -> Vec<Instruction> {
+    code.instructions()
+        .unwrap()
+        .into_iter()
+        .filter(|x| x.name() == name)
+        .collect()
+}
+
+#[test]
+fn test_branches() {
+    let mut class = new_class();
+    {
+        // static int sum(int n) { int total = 0; for (int i = 0; i < n; i++) total += i; }
+        let method = class.method(MFlags::Static.into(), "sum", "(I)I");
+        let mut code = method.code();
+        let (head, end) = (code.new_label(), code.new_label());
+        code.iconst(0).istore(1).iconst(0).istore(2);
+        code.label(head);
+        code.iload(2).iload(0).if_icmpge(end);
+        code.iload(1)
+            .iload(2)
+            .iadd()
+            .istore(1)
+            .iinc(2, 1)
+            .goto(head);
+        code.label(end);
+        code.iload(1).ireturn();
+    }
+    {
+        // static int abs(int x) { return x >= 0 ? x : -x; }
+        let method = class.method(MFlags::Static.into(), "abs", "(I)I");
+        let mut code = method.code();
+        let (positive, end) = (code.new_label(), code.new_label());
+        code.iload(0).ifge(positive);
+        code.iload(0).ineg().goto(end);
+        code.label(positive);
+        code.iload(0);
+        code.label(end);
+        code.ireturn();
+    }
+    {
+        // static void describe(String s) { System.out.println(s == null ? "null" : s); }
+        let method = class.method(MFlags::Static.into(), "describe", "(Ljava/lang/String;)V");
+        let mut code = method.code();
+        let not_null = code.new_label();
+        code.aload(0).ifnonnull(not_null);
+        code.aconst_str("null").astore(0);
+        code.label(not_null);
+        println(&mut code, "Ljava/lang/String;", |code| {
+            code.aload(0);
+        });
+        code.vreturn();
+    }
+
+    let (methods, output) = run("branches", class, |code| {
+        for n in [0, 1, 10] {
+            println(code, "I", |code| {
+                code.iconst(n).invokestatic(CLASS_NAME, "sum", "(I)I");
+            });
+        }
+        for x in [5, -7] {
+            println(code, "I", |code| {
+                code.iconst(x).invokestatic(CLASS_NAME, "abs", "(I)I");
+            });
+        }
+        code.aconst_str("text")
+            .invokestatic(CLASS_NAME, "describe", "(Ljava/lang/String;)V");
+        code.aconst_null()
+            .invokestatic(CLASS_NAME, "describe", "(Ljava/lang/String;)V");
+    });
+
+    // the loop branches backwards to its head
+    let sum = find_code(&methods, "sum");
+    let goto = &find_instructions(sum, "goto")[0];
+    assert!(matches!(goto.operand, Operand::Branch(target) if target < goto.offset as i64));
+    assert_eq!(find_instructions(sum, "iinc")[0].operand, Operand::Iinc { local: 2, delta: 1 });
+
+    if let Some(output) = output {
+        assert_eq!(output, ["0", "0", "45", "5", "7", "text", "null"]);
+    }
+}
+
+#[test]
+fn test_try_catch() {
+    let mut class = new_class();
+    {
+        // static int parse(String s), returning -1 if `s` is not a number, and -2 for any other
+        // exception
+        let method = class.method(MFlags::Static.into(), "parse", "(Ljava/lang/String;)I");
+        let mut code = method.code();
+        let (start, end) = (code.new_label(), code.new_label());
+        let (invalid, other) = (code.new_label(), code.new_label());
+        code.try_catch(start, end, invalid, Some("java/lang/NumberFormatException"))
+            .try_catch(start, end, other, None);
+        code.label(start);
+        code.aload(0)
+            .invokevirtual("java/lang/String", "length", "()I")
+            .pop()
+            .aload(0)
+            .invokestatic("java/lang/Integer", "parseInt", "(Ljava/lang/String;)I");
+        code.label(end);
+        code.ireturn();
+        code.label(invalid);
+        code.pop().iconst(-1).ireturn();
+        code.label(other);
+        code.pop().iconst(-2).ireturn();
+    }
+
+    let (methods, output) = run("try_catch", class, |code| {
+        for arg in [Some("12"), Some("x"), None] {
+            println(code, "I", |code| {
+                match arg {
+                    Some(arg) => code.aconst_str(arg),
+                    None => code.aconst_null(),
+                };
+                code.invokestatic(CLASS_NAME, "parse", "(Ljava/lang/String;)I");
+            });
+        }
+    });
+
+    let parse = find_code(&methods, "parse");
+    assert_eq!(parse.exception_table.len(), 2);
+    let (specific, any) = (parse.exception_table[0], parse.exception_table[1]);
+    assert_eq!((specific.start_pc, specific.end_pc), (any.start_pc, any.end_pc));
+    assert_ne!(specific.handler_pc, any.handler_pc);
+    assert_ne!(specific.catch_type, 0);
+    assert_eq!(any.catch_type, 0);
+
+    if let Some(output) = output {
+        assert_eq!(output, ["12", "-1", "-2"]);
+    }
+}
+
+#[test]
+fn test_switches() {
+    let mut class = new_class();
+    // the number of bytes before a switch determines its padding, so every alignment is tested
+    for pad in 0..4 {
+        {
+            let method = class.method(MFlags::Static.into(), &format!("table{pad}"), "(I)I");
+            let mut code = method.code();
+            for _ in 0..pad {
+                code.nop();
+            }
+            let default = code.new_label();
+            let targets = [code.new_label(), code.new_label(), code.new_label()];
+            code.iload(0).tableswitch(1, default, &targets);
+            for (i, target) in targets.into_iter().enumerate() {
+                code.label(target);
+                code.iconst(10 * (i as i32 + 1)).ireturn();
+            }
+            code.label(default);
+            code.iconst(-1).ireturn();
+        }
+        {
+            let method = class.method(MFlags::Static.into(), &format!("lookup{pad}"), "(I)I");
+            let mut code = method.code();
+            for _ in 0..pad {
+                code.nop();
+            }
+            let (default, a, b) = (code.new_label(), code.new_label(), code.new_label());
+            // keys are sorted when the instruction is written
+            code.iload(0)
+                .lookupswitch(default, &[(100, a), (-5, b), (7, a)]);
+            code.label(a);
+            code.iconst(1).ireturn();
+            code.label(b);
+            code.iconst(2).ireturn();
+            code.label(default);
+            code.iconst(-1).ireturn();
+        }
+    }
+
+    let (methods, output) = run("switches", class, |code| {
+        for pad in 0..4 {
+            for x in [0, 1, 2, 3, 4] {
+                println(code, "I", |code| {
+                    code.iconst(x)
+                        .invokestatic(CLASS_NAME, &format!("table{pad}"), "(I)I");
+                });
+            }
+            for x in [-5, 7, 100, 8] {
+                println(code, "I", |code| {
+                    code.iconst(x)
+                        .invokestatic(CLASS_NAME, &format!("lookup{pad}"), "(I)I");
+                });
+            }
+        }
+    });
+
+    for pad in 0..4 {
+        let table = find_code(&methods, &format!("table{pad}"));
+        let instr = &find_instructions(table, "tableswitch")[0];
+        assert_eq!(instr.offset, pad + 1);
+        match &instr.operand {
+            Operand::TableSwitch { low, high, targets, .. } => {
+                assert_eq!((*low, *high, targets.len()), (1, 3, 3));
+            }
+            operand => panic!("unexpected operand {operand:?}"),
+        }
+
+        let lookup = find_code(&methods, &format!("lookup{pad}"));
+        let instr = &find_instructions(lookup, "lookupswitch")[0];
+        match &instr.operand {
+            Operand::LookupSwitch { pairs, .. } => {
+                let keys: Vec<_> = pairs.iter().map(|x| x.0).collect();
+                assert_eq!(keys, [-5, 7, 100]);
+                assert_eq!(pairs[1].1, pairs[2].1);
+            }
+            operand => panic!("unexpected operand {operand:?}"),
+        }
+    }
+
+    if let Some(output) = output {
+        let expected: Vec<_> = (0..4)
+            .flat_map(|_| ["-1", "10", "20", "30", "-1", "2", "1", "1", "-1"])
+            .collect();
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn test_wide_locals() {
+    let mut class = new_class();
+    {
+        // static long wide(long a, double b, int c) { long x = a; if (c != 0) x += (long) b; }
+        let method = class.method(MFlags::Static.into(), "wide", "(JDI)J");
+        let mut code = method.code();
+        let skip = code.new_label();
+        code.lload(0).lstore(5);
+        code.iload(4).ifeq(skip);
+        code.dload(2).d2l().lload(5).ladd().lstore(5);
+        code.label(skip);
+        code.lload(5).lreturn();
+    }
+    {
+        // static double average(double a, long b), keeping a double and a long live across a loop
+        let method = class.method(MFlags::Static.into(), "average", "(DJ)D");
+        let mut code = method.code();
+        let (head, end) = (code.new_label(), code.new_label());
+        code.dconst(0.0).dstore(4).lconst(0).lstore(6);
+        code.label(head);
+        code.lload(6).lload(2).lcmp().ifge(end);
+        code.dload(4).dload(0).dadd().dstore(4);
+        code.lload(6).lconst(1).ladd().lstore(6);
+        code.goto(head);
+        code.label(end);
+        code.dload(4).lload(2).l2d().ddiv().dreturn();
+    }
+
+    let (methods, output) = run("wide_locals", class, |code| {
+        for c in [0, 1] {
+            println(code, "J", |code| {
+                code.lconst(1 << 40)
+                    .dconst(2.5)
+                    .iconst(c)
+                    .invokestatic(CLASS_NAME, "wide", "(JDI)J");
+            });
+        }
+        println(code, "D", |code| {
+            code.dconst(1.5)
+                .lconst(4)
+                .invokestatic(CLASS_NAME, "average", "(DJ)D");
+        });
+    });
+
+    // parameters and locals are counted in slots, with two slots for each long and double
+    assert_eq!(find_code(&methods, "wide").max_locals, 7);
+    assert_eq!(find_code(&methods, "average").max_locals, 8);
+    assert_eq!(find_code(&methods, "average").max_stack, 4);
+
+    if let Some(output) = output {
+        assert_eq!(output, ["1099511627776", "1099511627778", "1.5"]);
+    }
+}
+
+#[test]
+fn test_labels_at_same_offset() {
+    let mut class = new_class();
+    {
+        // branches reach `first` with a `String` in local 1, and `second` with an `Integer`, so
+        // the frame at their shared offset must be the one merged at `second`
+        let method = class.method(MFlags::Static.into(), "pick", "(I)I");
+        let mut code = method.code();
+        let (first, second) = (code.new_label(), code.new_label());
+        code.aconst_str("text").astore(1);
+        code.iload(0).ifeq(first);
+        code.iconst(1)
+            .invokestatic("java/lang/Integer", "valueOf", "(I)Ljava/lang/Integer;")
+            .astore(1);
+        code.iload(0).ifgt(second);
+        code.iconst(2).ireturn();
+        code.label(first);
+        code.label(second);
+        code.iload(0).ireturn();
+    }
+
+    let (methods, output) = run("labels_at_same_offset", class, |code| {
+        for x in [0, 1, -1] {
+            println(code, "I", |code| {
+                code.iconst(x).invokestatic(CLASS_NAME, "pick", "(I)I");
+            });
+        }
+    });
+
+    let pick = find_code(&methods, "pick");
+    let ifeq = &find_instructions(pick, "ifeq")[0];
+    let ifgt = &find_instructions(pick, "ifgt")[0];
+    assert_eq!(ifeq.operand, ifgt.operand);
+
+    if let Some(output) = output {
+        assert_eq!(output, ["0", "1", "2"]);
+    }
+}
+
+/// Returns the size in bytes of each instruction with the given name.
+fn instruction_sizes(code: &Code, name: &str) -> Vec<u32> {
+    let instructions = code.instructions().unwrap();
+    let mut sizes = Vec::new();
+    for (i, instr) in instructions.iter().enumerate() {
+        if instr.name() == name {
+            let end = instructions
+                .get(i + 1)
+                .map_or(code.code.len() as u32, |x| x.offset);
+            sizes.push(end - instr.offset);
+        }
+    }
+    sizes
+}
+
+#[test]
+fn test_merge_object_types() {
+    let mut class = new_class();
+    {
+        // static Object pick(boolean b) { return b ? "text" : Integer.valueOf(1); }
+        let method = class.method(MFlags::Static.into(), "pick", "(Z)Ljava/lang/Object;");
+        let mut code = method.code();
+        let (other, end) = (code.new_label(), code.new_label());
+        code.iload(0).ifeq(other);
+        code.aconst_str("text").goto(end);
+        code.label(other);
+        code.iconst(1)
+            .invokestatic("java/lang/Integer", "valueOf", "(I)Ljava/lang/Integer;");
+        code.label(end);
+        code.invokevirtual("java/lang/Object", "toString", "()Ljava/lang/String;")
+            .areturn();
+    }
+
+    let (_, output) = run("merge_object_types", class, |code| {
+        for b in [1, 0] {
+            println(code, "Ljava/lang/Object;", |code| {
+                code.iconst(b)
+                    .invokestatic(CLASS_NAME, "pick", "(Z)Ljava/lang/Object;");
+            });
+        }
+    });
+
+    if let Some(output) = output {
+        assert_eq!(output, ["text", "1"]);
+    }
+}
+
+#[test]
+fn test_arrays() {
+    let mut class = new_class();
+    {
+        // static long longs() { long[] x = new long[3]; x[2] = 7; return x[2] + x.length; }
+        let method = class.method(MFlags::Static.into(), "longs", "()J");
+        let mut code = method.code();
+        code.iconst(3).newarray("J").astore(0);
+        code.aload(0).iconst(2).lconst(7).lastore();
+        code.aload(0).iconst(2).laload();
+        code.aload(0).arraylength().i2l().ladd().lreturn();
+    }
+    {
+        // static int grid() { int[][] x = new int[2][5]; x[1][4] = 9; return x[1][4] + x[0].length; }
+        let method = class.method(MFlags::Static.into(), "grid", "()I");
+        let mut code = method.code();
+        code.iconst(2).iconst(5).multianewarray("[[I", 2).astore(0);
+        code.aload(0)
+            .iconst(1)
+            .aaload()
+            .iconst(4)
+            .iconst(9)
+            .iastore();
+        code.aload(0).iconst(1).aaload().iconst(4).iaload();
+        code.aload(0)
+            .iconst(0)
+            .aaload()
+            .arraylength()
+            .iadd()
+            .ireturn();
+    }
+
+    let (methods, output) = run("arrays", class, |code| {
+        println(code, "J", |code| {
+            code.invokestatic(CLASS_NAME, "longs", "()J");
+        });
+        println(code, "I", |code| {
+            code.invokestatic(CLASS_NAME, "grid", "()I");
+        });
+    });
+
+    let newarray = &find_instructions(find_code(&methods, "longs"), "newarray")[0];
+    assert_eq!(newarray.operand, Operand::NewArray { atype: 11 });
+    let multianewarray = &find_instructions(find_code(&methods, "grid"), "multianewarray")[0];
+    assert!(matches!(multianewarray.operand, Operand::MultiANewArray { dims: 2, .. }));
+
+    if let Some(output) = output {
+        assert_eq!(output, ["10", "14"]);
+    }
+}
+
+#[test]
+fn test_iinc() {
+    let mut class = new_class();
+    {
+        // increments a local with a small index and delta, then one that needs the `wide` prefix
+        let method = class.method(MFlags::Static.into(), "count", "(I)I");
+        let mut code = method.code();
+        code.iinc(0, 5);
+        code.iload(0).istore(300);
+        code.iinc(300, 1000).iinc(300, -1);
+        code.iload(300).ireturn();
+    }
+
+    let (methods, output) = run("iinc", class, |code| {
+        println(code, "I", |code| {
+            code.iconst(1).invokestatic(CLASS_NAME, "count", "(I)I");
+        });
+    });
+
+    let count = find_code(&methods, "count");
+    assert_eq!(count.max_locals, 301);
+    let operands: Vec<_> = find_instructions(count, "iinc")
+        .into_iter()
+        .map(|x| x.operand)
+        .collect();
+    assert_eq!(operands, [
+        Operand::Iinc { local: 0, delta: 5 },
+        Operand::Iinc { local: 300, delta: 1000 },
+        Operand::Iinc { local: 300, delta: -1 },
+    ]);
+    assert_eq!(instruction_sizes(count, "iinc"), [3, 6, 6]);
+
+    if let Some(output) = output {
+        assert_eq!(output, ["1005"]);
+    }
+}
+
+#[test]
+fn test_invoke_slots() {
+    let mut class = new_class();
+    {
+        // static long skip() { return new Random(0).longs(5).skip(2).count(); }
+        let method = class.method(MFlags::Static.into(), "skip", "()J");
+        let mut code = method.code();
+        code.new_object("java/util/Random")
+            .dup()
+            .lconst(0)
+            .invokespecial("java/util/Random", "<init>", "(J)V");
+        code.lconst(5).invokevirtual(
+            "java/util/Random",
+            "longs",
+            "(J)Ljava/util/stream/LongStream;",
+        );
+        code.lconst(2).invokeinterface(
+            "java/util/stream/LongStream",
+            "skip",
+            "(J)Ljava/util/stream/LongStream;",
+        );
+        code.invokeinterface("java/util/stream/LongStream", "count", "()J")
+            .lreturn();
+    }
+    {
+        // static String concat(long a, String b) { return a + "-" + b; }, using the same
+        // `invokedynamic` call javac emits for string concatenation
+        let method = class.method(
+            MFlags::Static.into(),
+            "concat",
+            "(JLjava/lang/String;)Ljava/lang/String;",
+        );
+        let mut code = method.code();
+        let bootstrap = Handle {
+            kind: HandleKind::InvokeStatic,
+            class: "java/lang/invoke/StringConcatFactory".to_string(),
+            name: "makeConcatWithConstants".to_string(),
+            desc: "(Ljava/lang/invoke/MethodHandles$Lookup;Ljava/lang/String;\
+                   Ljava/lang/invoke/MethodType;Ljava/lang/String;[Ljava/lang/Object;)\
+                   Ljava/lang/invoke/CallSite;"
+                .to_string(),
+            interface: false,
+        };
+        code.lload(0).aload(2);
+        code.invokedynamic(
+            &bootstrap,
+            &[Constant::String("\u{1}-\u{1}".to_string())],
+            "makeConcatWithConstants",
+            "(JLjava/lang/String;)Ljava/lang/String;",
+        );
+        code.areturn();
+    }
+
+    let (methods, output) = run("invoke_slots", class, |code| {
+        println(code, "J", |code| {
+            code.invokestatic(CLASS_NAME, "skip", "()J");
+        });
+        println(code, "Ljava/lang/String;", |code| {
+            code.lconst(5).aconst_str("abc").invokestatic(
+                CLASS_NAME,
+                "concat",
+                "(JLjava/lang/String;)Ljava/lang/String;",
+            );
+        });
+    });
+
+    // the argument count of `invokeinterface` is in slots, including the receiver
+    let skip = find_code(&methods, "skip");
+    let counts: Vec<_> = find_instructions(skip, "invokeinterface")
+        .into_iter()
+        .map(|x| match x.operand {
+            Operand::InvokeInterface { count, .. } => count,
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(counts, [3, 1]);
+    assert_eq!(skip.max_stack, 4);
+    let concat = find_code(&methods, "concat");
+    assert_eq!(find_instructions(concat, "invokedynamic").len(), 1);
+    assert_eq!(concat.max_stack, 3);
+
+    if let Some(output) = output {
+        assert_eq!(output, ["3", "5-abc"]);
+    }
+}
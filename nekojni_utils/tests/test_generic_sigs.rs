@@ -0,0 +1,117 @@
+use nekojni_utils::signatures::*;
+
+fn make_test_generic_sigs() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("(Ljava/util/List<Ljava/lang/String;>;I)V", "(Ljava/util/List;I)V"),
+        ("<T:Ljava/lang/Object;>(TT;)TT;", "(Ljava/lang/Object;)Ljava/lang/Object;"),
+        ("<T::Ljava/lang/Comparable<-TT;>;>([TT;)V", "([Ljava/lang/Comparable;)V"),
+        (
+            "<T:Ljava/lang/Number;U:TT;>(TU;)[[TU;",
+            "(Ljava/lang/Number;)[[Ljava/lang/Number;",
+        ),
+        (
+            "(Ljava/util/Map<+Ljava/lang/Number;*>;)[Ljava/util/List<[I>;",
+            "(Ljava/util/Map;)[Ljava/util/List;",
+        ),
+        (
+            "(Ljava/util/Map<TK;TV;>.Entry<TK;TV;>;)TV;",
+            "(Ljava/util/Map$Entry;)Ljava/lang/Object;",
+        ),
+        ("<E:Ljava/lang/Exception;>()V^TE;^Ljava/io/IOException;", "()V"),
+    ]
+}
+
+fn make_test_generic_class_sigs() -> Vec<(&'static str, &'static str, Vec<&'static str>)> {
+    vec![
+        ("Ljava/lang/Object;", "java/lang/Object", vec![]),
+        (
+            "<T:Ljava/lang/Object;>Ljava/lang/Object;Ljava/lang/Iterable<TT;>;",
+            "java/lang/Object",
+            vec!["java/lang/Iterable"],
+        ),
+        (
+            "Ljava/util/AbstractList<Ljava/lang/String;>;Ljava/util/RandomAccess;\
+             Ljava/util/Map<TK;TV;>.Entry<TK;TV;>;",
+            "java/util/AbstractList",
+            vec!["java/util/RandomAccess", "java/util/Map$Entry"],
+        ),
+    ]
+}
+
+#[test]
+fn test_generic_sigs_round_trip() {
+    for (generic_sig, _) in make_test_generic_sigs() {
+        let sig = GenericMethodSig::parse_generic(generic_sig).unwrap();
+        assert_eq!(&sig.display_generic().to_string(), generic_sig);
+    }
+    for (generic_sig, _, _) in make_test_generic_class_sigs() {
+        let sig = GenericClassSig::parse_generic(generic_sig).unwrap();
+        assert_eq!(&sig.display_generic().to_string(), generic_sig);
+    }
+}
+
+#[test]
+fn test_generic_sigs_erase() {
+    for (generic_sig, jni_sig) in make_test_generic_sigs() {
+        let sig = GenericMethodSig::parse_generic(generic_sig).unwrap();
+        assert_eq!(sig.erase(), MethodSig::parse_jni(jni_sig).unwrap());
+        assert_eq!(&sig.erase().display_jni().to_string(), jni_sig);
+    }
+    for (generic_sig, super_class, interfaces) in make_test_generic_class_sigs() {
+        let (erased_super, erased_interfaces) =
+            GenericClassSig::parse_generic(generic_sig).unwrap().erase();
+        assert_eq!(&erased_super.display_jni().to_string(), super_class);
+        let erased_interfaces: Vec<_> = erased_interfaces
+            .iter()
+            .map(|x| x.display_jni().to_string())
+            .collect();
+        assert_eq!(erased_interfaces, interfaces);
+    }
+}
+
+#[test]
+fn test_non_generic_sigs() {
+    // descriptors are valid generic signatures, and erase to themselves
+    for jni_sig in [
+        "(BSIJFDZC)V",
+        "()Ljava/lang/String;",
+        "(B[SLjava/lang/String;)[Ljava/lang/String;",
+    ] {
+        let sig = MethodSig::parse_jni(jni_sig).unwrap();
+        let generic = GenericMethodSig::parse_generic(jni_sig).unwrap();
+        assert_eq!(
+            generic,
+            GenericMethodSig::new(
+                sig.ret_ty.clone().into(),
+                sig.params
+                    .iter()
+                    .cloned()
+                    .map(GenericType::from)
+                    .collect::<Vec<_>>()
+            )
+        );
+        assert_eq!(generic.erase(), sig);
+        assert_eq!(&generic.display_generic().to_string(), jni_sig);
+    }
+}
+
+#[test]
+fn test_invalid_generic_sigs() {
+    for generic_sig in [
+        "",
+        "(I",
+        "(V)V",
+        "()",
+        "(Ljava/util/List<>;)V",
+        "(Ljava/util/List<Ljava/lang/String;)V",
+        "<>()V",
+        "<T>()V",
+        "(TT)V",
+        "()V^I",
+    ] {
+        assert!(GenericMethodSig::parse_generic(generic_sig).is_err(), "{generic_sig:?} parsed");
+    }
+    for generic_sig in ["", "I", "<T:Ljava/lang/Object;>", "Ljava/lang/Object"] {
+        assert!(GenericClassSig::parse_generic(generic_sig).is_err(), "{generic_sig:?} parsed");
+    }
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use common::*;
+use nekojni_utils::{
+    class_reader::*, verify_class, AnnotationList, CFlags, ExportedMethodMeta, FFlags,
+    GenerationOptions, JavaVersion, MFlags, NativeClassWrapper, VerifyError,
+};
+
+/// Generates the test class, changes the code of `getValue`, and verifies the result.
+fn verify_modified(modify: impl FnOnce(&ConstantPool, &mut Code)) -> Box<VerifyError> {
+    let mut data = make_class_data(GenerationOptions::default());
+    let mut class = get_class(&data, CLASS_NAME);
+
+    let pool = class.constant_pool.clone();
+    let method = class
+        .methods
+        .iter_mut()
+        .find(|x| x.name(&pool).unwrap() == "getValue")
+        .unwrap();
+    let attribute = method
+        .attributes
+        .iter_mut()
+        .find(|x| x.name(&pool).unwrap() == "Code")
+        .unwrap();
+    let mut code = Code::parse(&attribute.data).unwrap();
+    modify(&pool, &mut code);
+    attribute.data = write_code(&code);
+
+    let bytes = class.into_vec();
+    let err = verify_class(CLASS_NAME, &bytes).unwrap_err();
+    assert_eq!(err.origin, None);
+
+    data.add_class(CLASS_NAME, bytes);
+    let err = data.verify().unwrap_err();
+    assert_eq!(err.class, CLASS_NAME);
+    assert_eq!(err.method, Some(("getValue".to_string(), "(I)I".to_string())));
+    assert_eq!(err.origin.as_deref(), Some(RUST_NAME));
+    assert!(err.to_string().contains(RUST_NAME), "{err}");
+    err
+}
+
+fn write_code(code: &Code) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&code.max_stack.to_be_bytes());
+    data.extend_from_slice(&code.max_locals.to_be_bytes());
+    data.extend_from_slice(&(code.code.len() as u32).to_be_bytes());
+    data.extend_from_slice(&code.code);
+    assert!(code.exception_table.is_empty());
+    data.extend_from_slice(&0u16.to_be_bytes());
+    // the stack map table and line numbers are not needed for a method without branches
+    data.extend_from_slice(&0u16.to_be_bytes());
+    data
+}
+
+fn find_instruction(code: &Code, name: &str) -> Instruction {
+    code.instructions()
+        .unwrap()
+        .into_iter()
+        .find(|x| x.name() == name)
+        .unwrap()
+}
+
+#[test]
+fn test_generated_classes() {
+    for options in all_options() {
+        let data = make_class_data(options);
+        for (name, bytes) in data.classes() {
+            verify_class(name, bytes).unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_from_rust_constructor() {
+    const THREAD_NAME: &str = "test/RustThread";
+    for options in all_options() {
+        let mut data = make_class_data(options);
+        let mut class = NativeClassWrapper::new(
+            CFlags::Public.into(),
+            THREAD_NAME,
+            "java/lang/Thread",
+            "njni$$i",
+            options.target_version,
+        );
+        if options.use_cleaner {
+            class.cleaner_funcs("njni$$free", "test/Init", None);
+        } else {
+            class.dispose_funcs("njni$$free", None);
+        }
+        class.from_rust_constructor(&["init"]);
+        data.add_exported_class(class);
+        data.verify().unwrap();
+        for (name, bytes) in data.classes() {
+            verify_class(name, bytes).unwrap();
+        }
+
+        // the constructor must call the constructor of the superclass
+        let class = get_class(&data, THREAD_NAME);
+        let pool = &class.constant_pool;
+        let sig = "(JLtest/RustThread$NJni$$FromRust;)V";
+        let method = class
+            .methods
+            .iter()
+            .find(|x| x.name(pool).unwrap() == "<init>" && x.descriptor(pool).unwrap() == sig)
+            .unwrap();
+        assert!(method.flags().contains(MFlags::Synthetic));
+        let code = method.code(pool).unwrap().unwrap();
+        let index = match find_instruction(&code, "invokespecial").operand {
+            Operand::Pool(index) => index,
+            operand => panic!("unexpected operand {operand:?}"),
+        };
+        assert_eq!(pool.member_ref(index).unwrap(), ("java/lang/Thread", "<init>", "()V"));
+        get_class(&data, "test/RustThread$NJni$$FromRust");
+    }
+}
+
+#[test]
+fn test_unmodified_code() {
+    let mut data = make_class_data(GenerationOptions::default());
+    let bytes = get_class(&data, CLASS_NAME).into_vec();
+    data.add_class(CLASS_NAME, bytes);
+    data.verify().unwrap();
+}
+
+#[test]
+fn test_bad_stack_depth() {
+    let err = verify_modified(|_, code| code.max_stack = 1);
+    assert!(err.message.contains("max_stack"), "{err}");
+}
+
+#[test]
+fn test_wrong_return_type() {
+    let err = verify_modified(|_, code| {
+        let ireturn = find_instruction(code, "ireturn");
+        code.code[ireturn.offset as usize] = 0xb0; // areturn
+    });
+    assert_eq!(err.instruction.map(|x| x.1), Some("areturn"));
+}
+
+#[test]
+fn test_dangling_pool_index() {
+    let err = verify_modified(|pool, code| {
+        let invoke = find_instruction(code, "invokevirtual");
+        let offset = invoke.offset as usize;
+        code.code[offset + 1..offset + 3].copy_from_slice(&(pool.len() as u16).to_be_bytes());
+    });
+    assert_eq!(err.instruction.map(|x| x.1), Some("invokevirtual"));
+    assert!(err.message.contains("Constant pool"), "{err}");
+}
+
+#[test]
+fn test_invalid_class() {
+    let err = verify_class(CLASS_NAME, &[0xCA, 0xFE, 0xBA, 0xBE]).unwrap_err();
+    assert_eq!(err.method, None);
+    assert_eq!(err.instruction, None);
+
+    let data = make_class_data(GenerationOptions::default());
+    let (_, bytes) = data.classes().find(|(x, _)| *x == CLASS_NAME).unwrap();
+    let err = verify_class("test/WrongName", bytes).unwrap_err();
+    assert_eq!(err.class, "test/WrongName");
+    assert_eq!(err.method, None);
+}
+
+#[test]
+fn test_invalid_generic_signatures() {
+    let meta = |generic_sig| ExportedMethodMeta {
+        access: MFlags::Public.into(),
+        generic_sig,
+        rust_name: RUST_NAME,
+        line: 0,
+        param_names: &[],
+        annotations: &AnnotationList::EMPTY,
+        param_annotations: &[],
+    };
+    let check = |add: &dyn Fn(&mut NativeClassWrapper)| {
+        let mut data = make_class_data(GenerationOptions::default());
+        let mut class = NativeClassWrapper::new(
+            CFlags::Public.into(),
+            "test/Other",
+            "java/lang/Object",
+            "njni$$i",
+            JavaVersion::JAVA_8,
+        );
+        add(&mut class);
+        data.add_exported_class(class);
+        let err = data.verify().unwrap_err();
+        assert_eq!(err.class, "test/Other");
+        err
+    };
+
+    let err = check(&|class| {
+        class.export_native_wrapper("get", "(I)I", "njni$$get", "(I)I", false, &meta("(I"));
+    });
+    assert_eq!(err.method, Some(("get".to_string(), "(I)I".to_string())));
+    assert_eq!(err.origin.as_deref(), Some(RUST_NAME));
+    assert!(err.message.contains("not a valid"), "{err}");
+
+    let err = check(&|class| {
+        let generic_sig = "(Ljava/util/List<Ljava/lang/String;>;)I";
+        class.export_native_wrapper("get", "(I)I", "njni$$get", "(I)I", false, &meta(generic_sig));
+    });
+    assert!(err.message.contains("does not match"), "{err}");
+
+    let err = check(&|class| {
+        let annotations = AnnotationList::EMPTY;
+        class.export_field(FFlags::Public.into(), "x", "I", "TT;", &annotations);
+    });
+    assert_eq!(err.method, None);
+    assert!(err.message.contains("`x`"), "{err}");
+
+    for signature in ["<T:Ljava/lang/Object;>Ljava/lang/Object;", "Ljava/lang/Number;", "L"] {
+        let err = check(&|class| class.generic_signature(signature));
+        assert!(err.message.contains(signature), "{err}");
+    }
+
+    let err = check(&|class| {
+        class.generic_signature("Ljava/lang/Object;Ljava/lang/Runnable;");
+    });
+    assert!(err.message.contains("java/lang/Runnable"), "{err}");
+}
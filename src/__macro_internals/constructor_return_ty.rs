@@ -39,14 +39,14 @@ unsafe impl<'env, T: SyntheticTyType> JavaConversion<'env> for SyntheticTy<T> {
         _: Self::JavaType,
         _: JniEnv<'env>,
         _: impl FnOnce(&Self) -> R,
-    ) -> R {
+    ) -> Result<R> {
         unreachable!()
     }
     unsafe fn from_java_mut<R>(
         _: Self::JavaType,
         _: JniEnv<'env>,
         _: impl FnOnce(&mut Self) -> R,
-    ) -> R {
+    ) -> Result<R> {
         unreachable!()
     }
     fn null() -> Self::JavaType {
@@ -63,8 +63,8 @@ pub trait ConstructorReturnTy<'env, W: SyntheticTyType> {
 }
 
 impl<'env, T: JavaClass<'env>, W: SyntheticTyType> ConstructorReturnTy<'env, W> for T {
-    type ReturnType = u32;
-    const RET_TY: &'static str = "I";
+    type ReturnType = u64;
+    const RET_TY: &'static str = "J";
     const SUPER_CTOR_SIGNATURE: &'static str = "()V";
     const HELPER_CTOR_SIGNATURE: &'static str = "()V"; // unused
     fn ctor_new(self, param_class: &str, env: JniEnv<'env>) -> Result<Self::ReturnType> {
@@ -77,7 +77,7 @@ impl<'env, T: JavaClass<'env>, W: SyntheticTyType, P: JavaConversion<'env>>
     type ReturnType = SyntheticTy<W>;
     const RET_TY: &'static str = constcat_generic!("L", W::CLASS_NAME, ";");
     const SUPER_CTOR_SIGNATURE: &'static str = constcat_generic!("(", P::JNI_TYPE, ")V");
-    const HELPER_CTOR_SIGNATURE: &'static str = constcat_generic!("(I", P::JNI_TYPE, ")V");
+    const HELPER_CTOR_SIGNATURE: &'static str = constcat_generic!("(J", P::JNI_TYPE, ")V");
     fn ctor_new(self, param_class: &str, env: JniEnv<'env>) -> Result<Self::ReturnType> {
         let id = env.get_id_manager::<T>().allocate(RwLock::new(self.0))?;
         Ok(unsafe {
@@ -99,7 +99,7 @@ macro_rules! generate_tuples {
             const SUPER_CTOR_SIGNATURE: &'static str =
                 constcat_generic!("(", $($ty::JNI_TYPE,)* ")V");
             const HELPER_CTOR_SIGNATURE: &'static str =
-                constcat_generic!("(I", $($ty::JNI_TYPE,)* ")V");
+                constcat_generic!("(J", $($ty::JNI_TYPE,)* ")V");
             fn ctor_new(self, param_class: &str, env: JniEnv<'env>) -> Result<Self::ReturnType> {
                 let id = env.get_id_manager::<T>().allocate(RwLock::new(self.0))?;
                 Ok(unsafe {
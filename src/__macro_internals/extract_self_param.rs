@@ -1,17 +1,41 @@
-use crate::{java_class::JavaClass, JniEnv, JniRef, JniRefMut};
+use crate::{
+    errors::*,
+    java_class::{jni_ref::LockPolicy, JavaClass},
+    JniEnv, JniRef, JniRefMut,
+};
 use jni::{objects::JObject, sys::jobject};
 
-pub trait ExtractSelfParam<'env> {
-    unsafe fn extract(env: JniEnv<'env>, this: jobject, id: Option<u32>) -> Self;
+pub trait ExtractSelfParam<'env>: Sized {
+    unsafe fn extract(
+        env: JniEnv<'env>,
+        this: jobject,
+        id: Option<u64>,
+        method: &'static str,
+        lock: LockPolicy,
+    ) -> Result<Self>;
 }
 impl<'env, T: JavaClass<'env>> ExtractSelfParam<'env> for JniRef<'env, T> {
-    unsafe fn extract(env: JniEnv<'env>, this: jobject, id: Option<u32>) -> Self {
-        T::create_jni_ref(env, JObject::from(this), id).unwrap()
+    unsafe fn extract(
+        env: JniEnv<'env>,
+        this: jobject,
+        id: Option<u64>,
+        method: &'static str,
+        lock: LockPolicy,
+    ) -> Result<Self> {
+        crate::java_class::deadlock::with_method(method, || {
+            T::create_jni_ref(env, JObject::from(this), id, lock)
+        })
     }
 }
 impl<'env, T: JavaClass<'env>> ExtractSelfParam<'env> for JniRefMut<'env, T> {
-    unsafe fn extract(env: JniEnv<'env>, this: jobject, id: Option<u32>) -> Self {
-        let r = <JniRef<'env, T> as ExtractSelfParam<'env>>::extract(env, this, id);
-        r.upgrade_ref()
+    unsafe fn extract(
+        env: JniEnv<'env>,
+        this: jobject,
+        id: Option<u64>,
+        method: &'static str,
+        lock: LockPolicy,
+    ) -> Result<Self> {
+        let r = <JniRef<'env, T> as ExtractSelfParam<'env>>::extract(env, this, id, method, lock)?;
+        crate::java_class::deadlock::with_method(method, || r.upgrade_ref())
     }
 }
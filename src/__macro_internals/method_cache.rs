@@ -0,0 +1,132 @@
+use crate::{errors::*, jni_env::JniEnv};
+use jni::{
+    objects::{GlobalRef, JClass, JMethodID, JObject, JStaticMethodID, JValue},
+    signature::{JavaType, TypeSignature},
+    sys::{jmethodID, jobject},
+};
+use std::{
+    marker::PhantomData,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+struct MethodIds {
+    class: GlobalRef,
+    method: jmethodID,
+    ret: JavaType,
+}
+impl MethodIds {
+    /// Returns the return type of the method, without copying the class name of object types.
+    fn ret(&self) -> JavaType {
+        match &self.ret {
+            JavaType::Object(_) | JavaType::Array(_) => JavaType::Object(String::new()),
+            ty => ty.clone(),
+        }
+    }
+}
+unsafe impl Send for MethodIds {}
+unsafe impl Sync for MethodIds {}
+
+/// A per-JVM cache of the class and method ID used by a generated `extern "Java"` wrapper.
+///
+/// The type parameter is an uninhabited type declared in the wrapper, so that each wrapper gets a
+/// separate slot from [`JniEnv::get_jvm_instance`].
+pub struct MethodCache<K> {
+    ids: AtomicPtr<MethodIds>,
+    phantom: PhantomData<fn() -> K>,
+}
+impl<K> MethodCache<K> {
+    pub fn new() -> Self {
+        MethodCache { ids: AtomicPtr::new(std::ptr::null_mut()), phantom: PhantomData }
+    }
+
+    fn ids(
+        &self,
+        env: JniEnv,
+        class: &str,
+        name: &str,
+        sig: &str,
+        is_static: bool,
+    ) -> Result<&MethodIds> {
+        let ptr = self.ids.load(Ordering::Acquire);
+        if !ptr.is_null() {
+            return Ok(unsafe { &*ptr });
+        }
+
+        // no lock is held here, as `FindClass` may run a static initializer that calls this
+        // wrapper again on the same thread. If two threads race, one of the results is dropped.
+        let local_class = env.find_class(class)?;
+        let global_class = env.new_global_ref(local_class)?;
+        env.delete_local_ref(local_class.into())?;
+
+        let class_obj = JClass::from(global_class.as_obj());
+        let method = if is_static {
+            env.get_static_method_id(class_obj, name, sig)?.into_inner()
+        } else {
+            env.get_method_id(class_obj, name, sig)?.into_inner()
+        };
+        let ret = TypeSignature::from_str(sig)?.ret;
+
+        let ids = Box::into_raw(Box::new(MethodIds { class: global_class, method, ret }));
+        match self.ids.compare_exchange(
+            std::ptr::null_mut(),
+            ids,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => Ok(unsafe { &*ids }),
+            Err(existing) => {
+                std::mem::drop(unsafe { Box::from_raw(ids) });
+                Ok(unsafe { &*existing })
+            }
+        }
+    }
+
+    pub fn call_method<'env>(
+        &self,
+        env: JniEnv<'env>,
+        this: jobject,
+        class: &str,
+        name: &str,
+        sig: &str,
+        args: &[JValue],
+    ) -> Result<JValue<'env>> {
+        let ids = self.ids(env, class, name, sig, false)?;
+        let method = JMethodID::from(ids.method);
+        Ok(env.call_method_unchecked(JObject::from(this), method, ids.ret(), args)?)
+    }
+
+    pub fn call_static_method<'env>(
+        &self,
+        env: JniEnv<'env>,
+        class: &str,
+        name: &str,
+        sig: &str,
+        args: &[JValue],
+    ) -> Result<JValue<'env>> {
+        let ids = self.ids(env, class, name, sig, true)?;
+        let class = JClass::from(ids.class.as_obj());
+        let method = JStaticMethodID::from(ids.method);
+        Ok(env.call_static_method_unchecked(class, method, ids.ret(), args)?)
+    }
+
+    pub fn new_object<'env>(
+        &self,
+        env: JniEnv<'env>,
+        class: &str,
+        sig: &str,
+        args: &[JValue],
+    ) -> Result<JObject<'env>> {
+        let ids = self.ids(env, class, "<init>", sig, false)?;
+        let class = JClass::from(ids.class.as_obj());
+        let method = JMethodID::from(ids.method);
+        Ok(env.new_object_unchecked(class, method, args)?)
+    }
+}
+impl<K> Drop for MethodCache<K> {
+    fn drop(&mut self) {
+        let ptr = *self.ids.get_mut();
+        if !ptr.is_null() {
+            std::mem::drop(unsafe { Box::from_raw(ptr) })
+        }
+    }
+}
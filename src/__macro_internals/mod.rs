@@ -1,5 +1,6 @@
 mod constructor_return_ty;
 mod extract_self_param;
+mod method_cache;
 mod once;
 mod registration;
 mod return_ty;
@@ -16,8 +17,9 @@ pub use crate::{
 };
 pub use constructor_return_ty::*;
 pub use extract_self_param::*;
+pub use method_cache::MethodCache;
 pub use nekojni_macros::{java_name_to_jni, jni_export_internal};
-pub use nekojni_utils::{constcat_const, constcat_generic, CFlags, FFlags, MFlags};
+pub use nekojni_utils::{constcat_const, constcat_generic, AnnotationList, CFlags, FFlags, MFlags};
 pub use once::OnceCache;
 pub use registration::*;
 pub use return_ty::{ImportCtorReturnTy, ImportReturnTy};
@@ -34,33 +36,76 @@ use crate::{
 };
 
 pub mod jni_ref {
-    pub use crate::java_class::jni_ref::{new_rust, new_wrapped};
+    pub use crate::java_class::jni_ref::{new_rust, new_wrapped, with_current_self, LockPolicy};
 }
 pub mod jni_env {
     use crate::{
-        java_class::object_id::IdManager, objects::JavaClass, Error, JniEnv,
+        errors::*,
+        java_class::{
+            deadlock::{HeldLock, LockMode},
+            object_id::IdManager,
+            JavaClassType, RustContents,
+        },
+        objects::JavaClass,
+        JniEnv,
         __macro_internals::__njni_entry_point,
     };
-    use jni::{sys::jboolean, JNIEnv};
+    use jni::{
+        sys::{jboolean, jclass},
+        JNIEnv,
+    };
     use parking_lot::RwLock;
     use std::sync::Arc;
 
     pub fn get_manager<'env, T: JavaClass<'env>>(env: JniEnv<'env>) -> Arc<IdManager<RwLock<T>>> {
         env.get_id_manager()
     }
-    pub extern "C" fn export_free<T>(env: JNIEnv, i: i32, free_attempted: jboolean)
-    where for<'a> T: JavaClass<'a> {
+    /// The native method that frees the Rust value of an object, registered as the static
+    /// `(JZ)V` method called by `finalize`, `close` and the cleaning action of the class.
+    ///
+    /// This must use the JNI calling convention and take the class as its second parameter, as it
+    /// is called directly by the JVM as a static native method.
+    pub extern "system" fn export_free<T>(
+        env: JNIEnv,
+        _class: jclass,
+        i: i64,
+        free_attempted: jboolean,
+    )
+    where for<'a> T: RustContents<'a> {
         __njni_entry_point(
             env,
-            |env| -> Result<(), Error> {
+            |env| {
                 if free_attempted == 0 {
-                    get_manager::<T>(env).free(i as u32)?;
+                    // run the hook while the object can still be found by its id, and free it
+                    // even if the hook fails.
+                    let manager = get_manager::<T>(env);
+                    let value = manager.get(i as u64)?;
+                    let result = T::on_close(&value, env);
+                    manager.free(i as u64)?;
+                    result?;
                 }
                 Ok(())
             },
             "java/lang/RuntimeException",
         )
     }
+
+    pub fn on_close_ref<T: JavaClassType>(
+        this: &Arc<RwLock<T>>,
+        func: impl FnOnce(&T),
+    ) -> Result<()> {
+        let _held = HeldLock::acquire(this, LockMode::Read)?;
+        func(&this.read());
+        Ok(())
+    }
+    pub fn on_close_mut<T: JavaClassType>(
+        this: &Arc<RwLock<T>>,
+        func: impl FnOnce(&mut T),
+    ) -> Result<()> {
+        let _held = HeldLock::acquire(this, LockMode::Write)?;
+        func(&mut this.write());
+        Ok(())
+    }
 }
 
 /// An error function for [`JavaClassImpl::default_ptr`].
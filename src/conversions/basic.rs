@@ -17,11 +17,11 @@ unsafe impl<'env> JavaConversion<'env> for bool {
     }
 }
 unsafe impl<'env> JavaConversionOwned<'env> for bool {
-    unsafe fn from_java(java: Self::JavaType, _: JniEnv<'env>) -> Self {
-        java != 0
+    unsafe fn from_java(java: Self::JavaType, _: JniEnv<'env>) -> Result<Self> {
+        Ok(java != 0)
     }
     fn from_java_value(java: JValue<'env>, env: JniEnv<'env>) -> Result<Self> {
-        Ok(unsafe { Self::from_java(java.z()? as jboolean, env) })
+        unsafe { Self::from_java(java.z()? as jboolean, env) }
     }
 }
 
@@ -44,11 +44,11 @@ macro_rules! simple_conversion {
             }
         }
         unsafe impl<'env> JavaConversionOwned<'env> for $rust_ty {
-            unsafe fn from_java(java: Self::JavaType, _env: JniEnv<'env>) -> Self {
-                java
+            unsafe fn from_java(java: Self::JavaType, _env: JniEnv<'env>) -> Result<Self> {
+                Ok(java)
             }
             fn from_java_value(java: JValue<'env>, env: JniEnv<'env>) -> Result<Self> {
-                Ok(unsafe { Self::from_java(java.$conv()?, env) })
+                unsafe { Self::from_java(java.$conv()?, env) }
             }
         }
     )*}
@@ -82,15 +82,15 @@ macro_rules! numeric_conversion {
             }
         }
         unsafe impl<'env> JavaConversionOwned<'env> for $rust_ty {
-            unsafe fn from_java(java: Self::JavaType, _env: JniEnv<'env>) -> Self {
+            unsafe fn from_java(java: Self::JavaType, _env: JniEnv<'env>) -> Result<Self> {
                 assert!(
                     <$rust_ty>::MAX != 0 || java < 0,
                     concat!(stringify!($rust_ty), " cannot be negative")
                 );
-                java as $rust_ty
+                Ok(java as $rust_ty)
             }
             fn from_java_value(java: JValue<'env>, env: JniEnv<'env>) -> Result<Self> {
-                Ok(unsafe { Self::from_java(java.$conv()?, env) })
+                unsafe { Self::from_java(java.$conv()?, env) }
             }
         }
     )*}
@@ -102,6 +102,6 @@ numeric_conversion! {
     (u16, jshort, "S", Short, s)
     (i32, jint, "I", Int, i)
     (u32, jint, "I", Int, i)
-    (i64, jlong, "L", Long, j)
-    (u64, jlong, "L", Long, j)
+    (i64, jlong, "J", Long, j)
+    (u64, jlong, "J", Long, j)
 }
@@ -1,6 +1,9 @@
 use super::*;
 use crate::{
-    java_class::{jni_ref::JniRefType, JavaClass, JavaClassType},
+    java_class::{
+        jni_ref::{JniRefType, LockPolicy},
+        JavaClass, JavaClassType,
+    },
     JniRef, JniRefMut,
 };
 use jni::objects::JObject;
@@ -23,11 +26,11 @@ unsafe impl<'env, T: JavaClass<'env>> JavaConversion<'env> for JniRef<'env, T> {
     }
 }
 unsafe impl<'env, T: JavaClass<'env>> JavaConversionOwned<'env> for JniRef<'env, T> {
-    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Self {
-        T::create_jni_ref(env, JObject::from(java), None).unwrap()
+    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Result<Self> {
+        T::create_jni_ref(env, JObject::from(java), None, LockPolicy::Block)
     }
     fn from_java_value(java: JValue<'env>, env: JniEnv<'env>) -> Result<Self> {
-        Ok(unsafe { Self::from_java(java.l()?.into_inner(), env) })
+        unsafe { Self::from_java(java.l()?.into_inner(), env) }
     }
 }
 
@@ -44,12 +47,10 @@ unsafe impl<'env, T: JavaClass<'env>> JavaConversion<'env> for JniRefMut<'env, T
     }
 }
 unsafe impl<'env, T: JavaClass<'env>> JavaConversionOwned<'env> for JniRefMut<'env, T> {
-    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Self {
-        T::create_jni_ref(env, JObject::from(java), None)
-            .unwrap()
-            .upgrade_ref()
+    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Result<Self> {
+        T::create_jni_ref(env, JObject::from(java), None, LockPolicy::Block)?.upgrade_ref()
     }
     fn from_java_value(java: JValue<'env>, env: JniEnv<'env>) -> Result<Self> {
-        Ok(unsafe { Self::from_java(java.l()?.into_inner(), env) })
+        unsafe { Self::from_java(java.l()?.into_inner(), env) }
     }
 }
@@ -9,19 +9,28 @@ macro_rules! impl_borrowed_from_owned {
             java: Self::JavaType,
             env: JniEnv<$env>,
             func: impl FnOnce(&Self) -> R,
-        ) -> R {
-            func(&Self::from_java(java, env))
+        ) -> Result<R> {
+            Ok(func(&Self::from_java(java, env)?))
         }
         unsafe fn from_java_mut<R>(
             java: Self::JavaType,
             env: JniEnv<$env>,
             func: impl FnOnce(&mut Self) -> R,
-        ) -> R {
-            func(&mut Self::from_java(java, env))
+        ) -> Result<R> {
+            Ok(func(&mut Self::from_java(java, env)?))
         }
     };
 }
 
+/// Deletes the local reference contained in a [`JValue`] created by a conversion.
+fn delete_local_value(value: JValue, env: JniEnv) {
+    if let JValue::Object(obj) = value {
+        if !obj.is_null() {
+            env.delete_local_ref(obj).ok();
+        }
+    }
+}
+
 mod basic;
 mod java_type;
 mod jni_ref;
@@ -37,6 +46,13 @@ pub trait JavaConversionType {
 
     /// The JNI type used for this Rust object.
     const JNI_TYPE: &'static str;
+
+    /// The Java type used for this Rust object, including any type arguments, in the format used
+    /// by the `Signature` attribute of class files.
+    ///
+    /// This only needs to be overridden for generic types, and must erase to
+    /// [`JNI_TYPE`](Self::JNI_TYPE).
+    const JNI_GENERIC_TYPE: &'static str = Self::JNI_TYPE;
 }
 
 /// Main trait that converts between Java and Rust types.
@@ -57,6 +73,10 @@ pub unsafe trait JavaConversion<'env>: JavaConversionType {
     /// Convert the Rust type into a Java method parameter.
     fn to_java_value(&self, env: JniEnv<'env>) -> JValue<'env>;
 
+    /// Deletes a Java method parameter created by [`JavaConversion::to_java_value`], if it created
+    /// a new local reference.
+    fn delete_java_value(_value: JValue<'env>, _env: JniEnv<'env>) {}
+
     /// Convert the Java type into an borrowed Rust type.
     ///
     /// # Safety
@@ -66,7 +86,7 @@ pub unsafe trait JavaConversion<'env>: JavaConversionType {
         java: Self::JavaType,
         env: JniEnv<'env>,
         func: impl FnOnce(&Self) -> R,
-    ) -> R;
+    ) -> Result<R>;
 
     /// Convert the Java type into an mutably borrowed Rust type.
     ///
@@ -77,7 +97,7 @@ pub unsafe trait JavaConversion<'env>: JavaConversionType {
         java: Self::JavaType,
         env: JniEnv<'env>,
         func: impl FnOnce(&mut Self) -> R,
-    ) -> R;
+    ) -> Result<R>;
 
     /// Returns the closest thing to a null value in this type. Used as a return type for an JNI
     /// function after returning an exception.
@@ -97,7 +117,7 @@ pub unsafe trait JavaConversionOwned<'env>: JavaConversion<'env> + Sized {
     /// # Safety
     ///
     /// The value passed in must be valid for the lifetime of the [`JniEnv`].
-    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Self;
+    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Result<Self>;
 
     /// Convert the Java return value into an owned Rust type.
     fn from_java_value(java: JValue<'env>, env: JniEnv<'env>) -> Result<Self>;
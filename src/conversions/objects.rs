@@ -6,6 +6,7 @@ use nekojni_utils::constcat_generic;
 impl<'env, T: JavaConversionOwned<'env>> JavaConversionType for JArray<'env, T> {
     type JavaType = jobject;
     const JNI_TYPE: &'static str = constcat_generic!("[", T::JNI_TYPE);
+    const JNI_GENERIC_TYPE: &'static str = constcat_generic!("[", T::JNI_GENERIC_TYPE);
 }
 unsafe impl<'env, T: JavaConversionOwned<'env>> JavaConversion<'env> for JArray<'env, T> {
     fn to_java(&self, _: JniEnv<'env>) -> Self::JavaType {
@@ -20,10 +21,10 @@ unsafe impl<'env, T: JavaConversionOwned<'env>> JavaConversion<'env> for JArray<
     }
 }
 unsafe impl<'env, T: JavaConversionOwned<'env>> JavaConversionOwned<'env> for JArray<'env, T> {
-    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Self {
-        JArray::from_obj(env, JObject::from(java))
+    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Result<Self> {
+        Ok(JArray::from_obj(env, JObject::from(java)))
     }
     fn from_java_value(java: JValue<'env>, env: JniEnv<'env>) -> Result<Self> {
-        Ok(unsafe { Self::from_java(java.l()?.into_inner(), env) })
+        unsafe { Self::from_java(java.l()?.into_inner(), env) }
     }
 }
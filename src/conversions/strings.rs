@@ -12,21 +12,24 @@ unsafe impl<'env> JavaConversion<'env> for str {
     fn to_java_value(&self, env: JniEnv<'env>) -> JValue<'env> {
         JValue::Object(JObject::from(self.to_java(env)))
     }
+    fn delete_java_value(value: JValue<'env>, env: JniEnv<'env>) {
+        delete_local_value(value, env)
+    }
     unsafe fn from_java_ref<R>(
         java: Self::JavaType,
         env: JniEnv<'env>,
         func: impl FnOnce(&Self) -> R,
-    ) -> R {
-        let str = String::from_java(java, env);
-        func(&str)
+    ) -> Result<R> {
+        let str = String::from_java(java, env)?;
+        Ok(func(&str))
     }
     unsafe fn from_java_mut<R>(
         java: Self::JavaType,
         env: JniEnv<'env>,
         func: impl FnOnce(&mut Self) -> R,
-    ) -> R {
-        let mut str = String::from_java(java, env);
-        func(&mut str)
+    ) -> Result<R> {
+        let mut str = String::from_java(java, env)?;
+        Ok(func(&mut str))
     }
     fn null() -> Self::JavaType {
         std::ptr::null_mut()
@@ -44,17 +47,20 @@ unsafe impl<'env> JavaConversion<'env> for String {
     fn to_java_value(&self, env: JniEnv<'env>) -> JValue<'env> {
         JValue::Object(JObject::from(self.to_java(env)))
     }
+    fn delete_java_value(value: JValue<'env>, env: JniEnv<'env>) {
+        delete_local_value(value, env)
+    }
     impl_borrowed_from_owned!('env);
     fn null() -> Self::JavaType {
         std::ptr::null_mut()
     }
 }
 unsafe impl<'env> JavaConversionOwned<'env> for String {
-    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Self {
-        env.get_string(java.into()).unwrap().into()
+    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Result<Self> {
+        Ok(env.get_string(java.into())?.into())
     }
     fn from_java_value(java: JValue<'env>, env: JniEnv<'env>) -> Result<Self> {
-        Ok(unsafe { Self::from_java(java.l()?.into_inner(), env) })
+        unsafe { Self::from_java(java.l()?.into_inner(), env) }
     }
 }
 
@@ -69,21 +75,24 @@ unsafe impl<'env> JavaConversion<'env> for [u8] {
     fn to_java_value(&self, env: JniEnv<'env>) -> JValue<'env> {
         JValue::Object(JObject::from(self.to_java(env)))
     }
+    fn delete_java_value(value: JValue<'env>, env: JniEnv<'env>) {
+        delete_local_value(value, env)
+    }
     unsafe fn from_java_ref<R>(
         java: Self::JavaType,
         env: JniEnv<'env>,
         func: impl FnOnce(&Self) -> R,
-    ) -> R {
-        let vec = Vec::<u8>::from_java(java, env);
-        func(&vec)
+    ) -> Result<R> {
+        let vec = Vec::<u8>::from_java(java, env)?;
+        Ok(func(&vec))
     }
     unsafe fn from_java_mut<R>(
         java: Self::JavaType,
         env: JniEnv<'env>,
         func: impl FnOnce(&mut Self) -> R,
-    ) -> R {
-        let mut vec = Vec::<u8>::from_java(java, env);
-        func(&mut vec)
+    ) -> Result<R> {
+        let mut vec = Vec::<u8>::from_java(java, env)?;
+        Ok(func(&mut vec))
     }
     fn null() -> Self::JavaType {
         std::ptr::null_mut()
@@ -101,16 +110,19 @@ unsafe impl<'env> JavaConversion<'env> for Vec<u8> {
     fn to_java_value(&self, env: JniEnv<'env>) -> JValue<'env> {
         JValue::Object(JObject::from(self.to_java(env)))
     }
+    fn delete_java_value(value: JValue<'env>, env: JniEnv<'env>) {
+        delete_local_value(value, env)
+    }
     impl_borrowed_from_owned!('env);
     fn null() -> Self::JavaType {
         std::ptr::null_mut()
     }
 }
 unsafe impl<'env> JavaConversionOwned<'env> for Vec<u8> {
-    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Self {
-        env.convert_byte_array(java).unwrap()
+    unsafe fn from_java(java: Self::JavaType, env: JniEnv<'env>) -> Result<Self> {
+        Ok(env.convert_byte_array(java)?)
     }
     fn from_java_value(java: JValue<'env>, env: JniEnv<'env>) -> Result<Self> {
-        Ok(unsafe { Self::from_java(java.l()?.into_inner(), env) })
+        unsafe { Self::from_java(java.l()?.into_inner(), env) }
     }
 }
@@ -121,8 +121,9 @@ impl Error {
             Some(x) => x,
             None => exception_class,
         };
-        let exception =
-            env.new_object(class, "(Ljava/lang/String;)V", &[self.to_string().to_java_value(env)])?;
+        let exception = env.new_object(env.find_class(class)?, "(Ljava/lang/String;)V", &[self
+            .to_string()
+            .to_java_value(env)])?;
         'register_exc: {
             if exception_class == class && exception_class != "java/lang/RuntimeException" {
                 if let Some(backtrace) = self.backtrace() {
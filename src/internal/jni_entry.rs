@@ -10,6 +10,7 @@ pub trait MethodReturn {
     type Intermediate;
     type ReturnTy: JniAbiType;
     const JNI_RETURN_TYPE: &'static str;
+    const JNI_GENERIC_RETURN_TYPE: &'static str;
     fn into_inner(self) -> Self::Intermediate;
     fn is_error(&self) -> bool;
     fn emit_error(self, env: JniEnv, exception_class: &str) -> Result<()>;
@@ -19,6 +20,7 @@ impl<T: JavaConversionType> MethodReturn for T {
     type Intermediate = T;
     type ReturnTy = T::JavaType;
     const JNI_RETURN_TYPE: &'static str = T::JNI_TYPE;
+    const JNI_GENERIC_RETURN_TYPE: &'static str = T::JNI_GENERIC_TYPE;
     fn into_inner(self) -> T {
         self
     }
@@ -34,6 +36,7 @@ impl<T: JavaConversionType, E: ErrorTrait + 'static> MethodReturn for StdResult<
     type Intermediate = T;
     type ReturnTy = T::JavaType;
     const JNI_RETURN_TYPE: &'static str = T::JNI_TYPE;
+    const JNI_GENERIC_RETURN_TYPE: &'static str = T::JNI_GENERIC_TYPE;
     fn into_inner(self) -> T {
         self.expect("internal error: into_inner called on Err")
     }
@@ -51,6 +54,7 @@ impl<T: JavaConversionType> MethodReturn for Result<T> {
     type Intermediate = T;
     type ReturnTy = T::JavaType;
     const JNI_RETURN_TYPE: &'static str = T::JNI_TYPE;
+    const JNI_GENERIC_RETURN_TYPE: &'static str = T::JNI_GENERIC_TYPE;
     fn into_inner(self) -> T {
         self.expect("internal error: into_inner called on Err")
     }
@@ -119,7 +123,7 @@ pub fn catch_panic<R>(func: impl FnOnce() -> R) -> Result<R> {
 /// This has the weird name it does to allow us to identify it in the stack trace (for purposes of
 /// passing a stack trace cleanly into Java code).
 #[inline(never)]
-pub fn __njni_entry_point<R: MethodReturn, F: FnOnce(JniEnv) -> R>(
+pub fn __njni_entry_point<R: MethodReturn, F: FnOnce(JniEnv) -> Result<R>>(
     env: JNIEnv,
     func: F,
     exception_class: &str,
@@ -131,13 +135,18 @@ where
     // we invoke a lot of weird stuff trying to get the panic string.
     match std::panic::catch_unwind(AssertUnwindSafe(|| {
         match JniEnv::with_env(env, |env| {
-            match catch_panic(|| {
-                let result = func(env);
-                if result.is_error() {
-                    check_fail(result.emit_error(env, exception_class));
+            match catch_panic(|| match func(env) {
+                Ok(result) => {
+                    if result.is_error() {
+                        check_fail(result.emit_error(env, exception_class));
+                        R::Intermediate::null_ret()
+                    } else {
+                        result.into_inner().to_java_ret(env)
+                    }
+                }
+                Err(e) => {
+                    check_fail(e.emit_error(env, exception_class));
                     R::Intermediate::null_ret()
-                } else {
-                    result.into_inner().to_java_ret(env)
                 }
             }) {
                 Ok(v) => Ok(v),
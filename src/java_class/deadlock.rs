@@ -0,0 +1,172 @@
+// Tracking for the locks held on Rust objects by each thread.
+//
+// When the `deadlock_detection` feature is enabled, this is used to return an error for any lock
+// that would block forever because the current thread already holds a conflicting lock on the
+// same object, rather than hanging the JVM. Otherwise, all of these checks are no-ops.
+//
+// Only the locks held by the current thread are known, so this only detects a thread re-entrantly
+// locking an object it has already locked, such as a `&mut self` method being called on an object
+// from inside one of its `&self` methods. Deadlocks between multiple threads waiting on locks held
+// by each other are not detected, and still block.
+
+use crate::{errors::*, java_class::JavaClassType};
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+/// The type of lock held on an object.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LockMode {
+    Read,
+    Upgradable,
+    Write,
+}
+
+#[cfg(feature = "deadlock_detection")]
+mod imp {
+    use super::LockMode;
+    use crate::{errors::*, java_class::JavaClassType};
+    use std::cell::{Cell, RefCell};
+
+    thread_local! {
+        static HELD_LOCKS: RefCell<Vec<(usize, LockMode)>> = RefCell::new(Vec::new());
+        static CURRENT_METHOD: Cell<Option<&'static str>> = Cell::new(None);
+    }
+
+    pub struct HeldLock {
+        ptr: usize,
+        mode: LockMode,
+    }
+    impl HeldLock {
+        pub fn acquire<T: JavaClassType>(ptr: usize, mode: LockMode) -> Result<HeldLock> {
+            check_lock::<T>(ptr, mode)?;
+            HELD_LOCKS.with(|locks| locks.borrow_mut().push((ptr, mode)));
+            Ok(HeldLock { ptr, mode })
+        }
+
+        pub fn change_mode<T: JavaClassType>(&mut self, mode: LockMode) -> Result<()> {
+            self.remove();
+            let result = check_lock::<T>(self.ptr, mode);
+            if result.is_ok() {
+                self.mode = mode;
+            }
+            HELD_LOCKS.with(|locks| locks.borrow_mut().push((self.ptr, self.mode)));
+            result
+        }
+
+        pub fn set_mode(&mut self, mode: LockMode) {
+            self.remove();
+            self.mode = mode;
+            HELD_LOCKS.with(|locks| locks.borrow_mut().push((self.ptr, self.mode)));
+        }
+
+        fn remove(&self) {
+            HELD_LOCKS.with(|locks| {
+                let mut locks = locks.borrow_mut();
+                if let Some(i) = locks.iter().rposition(|x| *x == (self.ptr, self.mode)) {
+                    locks.remove(i);
+                }
+            })
+        }
+    }
+    impl Drop for HeldLock {
+        fn drop(&mut self) {
+            self.remove();
+        }
+    }
+
+    fn check_lock<T: JavaClassType>(ptr: usize, mode: LockMode) -> Result<()> {
+        let conflict = HELD_LOCKS.with(|locks| {
+            locks.borrow().iter().any(|&(held_ptr, held_mode)| {
+                held_ptr == ptr
+                    && match mode {
+                        LockMode::Read => held_mode == LockMode::Write,
+                        LockMode::Upgradable | LockMode::Write => true,
+                    }
+            })
+        });
+        if conflict {
+            let method = match CURRENT_METHOD.with(|x| x.get()) {
+                Some(method) => format!(" in `{method}`"),
+                None => String::new(),
+            };
+            let (held, requested) = match mode {
+                LockMode::Read => ("exclusively borrowed", "shared"),
+                LockMode::Upgradable | LockMode::Write => ("borrowed", "exclusive"),
+            };
+            Err(Error::message(format!(
+                "Deadlock detected{method}: An object of class `{}` is already {held} by this \
+                 thread, so {requested} access to it would block forever.",
+                T::JNI_TYPE.replace('/', "."),
+            ))
+            .set_exception_class("java/lang/IllegalStateException"))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn with_method<R>(method: &'static str, func: impl FnOnce() -> R) -> R {
+        struct RestoreMethod(Option<&'static str>);
+        impl Drop for RestoreMethod {
+            fn drop(&mut self) {
+                CURRENT_METHOD.with(|x| x.set(self.0));
+            }
+        }
+
+        let _restore = RestoreMethod(CURRENT_METHOD.with(|x| x.replace(Some(method))));
+        func()
+    }
+}
+
+#[cfg(not(feature = "deadlock_detection"))]
+mod imp {
+    use super::LockMode;
+    use crate::{errors::*, java_class::JavaClassType};
+
+    pub struct HeldLock;
+    impl HeldLock {
+        #[inline(always)]
+        pub fn acquire<T: JavaClassType>(_ptr: usize, _mode: LockMode) -> Result<HeldLock> {
+            Ok(HeldLock)
+        }
+
+        #[inline(always)]
+        pub fn change_mode<T: JavaClassType>(&mut self, _mode: LockMode) -> Result<()> {
+            Ok(())
+        }
+
+        #[inline(always)]
+        pub fn set_mode(&mut self, _mode: LockMode) {}
+    }
+
+    #[inline(always)]
+    pub fn with_method<R>(_method: &'static str, func: impl FnOnce() -> R) -> R {
+        func()
+    }
+}
+
+/// A record of a lock held on an object by the current thread.
+pub struct HeldLock(imp::HeldLock);
+impl HeldLock {
+    /// Records that the current thread is acquiring a lock on an object, returning an error if
+    /// doing so would deadlock.
+    pub fn acquire<T: JavaClassType>(lock: &Arc<RwLock<T>>, mode: LockMode) -> Result<HeldLock> {
+        Ok(HeldLock(imp::HeldLock::acquire::<T>(Arc::as_ptr(lock) as usize, mode)?))
+    }
+
+    /// Records that the current thread is changing the type of a lock it holds, returning an error
+    /// if doing so would deadlock.
+    pub fn change_mode<T: JavaClassType>(&mut self, mode: LockMode) -> Result<()> {
+        self.0.change_mode::<T>(mode)
+    }
+
+    /// Records that the current thread has changed the type of a lock it holds, without checking
+    /// whether doing so could deadlock.
+    pub fn set_mode(&mut self, mode: LockMode) {
+        self.0.set_mode(mode)
+    }
+}
+
+/// Runs a function, naming the method it is called from in any deadlock errors.
+pub fn with_method<R>(method: &'static str, func: impl FnOnce() -> R) -> R {
+    imp::with_method(method, func)
+}
@@ -1,7 +1,7 @@
 use crate::{errors::*, JniEnv};
 use enumset::EnumSet;
 use jni::{strings::JNIString, NativeMethod};
-use nekojni_utils::{CFlags, FFlags, MFlags};
+use nekojni_utils::{AnnotationList, CFlags, FFlags, MFlags};
 use std::ffi::c_void;
 
 /// Represents something exported from a Java class defined in Rust.
@@ -12,26 +12,45 @@ pub enum ExportedItem {
     NativeConstructor {
         flags: EnumSet<MFlags>,
         signature: &'static str,
+        generic_signature: &'static str,
 
         native_name: &'static str,
         native_signature: &'static str,
 
         super_signature: &'static str,
+
+        rust_name: &'static str,
+        line: u32,
+        param_names: &'static [&'static str],
+
+        annotations: AnnotationList<'static>,
+        param_annotations: &'static [AnnotationList<'static>],
     },
     NativeMethodWrapper {
         flags: EnumSet<MFlags>,
         name: &'static str,
         signature: &'static str,
+        generic_signature: &'static str,
 
         native_name: &'static str,
         native_signature: &'static str,
 
         has_id_param: bool,
+
+        rust_name: &'static str,
+        line: u32,
+        param_names: &'static [&'static str],
+
+        annotations: AnnotationList<'static>,
+        param_annotations: &'static [AnnotationList<'static>],
     },
     JavaField {
         flags: EnumSet<FFlags>,
         name: &'static str,
         field: &'static str,
+        generic_field: &'static str,
+
+        annotations: AnnotationList<'static>,
     },
 }
 
@@ -40,11 +59,14 @@ pub enum ExportedItem {
 pub struct RustNativeMethod {
     pub name: &'static str,
     pub sig: &'static str,
+    pub generic_sig: &'static str,
     pub fn_ptr: *mut c_void,
     pub is_static: bool,
 
     pub export_direct_flags: EnumSet<MFlags>,
     pub export_direct: bool,
+    pub annotations: AnnotationList<'static>,
+    pub param_annotations: &'static [AnnotationList<'static>],
 }
 unsafe impl Send for RustNativeMethod {}
 unsafe impl Sync for RustNativeMethod {}
@@ -64,12 +86,16 @@ pub struct ExportedClass {
     pub name: &'static str,
     pub super_class: Option<&'static str>,
     pub implements: &'static [&'static str],
+    pub generic_signature: Option<&'static str>,
+    pub annotations: AnnotationList<'static>,
     pub source_file: &'static str,
 
     pub id_field_name: &'static str,
     pub static_init: &'static [&'static str],
     pub instance_init: &'static [&'static str],
     pub free_fn: &'static str,
+    pub close_method: Option<&'static str>,
+    pub from_rust: bool,
 
     pub exports: &'static [ExportedItem],
     pub native_methods: &'static [RustNativeMethod],
@@ -92,3 +118,127 @@ impl ExportedClass {
         Ok(())
     }
 }
+#[cfg(feature = "classfile")]
+impl ExportedClass {
+    /// Generates the Java-side class for this exported class.
+    pub fn generate_class(&self, class_data: &mut nekojni_utils::ClassData, init_class: &str) {
+        let mut class = nekojni_utils::NativeClassWrapper::new(
+            self.access,
+            &self.name,
+            match &self.super_class {
+                None => "java/lang/Object",
+                Some(v) => v,
+            },
+            self.id_field_name,
+            class_data.options().target_version,
+        );
+        class.generate_init(init_class, &self.static_init);
+        for class_name in self.implements {
+            class.implements(class_name);
+        }
+        if let Some(signature) = self.generic_signature {
+            class.generic_signature(signature);
+        }
+        class.annotate(&self.annotations);
+        class.source_file(self.source_file);
+        if class_data.options().use_cleaner {
+            class.cleaner_funcs(&self.free_fn, init_class, self.close_method);
+        } else {
+            class.dispose_funcs(&self.free_fn, self.close_method);
+        }
+        if self.from_rust {
+            class.from_rust_constructor(self.instance_init);
+        }
+
+        for exports in self.exports {
+            match exports {
+                ExportedItem::NativeConstructor {
+                    flags,
+                    signature,
+                    generic_signature,
+                    native_name,
+                    native_signature,
+                    super_signature,
+                    rust_name,
+                    line,
+                    param_names,
+                    annotations,
+                    param_annotations,
+                } => {
+                    let meta = nekojni_utils::ExportedMethodMeta {
+                        access: *flags,
+                        generic_sig: generic_signature,
+                        rust_name,
+                        line: *line,
+                        param_names,
+                        annotations,
+                        param_annotations,
+                    };
+                    class.export_constructor(
+                        signature,
+                        native_name,
+                        native_signature,
+                        super_signature,
+                        self.instance_init,
+                        &meta,
+                    );
+                }
+                ExportedItem::NativeMethodWrapper {
+                    flags,
+                    name,
+                    signature,
+                    generic_signature,
+                    native_name,
+                    native_signature,
+                    has_id_param,
+                    rust_name,
+                    line,
+                    param_names,
+                    annotations,
+                    param_annotations,
+                } => {
+                    let meta = nekojni_utils::ExportedMethodMeta {
+                        access: *flags,
+                        generic_sig: generic_signature,
+                        rust_name,
+                        line: *line,
+                        param_names,
+                        annotations,
+                        param_annotations,
+                    };
+                    class.export_native_wrapper(
+                        name,
+                        signature,
+                        &jni_native_name(native_name, flags.contains(MFlags::Static), false),
+                        native_signature,
+                        *has_id_param,
+                        &meta,
+                    );
+                }
+                ExportedItem::JavaField { flags, name, field, generic_field, annotations } => {
+                    class.export_field(*flags, name, field, generic_field, annotations);
+                }
+            }
+        }
+        for method in self.native_methods {
+            if method.export_direct {
+                class.export_native_direct(
+                    method.export_direct_flags,
+                    &method.name,
+                    &method.sig,
+                    &method.generic_sig,
+                    &method.annotations,
+                    method.param_annotations,
+                );
+            } else {
+                class.export_native(
+                    &jni_native_name(method.name, method.is_static, false),
+                    &method.sig,
+                    method.is_static,
+                );
+            }
+        }
+
+        class_data.add_exported_class(class);
+    }
+}
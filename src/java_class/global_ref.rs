@@ -0,0 +1,125 @@
+use crate::{
+    errors::*,
+    java_class::{
+        jni_ref::{JniRefType, LockPolicy},
+        JavaClass,
+    },
+    jni_env::RefKind,
+    JniEnv, JniRef, JniVm,
+};
+use jni::{objects::JObject, sys::jobject};
+use std::marker::PhantomData;
+
+/// A reference to a Java object that is not tied to a single JNI call.
+///
+/// Unlike a [`JniRef`], this may be stored for any length of time, and sent to other threads. It
+/// can be turned back into a [`JniRef`] with [`GlobalRef::get`] once a [`JniEnv`] is available.
+///
+/// The reference is deleted when this is dropped. If the dropping thread is not attached to the
+/// JVM, the deletion is deferred until another thread calls into Rust code or is attached to the
+/// JVM.
+pub struct GlobalRef<T> {
+    vm: JniVm,
+    obj: jobject,
+    phantom: PhantomData<T>,
+}
+impl<T> GlobalRef<T>
+where T: for<'env> JavaClass<'env>
+{
+    /// Creates a new global reference to the object pointed to by a [`JniRef`].
+    pub fn new<'env, R: JniRefType>(obj: &JniRef<'env, T, R>) -> Result<Self> {
+        let env = obj.env();
+        let global = unsafe { new_ref_raw(env, JniRef::this(obj), RefKind::Global) };
+        jni_assert!(!global.is_null(), "Could not create global reference.");
+        Ok(GlobalRef { vm: env.vm()?, obj: global, phantom: PhantomData })
+    }
+
+    /// Creates a [`JniRef`] pointing to this object that can be used with a [`JniEnv`].
+    pub fn get<'env>(&self, env: JniEnv<'env>) -> Result<JniRef<'env, T>> {
+        let local = unsafe {
+            env.as_inner()
+                .new_local_ref::<()>(JObject::from(self.obj))?
+        };
+        jni_assert!(!local.is_null(), "Could not create local reference.");
+        T::create_jni_ref(env, local, None, LockPolicy::Block)
+    }
+
+    /// Creates a new weak reference to the object pointed to by this reference.
+    pub fn downgrade(&self, env: JniEnv) -> Result<WeakRef<T>> {
+        let weak = unsafe { new_ref_raw(env, JObject::from(self.obj), RefKind::Weak) };
+        jni_assert!(!weak.is_null(), "Could not create weak reference.");
+        Ok(WeakRef { vm: self.vm.clone(), obj: weak, phantom: PhantomData })
+    }
+}
+impl<T> Drop for GlobalRef<T> {
+    fn drop(&mut self) {
+        unsafe { self.vm.delete_ref(RefKind::Global, self.obj) }
+    }
+}
+// SAFETY: Global and weak references are valid on any thread attached to the JVM, and `T` is only
+// a marker for the class of the object. The Rust value of the object is not owned by this type,
+// and is only reachable through a `JniRef` created for a specific `JniEnv`.
+unsafe impl<T> Send for GlobalRef<T> {}
+unsafe impl<T> Sync for GlobalRef<T> {}
+
+/// A weak reference to a Java object that is not tied to a single JNI call.
+///
+/// This does not prevent the object from being garbage collected. Otherwise, this behaves like a
+/// [`GlobalRef`], and is deleted when it is dropped.
+pub struct WeakRef<T> {
+    vm: JniVm,
+    obj: jobject,
+    phantom: PhantomData<T>,
+}
+impl<T> WeakRef<T>
+where T: for<'env> JavaClass<'env>
+{
+    /// Creates a new weak reference to the object pointed to by a [`JniRef`].
+    pub fn new<'env, R: JniRefType>(obj: &JniRef<'env, T, R>) -> Result<Self> {
+        let env = obj.env();
+        let weak = unsafe { new_ref_raw(env, JniRef::this(obj), RefKind::Weak) };
+        jni_assert!(!weak.is_null(), "Could not create weak reference.");
+        Ok(WeakRef { vm: env.vm()?, obj: weak, phantom: PhantomData })
+    }
+
+    /// Creates a [`JniRef`] pointing to this object, or returns `None` if it has already been
+    /// garbage collected.
+    pub fn get<'env>(&self, env: JniEnv<'env>) -> Result<Option<JniRef<'env, T>>> {
+        let local = unsafe {
+            env.as_inner()
+                .new_local_ref::<()>(JObject::from(self.obj))?
+        };
+        if local.is_null() {
+            Ok(None)
+        } else {
+            Ok(Some(T::create_jni_ref(env, local, None, LockPolicy::Block)?))
+        }
+    }
+
+    /// Creates a new strong reference to this object, or returns `None` if it has already been
+    /// garbage collected.
+    pub fn upgrade(&self, env: JniEnv) -> Result<Option<GlobalRef<T>>> {
+        let global = unsafe { new_ref_raw(env, JObject::from(self.obj), RefKind::Global) };
+        if global.is_null() {
+            Ok(None)
+        } else {
+            Ok(Some(GlobalRef { vm: self.vm.clone(), obj: global, phantom: PhantomData }))
+        }
+    }
+}
+impl<T> Drop for WeakRef<T> {
+    fn drop(&mut self) {
+        unsafe { self.vm.delete_ref(RefKind::Weak, self.obj) }
+    }
+}
+// SAFETY: See `GlobalRef`.
+unsafe impl<T> Send for WeakRef<T> {}
+unsafe impl<T> Sync for WeakRef<T> {}
+
+unsafe fn new_ref_raw(env: JniEnv, obj: JObject, kind: RefKind) -> jobject {
+    let raw = env.as_inner().get_native_interface();
+    match kind {
+        RefKind::Global => ((**raw).NewGlobalRef.unwrap())(raw, obj.into_inner()),
+        RefKind::Weak => ((**raw).NewWeakGlobalRef.unwrap())(raw, obj.into_inner()),
+    }
+}
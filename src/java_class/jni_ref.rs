@@ -1,21 +1,33 @@
 #![allow(deprecated)]
 
-use crate::{errors::*, java_class::*};
-use jni::objects::{JObject, JValue};
+use crate::{
+    errors::*,
+    java_class::{
+        deadlock::{HeldLock, LockMode},
+        *,
+    },
+};
+use jni::{
+    objects::{JClass, JObject, JValue},
+    sys::jobject,
+};
 use parking_lot::{
-    lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard},
-    RawRwLock,
+    lock_api::{ArcRwLockReadGuard, ArcRwLockUpgradableReadGuard, ArcRwLockWriteGuard},
+    RawRwLock, RwLock,
 };
 use std::{
+    any::TypeId,
+    cell::RefCell,
     marker::PhantomData,
     ops::{Deref, DerefMut},
+    sync::Arc,
+    time::Duration,
 };
 
 mod sealed {
     pub trait Sealed {}
 }
 
-// TODO: Deadlock detection of some sort.
 // TODO: Sort out locks from the same thread/JNIEnv more cleanly.
 
 /// A marker trait used to represent a possible mode of a [`JniRef`].
@@ -34,8 +46,74 @@ impl JniRefType for JniRefWrite {}
 
 enum InnerRef<T> {
     Default,
-    Read(ArcRwLockReadGuard<RawRwLock, T>),
-    Write(ArcRwLockWriteGuard<RawRwLock, T>),
+    Read(u64, HeldLock, ArcRwLockReadGuard<RawRwLock, T>),
+    Write(u64, HeldLock, ArcRwLockWriteGuard<RawRwLock, T>),
+    Upgradable(u64, HeldLock, ArcRwLockUpgradableReadGuard<RawRwLock, T>),
+    Unlocked(u64, Arc<RwLock<T>>),
+}
+
+/// How a [`JniRef`] waits for the lock on the contents of an object.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LockPolicy {
+    /// Waits for as long as the lock is held by other threads.
+    Block,
+    /// Fails immediately if the lock is held by another thread.
+    Try,
+    /// Fails if the lock is held by another thread for longer than the given duration.
+    Timeout(Duration),
+}
+impl LockPolicy {
+    fn read<T>(self, lock: &Arc<RwLock<T>>) -> Result<ArcRwLockReadGuard<RawRwLock, T>> {
+        let guard = match self {
+            LockPolicy::Block => Some(lock.read_arc_recursive()),
+            LockPolicy::Try => lock.try_read_recursive_arc(),
+            LockPolicy::Timeout(timeout) => lock.try_read_arc_recursive_for(timeout),
+        };
+        guard.ok_or_else(lock_failed::<T>)
+    }
+
+    fn write<T>(self, lock: &Arc<RwLock<T>>) -> Result<ArcRwLockWriteGuard<RawRwLock, T>> {
+        let guard = match self {
+            LockPolicy::Block => Some(lock.write_arc()),
+            LockPolicy::Try => lock.try_write_arc(),
+            LockPolicy::Timeout(timeout) => lock.try_write_arc_for(timeout),
+        };
+        guard.ok_or_else(lock_failed::<T>)
+    }
+
+    fn upgrade<T>(
+        self,
+        guard: ArcRwLockUpgradableReadGuard<RawRwLock, T>,
+    ) -> std::result::Result<
+        ArcRwLockWriteGuard<RawRwLock, T>,
+        ArcRwLockUpgradableReadGuard<RawRwLock, T>,
+    > {
+        match self {
+            LockPolicy::Block => Ok(ArcRwLockUpgradableReadGuard::upgrade(guard)),
+            LockPolicy::Try => ArcRwLockUpgradableReadGuard::try_upgrade(guard),
+            LockPolicy::Timeout(timeout) => {
+                ArcRwLockUpgradableReadGuard::try_upgrade_for(guard, timeout)
+            }
+        }
+    }
+}
+
+#[inline(never)]
+fn lock_failed<T>() -> Error {
+    Error::message(format!(
+        "Object of type `{}` could not be locked, as it is in use elsewhere.",
+        std::any::type_name::<T>(),
+    ))
+    .set_exception_class("java/util/ConcurrentModificationException")
+}
+
+#[inline(never)]
+fn unlocked_write_fail<T>() -> Error {
+    Error::message(format!(
+        "Objects of type `{}` cannot be borrowed mutably, as they are exported with \
+         `lock = \"none\"`.",
+        std::any::type_name::<T>(),
+    ))
 }
 
 /// A pointer type holding a JNI environment and a an exported object.
@@ -45,6 +123,7 @@ enum InnerRef<T> {
 pub struct JniRef<'env, T, R: JniRefType = JniRefRead> {
     this: JObject<'env>,
     inner: InnerRef<T>,
+    lock: LockPolicy,
     env: JniEnv<'env>,
     phantom: PhantomData<R>,
 }
@@ -60,43 +139,244 @@ impl<'env, T: JavaClass<'env>, R: JniRefType> JniRef<'env, T, R> {
     }
 }
 
+impl<'env, T: JavaClass<'env>, R: JniRefType> JniRef<'env, T, R> {
+    /// Temporarily acquires exclusive access to the contents of this pointer.
+    ///
+    /// If this pointer only has shared access, its read lock is released before the write lock is
+    /// acquired, so other threads may modify the object in between. Shared access is restored
+    /// when the guard is dropped.
+    ///
+    /// The write lock is acquired using the same [`LockPolicy`] as the original lock on this
+    /// pointer, and an error is returned if it cannot be acquired.
+    ///
+    /// This is an associated function to avoid conflicting with methods on `T`.
+    pub fn write<'a>(this: &'a mut Self) -> Result<JniRefWriteGuard<'a, 'env, T, R>> {
+        let downgrade_on_drop = match std::mem::replace(&mut this.inner, InnerRef::Default) {
+            InnerRef::Default => {
+                jni_bail!("`JniRef::write` called on a reference with no Rust contents.")
+            }
+            InnerRef::Read(id, mut held, read) => {
+                if let Err(e) = held.change_mode::<T>(LockMode::Write) {
+                    this.inner = InnerRef::Read(id, held, read);
+                    return Err(e);
+                }
+                let lock = ArcRwLockReadGuard::rwlock(&read).clone();
+                match relock_write(this.env, id, read, this.lock) {
+                    Ok(write) => this.inner = InnerRef::Write(id, held, write),
+                    Err(e) => {
+                        held.set_mode(LockMode::Read);
+                        this.inner = InnerRef::Read(id, held, lock.read_arc_recursive());
+                        return Err(e);
+                    }
+                }
+                true
+            }
+            InnerRef::Write(id, held, write) => {
+                this.inner = InnerRef::Write(id, held, write);
+                false
+            }
+            InnerRef::Upgradable(id, mut held, upgradable) => {
+                if let Err(e) = held.change_mode::<T>(LockMode::Write) {
+                    this.inner = InnerRef::Upgradable(id, held, upgradable);
+                    return Err(e);
+                }
+                match this.lock.upgrade(upgradable) {
+                    Ok(write) => this.inner = InnerRef::Write(id, held, write),
+                    Err(upgradable) => {
+                        held.set_mode(LockMode::Upgradable);
+                        this.inner = InnerRef::Upgradable(id, held, upgradable);
+                        return Err(lock_failed::<T>());
+                    }
+                }
+                false
+            }
+            InnerRef::Unlocked(id, lock) => {
+                this.inner = InnerRef::Unlocked(id, lock);
+                return Err(unlocked_write_fail::<T>());
+            }
+        };
+        Ok(JniRefWriteGuard { this, downgrade_on_drop })
+    }
+
+    /// Temporarily gives up exclusive access to the contents of this pointer, allowing other
+    /// threads to read it until the guard is dropped.
+    ///
+    /// No other thread may modify the object while the guard exists, so exclusive access is
+    /// restored without the object changing in between.
+    ///
+    /// This is an associated function to avoid conflicting with methods on `T`.
+    pub fn read<'a>(this: &'a mut Self) -> JniRefReadGuard<'a, 'env, T, R> {
+        let upgrade_on_drop = match std::mem::replace(&mut this.inner, InnerRef::Default) {
+            InnerRef::Write(id, mut held, write) => {
+                held.set_mode(LockMode::Upgradable);
+                let upgradable = ArcRwLockWriteGuard::downgrade_to_upgradable(write);
+                this.inner = InnerRef::Upgradable(id, held, upgradable);
+                true
+            }
+            inner => {
+                this.inner = inner;
+                false
+            }
+        };
+        JniRefReadGuard { this, upgrade_on_drop }
+    }
+}
+
 impl<'env, T: JavaClass<'env>> JniRef<'env, T> {
     /// Upgrades this [`JniRef`] into a [`JniRefMut`]. As this requires an owning reference, this
     /// can only usually be used in practice with references returned from Java functions.
-    pub fn upgrade_ref(self) -> JniRefMut<'env, T> {
-        JniRef {
+    ///
+    /// The read lock held by this pointer is released before the write lock is acquired, so other
+    /// threads may modify the object in between. The write lock is acquired using the same
+    /// [`LockPolicy`] as the original lock on this pointer.
+    pub fn upgrade_ref(self) -> Result<JniRefMut<'env, T>> {
+        Ok(JniRef {
             this: self.this,
             inner: match self.inner {
                 InnerRef::Default => {
                     panic!("internal error: ugprading `JniRef` with no Rust contents.")
                 }
-                InnerRef::Read(read) => {
-                    todo!()
+                InnerRef::Read(id, mut held, read) => {
+                    held.change_mode::<T>(LockMode::Write)?;
+                    InnerRef::Write(id, held, relock_write(self.env, id, read, self.lock)?)
+                }
+                InnerRef::Write(id, held, write) => InnerRef::Write(id, held, write),
+                InnerRef::Upgradable(id, mut held, upgradable) => {
+                    held.change_mode::<T>(LockMode::Write)?;
+                    match self.lock.upgrade(upgradable) {
+                        Ok(write) => InnerRef::Write(id, held, write),
+                        Err(_) => return Err(lock_failed::<T>()),
+                    }
                 }
-                InnerRef::Write(write) => InnerRef::Write(write),
+                InnerRef::Unlocked(..) => return Err(unlocked_write_fail::<T>()),
             },
+            lock: self.lock,
             env: self.env,
             phantom: PhantomData,
+        })
+    }
+}
+
+impl<'env, T: RustContents<'env>> JniRef<'env, T> {
+    /// Creates a new instance of an exported class containing a value created in Rust code.
+    ///
+    /// The class must be declared with `#[jni(from_rust)]`, which generates a private synthetic
+    /// constructor for this function. The object is allocated with `AllocObject` and initialized
+    /// with that constructor, so none of the constructors exported to Java are run. Only the
+    /// constructor of the superclass that takes no parameters and the `#[jni(init)]` methods of the
+    /// class are called. This can be used to return existing Rust values to Java code.
+    ///
+    /// This returns an error if the superclass has no constructor without parameters.
+    pub fn new_from_rust(env: JniEnv<'env>, value: T) -> Result<JniRef<'env, T>> {
+        jni_assert!(
+            T::FROM_RUST,
+            "`{}` must be declared with `#[jni(from_rust)]` to be created from Rust code.",
+            T::JNI_TYPE.replace('/', ".")
+        );
+
+        let class = env.find_class(T::JNI_TYPE)?;
+        check_super_constructor::<T>(env, class)?;
+        let obj = env.alloc_object(class)?;
+        let manager = env.get_id_manager::<T>();
+        let id = manager.allocate(RwLock::new(value))?;
+
+        match init_from_rust::<T>(env, class, obj, id) {
+            Ok(()) => T::create_jni_ref(env, obj, Some(id), LockPolicy::Block),
+            Err(e) => {
+                manager.free(id)?;
+                Err(e)
+            }
         }
     }
 }
 
+/// Checks that the superclass of a class created by [`JniRef::new_from_rust`] has a constructor
+/// without parameters, which is called by the generated constructor.
+fn check_super_constructor<'env, T: RustContents<'env>>(
+    env: JniEnv<'env>,
+    class: JClass<'env>,
+) -> Result<()> {
+    let super_class = env.get_superclass(class)?;
+    if env.get_method_id(super_class, "<init>", "()V").is_err() {
+        env.exception_clear()?;
+        let name = env.call_method(super_class, "getName", "()Ljava/lang/String;", &[])?;
+        let name: String = env.get_string(name.l()?.into())?.into();
+        jni_bail!(
+            "`{}` cannot be created from Rust code, as its superclass `{name}` has no constructor \
+             without parameters.",
+            T::JNI_TYPE.replace('/', "."),
+        );
+    }
+    Ok(())
+}
+
+/// Initializes an object created with `AllocObject` by [`JniRef::new_from_rust`].
+fn init_from_rust<'env, T: RustContents<'env>>(
+    env: JniEnv<'env>,
+    class: JClass<'env>,
+    obj: JObject<'env>,
+    id: u64,
+) -> Result<()> {
+    // matches the constructor generated by `NativeClassWrapper::from_rust_constructor`
+    let sig = format!("(JL{}$NJni$$FromRust;)V", T::JNI_TYPE);
+    let init = env.get_method_id(class, "<init>", sig)?;
+    let args = [JValue::Long(id as i64).to_jni(), JValue::Object(JObject::null()).to_jni()];
+    // SAFETY: `init` is a constructor of `class` taking a `long` and an object.
+    unsafe {
+        let raw = env.as_inner().get_native_interface();
+        ((**raw).CallNonvirtualVoidMethodA.unwrap())(
+            raw,
+            obj.into_inner(),
+            class.into_inner(),
+            init.into_inner(),
+            args.as_ptr(),
+        );
+    }
+    if env.exception_check()? {
+        return Err(jni::errors::Error::JavaException.into());
+    }
+    Ok(())
+}
+
+/// Releases a read lock on an object and acquires a write lock, checking that the object was not
+/// freed while it was unlocked.
+fn relock_write<'env, T: JavaClass<'env>>(
+    env: JniEnv<'env>,
+    id: u64,
+    read: ArcRwLockReadGuard<RawRwLock, T>,
+    policy: LockPolicy,
+) -> Result<ArcRwLockWriteGuard<RawRwLock, T>> {
+    let lock: Arc<RwLock<T>> = ArcRwLockReadGuard::rwlock(&read).clone();
+    drop(read);
+    let write = policy.write(&lock)?;
+    let current = env.get_id_manager::<T>().get(id)?;
+    jni_assert!(
+        Arc::ptr_eq(&current, &lock),
+        "Object of type '{}' was freed while its lock was being upgraded.",
+        std::any::type_name::<T>(),
+    );
+    Ok(write)
+}
+
 impl<'env, T: JavaClass<'env>, R: JniRefType> Deref for JniRef<'env, T, R> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         match &self.inner {
             InnerRef::Default => T::default_ptr(),
-            InnerRef::Read(p) => &p,
-            InnerRef::Write(p) => &p,
+            InnerRef::Read(_, _, p) => &p,
+            InnerRef::Write(_, _, p) => &p,
+            InnerRef::Upgradable(_, _, p) => &p,
+            // SAFETY: Write locks are never taken on objects of types exported with
+            // `lock = "none"`, so no mutable references to the contents can exist.
+            InnerRef::Unlocked(_, lock) => unsafe { &*lock.data_ptr() },
         }
     }
 }
 impl<'env, T: JavaClass<'env>> DerefMut for JniRef<'env, T, JniRefWrite> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         match &mut self.inner {
-            InnerRef::Default => mut_ptr_fail(),
-            InnerRef::Read(_) => mut_ptr_fail(),
-            InnerRef::Write(p) => p.deref_mut(),
+            InnerRef::Write(_, _, p) => p.deref_mut(),
+            _ => mut_ptr_fail(),
         }
     }
 }
@@ -107,24 +387,116 @@ impl<'a, 'env: 'a, T: JavaClass<'env>> AsRef<JniEnv<'env>> for &'a JniRef<'env,
     }
 }
 
+/// The receiver of an exported `&self` method that is currently running on this thread.
+#[derive(Copy, Clone)]
+struct CurrentSelf {
+    ptr: *const (),
+    type_id: TypeId,
+    env: JniEnv<'static>,
+    this: jobject,
+    id: u64,
+    lock: LockPolicy,
+}
+thread_local! {
+    static CURRENT_SELF: RefCell<Vec<CurrentSelf>> = RefCell::new(Vec::new());
+}
+
+impl<'env, T: JavaClass<'env>> JniRef<'env, T> {
+    /// Returns a [`JniRef`] pointing to the object that an exported `&self` method was called on.
+    ///
+    /// This allows methods taking a plain `&self` to retrieve the Java object and its [`JniEnv`],
+    /// for example to call `extern "Java"` methods on it.
+    ///
+    /// This only works while an exported method taking `&self` is running on this thread, and
+    /// `this` is its receiver. Methods taking `self: &JniRef<Self>` already have the reference and
+    /// do not register their receiver, and `&mut self` methods should take
+    /// `self: &mut JniRefMut<Self>` instead. In those methods, in static methods, and once the
+    /// `&self` method has returned, this returns an error.
+    ///
+    /// This is an associated function to avoid conflicting with methods on `T`.
+    pub fn from_self(this: &'env T) -> Result<JniRef<'env, T>> {
+        let ptr = this as *const T as *const ();
+        let current = CURRENT_SELF.with(|x| {
+            x.borrow()
+                .iter()
+                .rev()
+                .find(|x| x.ptr == ptr && x.type_id == TypeId::of::<T>())
+                .copied()
+        });
+        match current {
+            Some(current) => {
+                // SAFETY: The entry is removed before the call it was created for returns, and
+                // `this` cannot outlive that call, as it is borrowed from the `JniRef` it used.
+                let env: JniEnv<'env> = unsafe { std::mem::transmute(current.env) };
+                T::create_jni_ref(env, JObject::from(current.this), Some(current.id), current.lock)
+            }
+            None => jni_bail!(
+                "`JniRef::from_self` was called on an object of type `{}` that is not the \
+                 receiver of an exported `&self` method running on this thread.",
+                std::any::type_name::<T>(),
+            ),
+        }
+    }
+}
+
+/// Runs an exported `&self` method, allowing its receiver to be used with [`JniRef::from_self`].
+pub fn with_current_self<'env, T: JavaClass<'env>, R>(
+    this: &JniRef<'env, T>,
+    func: impl FnOnce() -> R,
+) -> R {
+    // the entry is removed even if `func` panics, so it can never outlive the call
+    struct RestoreCurrent(usize);
+    impl Drop for RestoreCurrent {
+        fn drop(&mut self) {
+            CURRENT_SELF.with(|x| x.borrow_mut().truncate(self.0));
+        }
+    }
+
+    let id = match &this.inner {
+        InnerRef::Read(id, ..) | InnerRef::Unlocked(id, _) => *id,
+        _ => return func(),
+    };
+    let current = CurrentSelf {
+        ptr: &**this as *const T as *const (),
+        type_id: TypeId::of::<T>(),
+        // SAFETY: See `JniRef::from_self`.
+        env: unsafe { std::mem::transmute(this.env) },
+        this: this.this.into_inner(),
+        id,
+        lock: this.lock,
+    };
+    let _restore = RestoreCurrent(CURRENT_SELF.with(|x| {
+        let mut current_self = x.borrow_mut();
+        current_self.push(current);
+        current_self.len() - 1
+    }));
+    func()
+}
+
 /// Creates a new [`JniRef`] from a JNI environment and a java object containing an ID.
 pub fn new_rust<'env, T: RustContents<'env>>(
     env: JniEnv<'env>,
     this_class: &str, // TODO
     this: JObject<'env>,
-    id: Option<u32>,
+    id: Option<u64>,
+    lock: LockPolicy,
 ) -> Result<JniRef<'env, T>> {
     let id = match id {
         Some(id) => id,
-        None => match env.get_field(this, T::ID_FIELD, "I")? {
-            JValue::Int(i) => i as u32,
+        None => match env.get_field(this, T::ID_FIELD, "J")? {
+            JValue::Long(i) => i as u64,
             _ => unreachable!(),
         },
     };
     let manager = env.get_id_manager::<T>();
-    let lock = manager.get(id)?;
-    let inner = InnerRef::Read(lock.read_arc_recursive());
-    Ok(JniRef { this, inner, env, phantom: PhantomData })
+    let obj = manager.get(id)?;
+    let inner = if T::LOCK_FREE {
+        InnerRef::Unlocked(id, obj)
+    } else {
+        let held = HeldLock::acquire(&obj, LockMode::Read)?;
+        InnerRef::Read(id, held, lock.read(&obj)?)
+    };
+    Ok(JniRef { this, inner, lock, env, phantom: PhantomData })
 }
 
 /// Creates a new [`JniRef`] from a JNI environment and a java object.
@@ -132,12 +504,79 @@ pub fn new_wrapped<'env, T: JavaClass<'env>>(
     env: JniEnv<'env>,
     this: JObject<'env>,
 ) -> Result<JniRef<'env, T>> {
-    Ok(JniRef { this, inner: InnerRef::Default, env, phantom: PhantomData })
+    Ok(JniRef {
+        this,
+        inner: InnerRef::Default,
+        lock: LockPolicy::Block,
+        env,
+        phantom: PhantomData,
+    })
 }
 
 /// A [`JniRef`] that allows read-write access to its contents.
 pub type JniRefMut<'env, T> = JniRef<'env, T, JniRefWrite>;
 
+/// A guard giving exclusive access to the contents of a [`JniRef`], created with
+/// [`JniRef::write`].
+pub struct JniRefWriteGuard<'a, 'env, T: JavaClass<'env>, R: JniRefType> {
+    this: &'a mut JniRef<'env, T, R>,
+    downgrade_on_drop: bool,
+}
+impl<'a, 'env, T: JavaClass<'env>, R: JniRefType> Deref for JniRefWriteGuard<'a, 'env, T, R> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.this
+    }
+}
+impl<'a, 'env, T: JavaClass<'env>, R: JniRefType> DerefMut for JniRefWriteGuard<'a, 'env, T, R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match &mut self.this.inner {
+            InnerRef::Write(_, _, p) => p.deref_mut(),
+            _ => mut_ptr_fail(),
+        }
+    }
+}
+impl<'a, 'env, T: JavaClass<'env>, R: JniRefType> Drop for JniRefWriteGuard<'a, 'env, T, R> {
+    fn drop(&mut self) {
+        if self.downgrade_on_drop {
+            match std::mem::replace(&mut self.this.inner, InnerRef::Default) {
+                InnerRef::Write(id, mut held, write) => {
+                    held.set_mode(LockMode::Read);
+                    let read = ArcRwLockWriteGuard::downgrade(write);
+                    self.this.inner = InnerRef::Read(id, held, read)
+                }
+                _ => mut_ptr_fail(),
+            }
+        }
+    }
+}
+
+/// A guard giving shared access to the contents of a [`JniRef`], created with [`JniRef::read`].
+pub struct JniRefReadGuard<'a, 'env, T: JavaClass<'env>, R: JniRefType> {
+    this: &'a mut JniRef<'env, T, R>,
+    upgrade_on_drop: bool,
+}
+impl<'a, 'env, T: JavaClass<'env>, R: JniRefType> Deref for JniRefReadGuard<'a, 'env, T, R> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.this
+    }
+}
+impl<'a, 'env, T: JavaClass<'env>, R: JniRefType> Drop for JniRefReadGuard<'a, 'env, T, R> {
+    fn drop(&mut self) {
+        if self.upgrade_on_drop {
+            match std::mem::replace(&mut self.this.inner, InnerRef::Default) {
+                InnerRef::Upgradable(id, mut held, upgradable) => {
+                    held.set_mode(LockMode::Write);
+                    let write = ArcRwLockUpgradableReadGuard::upgrade(upgradable);
+                    self.this.inner = InnerRef::Write(id, held, write)
+                }
+                _ => mut_ptr_fail(),
+            }
+        }
+    }
+}
+
 #[inline(never)]
 fn mut_ptr_fail() -> ! {
     panic!("internal error: read-only lock in `JniRefMut`?")
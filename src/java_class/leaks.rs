@@ -0,0 +1,72 @@
+use backtrace::Backtrace;
+use parking_lot::{const_rwlock, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Where reports of leaked objects are written when the JVM shuts down.
+///
+/// An object is considered leaked if the Java object that owns it has not been closed or garbage
+/// collected by the time the JVM shuts down. This usually means that Java code forgot to call
+/// `close()` on it.
+pub enum LeakReporter {
+    /// Leaked objects are not reported.
+    Disabled,
+    /// Leaked objects are reported to stderr.
+    Stderr,
+    /// Leaked objects are reported to a custom function, which is called once for each leaked
+    /// object.
+    Custom(Box<dyn Fn(&str) + Send + Sync>),
+}
+
+static LEAK_REPORTER: RwLock<LeakReporter> = const_rwlock(LeakReporter::Disabled);
+static LEAK_REPORTING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Sets where reports of objects that are still allocated when the JVM shuts down are written.
+///
+/// In debug builds, the backtrace of where each leaked object was allocated is included in its
+/// report. This is only recorded for objects allocated while leak reporting is enabled, so this
+/// should be called as early as possible.
+pub fn set_leak_reporter(reporter: LeakReporter) {
+    let enabled = !matches!(reporter, LeakReporter::Disabled);
+    *LEAK_REPORTER.write() = reporter;
+    LEAK_REPORTING_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn is_enabled() -> bool {
+    LEAK_REPORTING_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Returns the backtrace to record for a newly allocated object, if any.
+pub(crate) fn allocation_backtrace() -> Option<Box<Backtrace>> {
+    if cfg!(debug_assertions) && is_enabled() {
+        Some(Box::new(Backtrace::new_unresolved()))
+    } else {
+        None
+    }
+}
+
+/// Resolves an allocation backtrace, removing the frames outside of the native method.
+pub(crate) fn trim_backtrace(backtrace: &Backtrace) -> Backtrace {
+    let mut backtrace = backtrace.clone();
+    backtrace.resolve();
+
+    let mut frames = Vec::new();
+    for frame in backtrace.frames() {
+        let is_entry_point = frame.symbols().iter().any(|symbol| match symbol.name() {
+            Some(name) => name.to_string().contains("__njni_entry_point"),
+            None => false,
+        });
+        if is_entry_point {
+            break;
+        }
+        frames.push(frame.clone());
+    }
+    frames.into()
+}
+
+pub(crate) fn report_leak(message: &str) {
+    match &*LEAK_REPORTER.read() {
+        LeakReporter::Disabled => {}
+        LeakReporter::Stderr => eprintln!("{message}"),
+        LeakReporter::Custom(func) => func(message),
+    }
+}
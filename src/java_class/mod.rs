@@ -1,9 +1,22 @@
+pub(crate) mod deadlock;
 pub mod exported_class;
+pub mod global_ref;
 pub mod jni_ref;
+pub mod leaks;
 pub mod object_id;
 
-use crate::{errors::*, java_class::exported_class::ExportedClass, JniEnv};
-use jni::objects::JObject;
+use crate::{
+    errors::*, internal::jni_entry::__njni_entry_point, java_class::exported_class::ExportedClass,
+    JniEnv,
+};
+use jni::{
+    objects::JObject,
+    strings::JNIString,
+    sys::{jclass, jstring},
+    JNIEnv, NativeMethod,
+};
+use parking_lot::RwLock;
+use std::sync::Arc;
 
 // TODO: Generate native-image configurations.
 
@@ -22,7 +35,8 @@ pub trait JavaClassImpl<'env>: JavaClassType + Sized + Send + Sync + 'static {
     fn create_jni_ref(
         env: JniEnv<'env>,
         obj: JObject<'env>,
-        id: Option<u32>,
+        id: Option<u64>,
+        lock: jni_ref::LockPolicy,
     ) -> Result<jni_ref::JniRef<'env, Self>>
     where
         Self: JavaClass<'env>;
@@ -30,11 +44,52 @@ pub trait JavaClassImpl<'env>: JavaClassType + Sized + Send + Sync + 'static {
 
 pub trait RustContents<'env>: JavaClass<'env> {
     const ID_FIELD: &'static str;
+    const LOCK_FREE: bool;
+    /// Whether the class was declared with `#[jni(from_rust)]`, and can be created with
+    /// [`JniRef::new_from_rust`](jni_ref::JniRef::new_from_rust).
+    const FROM_RUST: bool;
+
+    /// Runs the `#[jni(on_close)]` hook of the class, if it has one.
+    ///
+    /// The hook locks the object like any other method, so closing an object from inside one of
+    /// its own methods deadlocks if the two locks conflict (e.g. a `&mut self` hook called from a
+    /// `&self` method). With the `deadlock_detection` feature, this raises an
+    /// `IllegalStateException` instead.
+    fn on_close(_this: &Arc<RwLock<Self>>, _env: JniEnv<'env>) -> Result<()> {
+        Ok(())
+    }
 }
 
-pub trait JavaModule: JavaModuleImpl {}
+/// A trait representing the module generated by [`jni_module!`](`crate::jni_module`).
+pub trait JavaModule: JavaModuleImpl {
+    /// Launches a new JVM in the current process with this module loaded into it, and runs a
+    /// function with a [`JniEnv`] attached to it.
+    ///
+    /// The classes generated for this module are defined directly into the new JVM using a
+    /// dedicated class loader, and their native methods are registered without loading a native
+    /// library with `System.loadLibrary`. This allows Rust binaries to embed Java libraries and
+    /// call them using the classes exported from this module.
+    ///
+    /// The current thread stays attached to the new JVM until it exits. Use [`JniEnv::vm`] to
+    /// keep a handle to the JVM after the function returns.
+    ///
+    /// Only one JVM may be created in a single process. This will fail if one already exists.
+    #[cfg(feature = "invocation")]
+    fn launch_jvm<R>(
+        options: crate::JvmOptions,
+        func: impl FnOnce(JniEnv) -> Result<R>,
+    ) -> Result<R>
+    where Self: Sized {
+        crate::jni_env::launch_jvm::<Self, R, _>(options, func)
+    }
+}
 pub trait JavaModuleImpl {
     fn get_info(&self) -> &'static JavaModuleInfo;
+
+    /// Returns the same information as [`JavaModuleImpl::get_info`] without an instance of the
+    /// module, for use in native methods registered by nekojni itself.
+    fn get_info_static() -> &'static JavaModuleInfo
+    where Self: Sized;
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -62,5 +117,53 @@ impl JavaModuleInfo {
         std::str::from_utf8_unchecked(str)
     }
 }
+impl JavaModuleInfo {
+    pub unsafe fn register_natives(&self, env: JniEnv) -> Result<()> {
+        for class in self.class_info {
+            class.exported.register_natives(env)?;
+        }
+        env.register_native_methods(self.init_class_name, &[NativeMethod {
+            name: JNIString::from("nativeObjectStats"),
+            sig: JNIString::from("()Ljava/lang/String;"),
+            fn_ptr: native_object_stats as *mut _,
+        }])?;
+        Ok(())
+    }
+}
+
+extern "system" fn native_object_stats(env: JNIEnv, _class: jclass) -> jstring {
+    __njni_entry_point(
+        env,
+        |env| {
+            // this is parsed by `ModuleInitWrapper.objectStats`
+            let mut out = String::new();
+            for (name, stats) in env.all_object_stats() {
+                out.push_str(&format!(
+                    "{} {} {} {} {}\n",
+                    name.replace('/', "."),
+                    stats.live,
+                    stats.high_water_mark,
+                    stats.allocations,
+                    stats.frees,
+                ));
+            }
+            Ok(out)
+        },
+        "java/lang/RuntimeException",
+    )
+}
+#[cfg(feature = "classfile")]
+impl JavaModuleInfo {
+    /// Generates the Java-side classes for this module.
+    ///
+    /// A native library loader must already have been added to the class data.
+    pub fn generate_classes(&self, data: &mut nekojni_utils::ClassData) {
+        data.add_module_loader(self.init_class_name);
+        data.add_module_exception(self.except_class_name);
+        for class in self.class_info {
+            class.exported.generate_class(data, self.init_class_name);
+        }
+    }
+}
 unsafe impl Send for JavaModuleInfo {}
 unsafe impl Sync for JavaModuleInfo {}
@@ -1,87 +1,201 @@
-use crate::errors::*;
+use crate::{errors::*, java_class::leaks};
+use backtrace::Backtrace;
 use parking_lot::RwLock;
-use std::{any::type_name, sync::Arc};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
-const ENFORCED_MAX: usize = 0x7FFFFFFF;
+// IDs are packed into a Java `long`, with the index of the slot in the low 32 bits, and a
+// generation counter in the high 32 bits. The generation is incremented whenever a slot is freed,
+// so stale IDs pointing to a reused slot can be detected.
+const INDEX_BITS: u32 = 32;
+const INDEX_MASK: u64 = (1 << INDEX_BITS) - 1;
+const ENFORCED_MAX: usize = INDEX_MASK as usize;
 
 enum FreeListNode<T> {
-    Data(T),
-    Free(usize),
+    Data(u32, T, Option<Box<Backtrace>>),
+    Free(u32, usize),
+}
+
+fn pack_id(index: usize, generation: u32) -> u64 {
+    ((generation as u64) << INDEX_BITS) | index as u64
+}
+fn unpack_id(id: u64) -> (usize, u32) {
+    ((id & INDEX_MASK) as usize, (id >> INDEX_BITS) as u32)
+}
+
+#[inline(never)]
+fn use_after_free(type_name: &str) -> Error {
+    Error::message(format!("Object of type '{type_name}' was used after it was closed."))
+        .set_exception_class("java/lang/IllegalStateException")
 }
 
 struct IdManagerData<T: Send + Sync> {
     nodes: Vec<FreeListNode<Arc<T>>>,
     head: usize,
+    type_name: &'static str,
 }
 impl<T: Send + Sync> IdManagerData<T> {
-    const fn new() -> Self {
-        IdManagerData { nodes: Vec::new(), head: 0 }
+    const fn new(type_name: &'static str) -> Self {
+        IdManagerData { nodes: Vec::new(), head: 0, type_name }
     }
 
-    fn get(&self, id: u32) -> Result<Arc<T>> {
-        let type_name = type_name::<T>();
-        let id = id as usize;
-        if id >= self.nodes.len() {
+    fn get(&self, id: u64) -> Result<Arc<T>> {
+        let type_name = self.type_name;
+        let (index, generation) = unpack_id(id);
+        if index >= self.nodes.len() {
             jni_bail!("freelist for '{type_name}': id after end of list in get");
         } else {
-            match &self.nodes[id] {
-                FreeListNode::Data(v) => Ok(v.clone()),
-                FreeListNode::Free(_) => jni_bail!("freelist for '{type_name}': use after free"),
+            match &self.nodes[index] {
+                FreeListNode::Data(gen, v, _) if *gen == generation => Ok(v.clone()),
+                _ => Err(use_after_free(type_name)),
             }
         }
     }
-    fn allocate(&mut self, t: T) -> Result<u32> {
-        let type_name = type_name::<T>();
+    fn allocate(&mut self, t: T, backtrace: Option<Box<Backtrace>>) -> Result<u64> {
+        let type_name = self.type_name;
         let t = Arc::new(t);
         if self.head > ENFORCED_MAX {
             jni_bail!("freelist for '{type_name}': out of allocatable ids");
         } else if self.head < self.nodes.len() {
             let new_id = self.head;
-            let new_head = match &self.nodes[new_id] {
-                FreeListNode::Data(_) => jni_bail!("freelist for '{type_name}': already allocated"),
-                FreeListNode::Free(head) => *head,
+            let (generation, new_head) = match &self.nodes[new_id] {
+                FreeListNode::Data(..) => {
+                    jni_bail!("freelist for '{type_name}': already allocated")
+                }
+                FreeListNode::Free(generation, head) => (*generation, *head),
             };
-            self.nodes[new_id] = FreeListNode::Data(t);
+            self.nodes[new_id] = FreeListNode::Data(generation, t, backtrace);
             self.head = new_head;
-            Ok(new_id as u32)
+            Ok(pack_id(new_id, generation))
         } else {
             let new_id = self.head;
-            self.nodes.push(FreeListNode::Data(t));
+            self.nodes.push(FreeListNode::Data(0, t, backtrace));
             self.head += 1;
-            Ok(new_id as u32)
+            Ok(pack_id(new_id, 0))
         }
     }
-    fn free(&mut self, id: u32) -> Result<()> {
-        let type_name = type_name::<T>();
-        let id = id as usize;
-        if id >= self.nodes.len() {
+    fn free(&mut self, id: u64) -> Result<Arc<T>> {
+        let type_name = self.type_name;
+        let (index, generation) = unpack_id(id);
+        if index >= self.nodes.len() {
             jni_bail!("freelist for '{type_name}': id after end of list in free");
         } else {
-            match &self.nodes[id] {
-                FreeListNode::Data(_) => {
-                    self.nodes[id] = FreeListNode::Free(self.head);
-                    self.head = id;
-                    Ok(())
+            match &self.nodes[index] {
+                FreeListNode::Data(gen, ..) if *gen == generation => {
+                    let next_generation = generation.wrapping_add(1);
+                    let node = std::mem::replace(
+                        &mut self.nodes[index],
+                        FreeListNode::Free(next_generation, self.head),
+                    );
+                    self.head = index;
+                    match node {
+                        FreeListNode::Data(_, v, _) => Ok(v),
+                        FreeListNode::Free(..) => unreachable!(),
+                    }
+                }
+                _ => Err(use_after_free(type_name)),
+            }
+        }
+    }
+
+    fn report_leaks(&self, class_name: &str) {
+        let type_name = self.type_name;
+        for (index, node) in self.nodes.iter().enumerate() {
+            if let FreeListNode::Data(generation, _, backtrace) = node {
+                let id = pack_id(index, *generation);
+                let mut message = format!(
+                    "nekojni: Object of type `{type_name}` owned by `{class_name}` (id {id:#x}) \
+                     was still allocated when the JVM shut down."
+                );
+                if let Some(backtrace) = backtrace {
+                    let backtrace = leaks::trim_backtrace(backtrace);
+                    message.push_str(&format!("\nAllocated at:\n{backtrace:?}"));
                 }
-                FreeListNode::Free(_) => jni_bail!("freelist for '{type_name}': use after free"),
+                leaks::report_leak(&message);
             }
         }
     }
 }
 
-pub struct IdManager<T: Send + Sync>(RwLock<IdManagerData<T>>);
+/// Statistics about the Rust objects owned by instances of an exported class.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct ObjectStats {
+    /// The number of objects that are currently allocated.
+    pub live: u64,
+    /// The largest number of objects that have been allocated at the same time.
+    pub high_water_mark: u64,
+    /// The total number of objects that have been allocated.
+    pub allocations: u64,
+    /// The total number of objects that have been freed.
+    pub frees: u64,
+}
+
+#[derive(Default)]
+struct IdCounters {
+    live: AtomicU64,
+    high_water_mark: AtomicU64,
+    allocations: AtomicU64,
+    frees: AtomicU64,
+}
+impl IdCounters {
+    fn allocated(&self) {
+        let live = self.live.fetch_add(1, Ordering::Relaxed) + 1;
+        self.high_water_mark.fetch_max(live, Ordering::Relaxed);
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+    }
+    fn freed(&self) {
+        self.live.fetch_sub(1, Ordering::Relaxed);
+        self.frees.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> ObjectStats {
+        ObjectStats {
+            live: self.live.load(Ordering::Relaxed),
+            high_water_mark: self.high_water_mark.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+            frees: self.frees.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct IdManager<T: Send + Sync> {
+    data: RwLock<IdManagerData<T>>,
+    counters: IdCounters,
+}
 impl<T: Send + Sync> IdManager<T> {
-    pub const fn new() -> Self {
-        IdManager(RwLock::new(IdManagerData::new()))
+    pub fn new(type_name: &'static str) -> Self {
+        IdManager { data: RwLock::new(IdManagerData::new(type_name)), counters: Default::default() }
     }
 
-    pub fn get(&self, id: u32) -> Result<Arc<T>> {
-        self.0.read().get(id)
+    pub fn get(&self, id: u64) -> Result<Arc<T>> {
+        self.data.read().get(id)
+    }
+    pub fn allocate(&self, t: T) -> Result<u64> {
+        // capturing a backtrace is slow, so it must not be done while the lock is held
+        let backtrace = leaks::allocation_backtrace();
+        let id = self.data.write().allocate(t, backtrace)?;
+        self.counters.allocated();
+        Ok(id)
+    }
+    pub fn free(&self, id: u64) -> Result<Arc<T>> {
+        let value = self.data.write().free(id)?;
+        self.counters.freed();
+        Ok(value)
     }
-    pub fn allocate(&self, t: T) -> Result<u32> {
-        self.0.write().allocate(t)
+}
+
+/// The operations on an [`IdManager`] that do not depend on the type of object it contains.
+pub trait IdManagerInfo: Send + Sync {
+    fn stats(&self) -> ObjectStats;
+    fn report_leaks(&self, class_name: &str);
+}
+impl<T: Send + Sync> IdManagerInfo for IdManager<T> {
+    fn stats(&self) -> ObjectStats {
+        self.counters.stats()
     }
-    pub fn free(&self, id: u32) -> Result<()> {
-        self.0.write().free(id)
+    fn report_leaks(&self, class_name: &str) {
+        self.data.read().report_leaks(class_name)
     }
 }
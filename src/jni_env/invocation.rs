@@ -0,0 +1,173 @@
+use crate::{
+    conversions::{JavaConversion, JavaConversionOwned},
+    errors::*,
+    internal::jni_entry::__njni_entry_point,
+    java_class::{JavaModuleImpl, JavaModuleInfo},
+    jni_env::{jni_new_ref, JniEnv, JniVm},
+};
+use jni::{
+    objects::{JObject, JValue},
+    strings::JNIString,
+    sys::jclass,
+    InitArgsBuilder, JNIEnv, JNIVersion, JavaVM, NativeMethod,
+};
+use nekojni_utils::{signatures::ClassName, ClassData, GenerationOptions, JavaVersion};
+use std::collections::{HashMap, HashSet};
+
+/// The options used to launch a new JVM with [`JavaModule::launch_jvm`].
+///
+/// [`JavaModule::launch_jvm`]: crate::objects::JavaModule::launch_jvm
+#[derive(Clone, Debug, Default)]
+pub struct JvmOptions {
+    /// Additional paths to add to the classpath of the JVM.
+    pub classpath: Vec<String>,
+    /// Additional options to pass to the JVM, such as `-Xmx1G`.
+    pub jvm_args: Vec<String>,
+    /// Whether the JVM should ignore options in `jvm_args` that it does not recognize.
+    pub ignore_unrecognized: bool,
+    /// Whether objects should be freed using a `java.lang.ref.Cleaner` rather than `finalize`.
+    ///
+    /// This requires Java 9 or later.
+    pub use_cleaner: bool,
+}
+
+pub(crate) fn launch_jvm<M: JavaModuleImpl, R, F: FnOnce(JniEnv) -> Result<R>>(
+    options: JvmOptions,
+    func: F,
+) -> Result<R> {
+    // build the arguments for the JVM
+    let mut args = InitArgsBuilder::new()
+        .version(JNIVersion::V8)
+        .ignore_unrecognized(options.ignore_unrecognized);
+    if !options.classpath.is_empty() {
+        let separator = if cfg!(windows) { ";" } else { ":" };
+        let classpath = options.classpath.join(separator);
+        args = args.option(&format!("-Djava.class.path={classpath}"));
+    }
+    for arg in &options.jvm_args {
+        args = args.option(arg);
+    }
+
+    // create the JVM and attach the current thread to it
+    let vm = JavaVM::new(args.build()?)?;
+    let cache = jni_new_ref(vm.attach_current_thread_permanently()?)?;
+    let vm = JniVm::new(vm, cache);
+
+    vm.attach_current_thread(|env| {
+        let info = M::get_info_static();
+        define_module_classes(env, info, &options)?;
+
+        // `ModuleInitWrapper` calls this after the null loader, instead of relying on the
+        // `Java_*` symbol being found by `System.loadLibrary`.
+        env.register_native_methods(env.find_class(info.init_class_name)?, &[NativeMethod {
+            name: JNIString::from("initialize"),
+            sig: JNIString::from("()V"),
+            fn_ptr: initialize_module::<M> as *mut _,
+        }])?;
+
+        func(env)
+    })
+}
+
+/// Defines all classes generated for a module into the JVM.
+///
+/// The classes are defined into a new class loader that delegates to the system class loader, so
+/// they can still use classes from the classpath. As `FindClass` only searches the system class
+/// loader on threads attached by Rust code, the new class loader is also registered with the
+/// [`JniEnv`] cache so that [`JniEnv::find_class`] can find the module's classes.
+fn define_module_classes(
+    env: JniEnv,
+    info: &'static JavaModuleInfo,
+    options: &JvmOptions,
+) -> Result<()> {
+    let loader = ClassName::parse_jni(info.init_class_name)?;
+    let loader_name = format!("{}/nekojni_rt/InvocationLoader", loader.package.join("/"));
+
+    let options = GenerationOptions {
+        use_cleaner: options.use_cleaner,
+        target_version: running_java_version(env)?,
+    };
+    if let Err(e) = options.validate() {
+        jni_bail!("{e}");
+    }
+    let mut data = ClassData::with_options(options);
+    data.add_null_loader(&loader_name);
+    info.generate_classes(&mut data);
+
+    // superclasses need to be defined before any classes that extend them
+    let mut super_classes = HashMap::new();
+    for class in info.class_info {
+        if let Some(super_class) = class.exported.super_class {
+            super_classes.insert(class.exported.name, super_class);
+        }
+    }
+
+    let class_loader = new_class_loader(env)?;
+    *env.cache.class_loader.lock() = Some(env.new_global_ref(class_loader)?);
+
+    let classes: HashMap<_, _> = data.classes().collect();
+    let mut defined = HashSet::new();
+    for name in classes.keys() {
+        define_class(env, class_loader, &classes, &super_classes, &mut defined, name)?;
+    }
+
+    Ok(())
+}
+
+/// Creates the class loader the classes of a module are defined into.
+fn new_class_loader(env: JniEnv) -> Result<JObject> {
+    let system_loader = env
+        .call_static_method(
+            "java/lang/ClassLoader",
+            "getSystemClassLoader",
+            "()Ljava/lang/ClassLoader;",
+            &[],
+        )?
+        .l()?;
+    let urls = env.new_object_array(0, "java/net/URL", JObject::null())?;
+    Ok(env.new_object(
+        "java/net/URLClassLoader",
+        "([Ljava/net/URL;Ljava/lang/ClassLoader;)V",
+        &[JValue::Object(urls.into()), JValue::Object(system_loader)],
+    )?)
+}
+
+/// Returns the newest Java version that classes defined into the running JVM may target.
+fn running_java_version(env: JniEnv) -> Result<JavaVersion> {
+    let version = env.call_static_method(
+        "java/lang/System",
+        "getProperty",
+        "(Ljava/lang/String;)Ljava/lang/String;",
+        &["java.specification.version".to_string().to_java_value(env)],
+    )?;
+    let version = String::from_java_value(version, env)?;
+
+    // Java 8 and earlier use versions of the form `1.8`
+    let release = version.strip_prefix("1.").unwrap_or(&version);
+    let release = release.parse::<u8>().unwrap_or(0);
+    Ok(JavaVersion::new(release.min(JavaVersion::JAVA_21.release())).unwrap_or_default())
+}
+
+fn define_class<'a>(
+    env: JniEnv,
+    class_loader: JObject,
+    classes: &HashMap<&'a str, &[u8]>,
+    super_classes: &HashMap<&str, &'a str>,
+    defined: &mut HashSet<&'a str>,
+    name: &'a str,
+) -> Result<()> {
+    if defined.insert(name) {
+        if let Some(super_class) = super_classes.get(name) {
+            if classes.contains_key(super_class) {
+                define_class(env, class_loader, classes, super_classes, defined, super_class)?;
+            }
+        }
+        env.define_class(name, class_loader, classes[name])?;
+    }
+    Ok(())
+}
+
+extern "system" fn initialize_module<M: JavaModuleImpl>(env: JNIEnv, _class: jclass) {
+    let info = M::get_info_static();
+    __njni_entry_point(env, |env| unsafe { info.register_natives(env) }, info.except_class_name)
+}
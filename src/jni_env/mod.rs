@@ -1,21 +1,43 @@
+#[cfg(feature = "invocation")]
+mod invocation;
 pub mod objects;
 mod param_traits;
+mod vm;
 
-use crate::{errors::*, java_class::object_id::IdManager};
+#[cfg(feature = "invocation")]
+pub use invocation::JvmOptions;
+pub use vm::JniVm;
+
+#[cfg(feature = "invocation")]
+pub(crate) use invocation::launch_jvm;
+pub(crate) use vm::RefKind;
+
+use crate::{
+    errors::*,
+    java_class::{
+        leaks,
+        object_id::{IdManager, IdManagerInfo, ObjectStats},
+        JavaClass, JavaClassType,
+    },
+    jni_env::objects::AutoLocal,
+};
 use chashmap::CHashMap;
 use jni::{
-    objects::{JObject, JValue},
+    objects::{GlobalRef, JClass, JObject, JValue},
     strings::JNIString,
-    sys::jclass,
+    sys::{jclass, jobject},
     JNIEnv, NativeMethod,
 };
 use lazy_static::lazy_static;
-use parking_lot::{lock_api::ArcRwLockUpgradableReadGuard, RwLock};
+use parking_lot::{lock_api::ArcRwLockUpgradableReadGuard, Mutex, RwLock};
 use std::{
-    any::{Any, TypeId},
+    any::{type_name, Any, TypeId},
     marker::PhantomData,
     ops::Deref,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 #[derive(Default)]
@@ -26,6 +48,25 @@ struct TransientCache<'env> {
 #[derive(Default)]
 struct JniEnvCacheData {
     rust_objects: CHashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    deferred_refs: Mutex<Vec<(RefKind, usize)>>,
+    has_deferred_refs: AtomicBool,
+    id_managers: Mutex<Vec<(&'static str, Arc<dyn IdManagerInfo>)>>,
+    class_loader: Mutex<Option<GlobalRef>>,
+}
+impl JniEnvCacheData {
+    fn defer_delete_ref(&self, kind: RefKind, obj: jobject) {
+        let mut lock = self.deferred_refs.lock();
+        lock.push((kind, obj as usize));
+        self.has_deferred_refs.store(true, Ordering::Release);
+    }
+    fn delete_deferred_refs(&self, env: JNIEnv) {
+        if self.has_deferred_refs.swap(false, Ordering::Acquire) {
+            let refs = std::mem::take(&mut *self.deferred_refs.lock());
+            for (kind, obj) in refs {
+                unsafe { vm::delete_ref_raw(env, kind, obj as jobject) }
+            }
+        }
+    }
 }
 
 #[derive(Default)]
@@ -96,10 +137,15 @@ extern "system" fn jni_shutdown(env: JNIEnv, class: jclass) {
     crate::internal::jni_entry::__njni_entry_point(
         env,
         |env| {
+            if leaks::is_enabled() {
+                env.report_leaks();
+            }
+
             let offset = vm_offset(*env).expect("Could not find offset?");
             CACHES
                 .remove(&offset)
                 .expect("JNIEnv has already been shutdown?");
+            Ok(())
         },
         "java/lang/RuntimeException",
     )
@@ -109,16 +155,33 @@ extern "system" fn jni_shutdown(env: JNIEnv, class: jclass) {
 #[derive(Copy, Clone)]
 pub struct JniEnv<'env> {
     env: JNIEnv<'env>,
-    cache: &'env JniEnvCacheData,
+    cache: &'env Arc<JniEnvCacheData>,
     transient_cache: &'env TransientCache<'env>,
 }
 impl<'env> JniEnv<'env> {
     /// Creates a new [`JniEnv`] wrapping this class.
     pub(crate) fn with_env<R>(env: JNIEnv, func: impl FnOnce(JniEnv) -> Result<R>) -> Result<R> {
         let data = jni_new_ref(env)?;
+        data.delete_deferred_refs(env);
+        Self::with_cache(env, &data, func)
+    }
+
+    /// Creates a new [`JniEnv`] wrapping this class, using an already retrieved cache.
+    fn with_cache<R>(
+        env: JNIEnv,
+        cache: &Arc<JniEnvCacheData>,
+        func: impl FnOnce(JniEnv) -> Result<R>,
+    ) -> Result<R> {
         let transient_cache = TransientCache::default();
-        let ret_val = func(JniEnv { env, cache: &data, transient_cache: &transient_cache });
-        ret_val
+        func(JniEnv { env, cache, transient_cache: &transient_cache })
+    }
+
+    /// Returns a handle to the JVM this environment belongs to.
+    ///
+    /// Unlike a [`JniEnv`], this handle may be stored and sent to other threads, and is used to
+    /// call into Java from threads not created by the JVM.
+    pub fn vm(&self) -> Result<JniVm> {
+        Ok(JniVm::new(self.env.get_java_vm()?, self.cache.clone()))
     }
 
     /// Returns an instance of an object for this entire JVM.
@@ -150,8 +213,44 @@ impl<'env> JniEnv<'env> {
     }
 
     /// Returns the ID manager for this type.
-    pub(crate) fn get_id_manager<T: Any + Send + Sync>(&self) -> Arc<IdManager<RwLock<T>>> {
-        self.get_jvm_instance(IdManager::new)
+    pub(crate) fn get_id_manager<T: JavaClassType + Any + Send + Sync>(
+        &self,
+    ) -> Arc<IdManager<RwLock<T>>> {
+        let mut created = false;
+        let manager = self.get_jvm_instance(|| {
+            created = true;
+            IdManager::new(type_name::<T>())
+        });
+        if created {
+            let info: Arc<dyn IdManagerInfo> = manager.clone();
+            self.cache.id_managers.lock().push((T::JNI_TYPE, info));
+        }
+        manager
+    }
+
+    /// Reports all Rust objects that are still allocated to the configured [`LeakReporter`].
+    ///
+    /// [`LeakReporter`]: crate::LeakReporter
+    fn report_leaks(&self) {
+        let managers = self.cache.id_managers.lock();
+        for (name, manager) in managers.iter() {
+            manager.report_leaks(&name.replace('/', "."));
+        }
+    }
+
+    /// Returns statistics about the Rust objects owned by instances of an exported class.
+    pub fn object_stats<T: JavaClass<'env>>(&self) -> ObjectStats {
+        self.get_id_manager::<T>().stats()
+    }
+
+    /// Returns statistics about the Rust objects owned by each exported class that has been used
+    /// in this JVM, along with the JNI name of each class.
+    pub fn all_object_stats(&self) -> Vec<(&'static str, ObjectStats)> {
+        let managers = self.cache.id_managers.lock();
+        managers
+            .iter()
+            .map(|(name, manager)| (*name, manager.stats()))
+            .collect()
     }
 
     /// Returns the inner [`JNIEnv`].
@@ -165,6 +264,35 @@ impl<'env> JniEnv<'env> {
         self.env
     }
 
+    /// Finds a class by its JNI name.
+    ///
+    /// Unlike [`JNIEnv::find_class`], this also searches the class loader the module's classes
+    /// were defined into when the JVM was launched with [`JavaModule::launch_jvm`]. `FindClass`
+    /// only searches the system class loader on threads attached from Rust code.
+    ///
+    /// [`JavaModule::launch_jvm`]: crate::objects::JavaModule::launch_jvm
+    pub fn find_class(&self, name: &str) -> Result<JClass<'env>> {
+        match self.env.find_class(name) {
+            Err(jni::errors::Error::JavaException) => {
+                let loader = match &*self.cache.class_loader.lock() {
+                    Some(loader) => loader.clone(),
+                    None => return Err(jni::errors::Error::JavaException.into()),
+                };
+                self.env.exception_clear()?;
+
+                let name = self.env.new_string(name.replace('/', "."))?;
+                let class = self.env.call_method(
+                    JObject::from(loader.as_obj().into_inner()),
+                    "loadClass",
+                    "(Ljava/lang/String;)Ljava/lang/Class;",
+                    &[JValue::Object(name.into())],
+                )?;
+                Ok(JClass::from(class.l()?))
+            }
+            class => Ok(class?),
+        }
+    }
+
     /// Returns the value of a field in an object.
     ///
     /// This can not retrieve private fields from a subclass of a class. If you need to do so, use
@@ -173,6 +301,53 @@ impl<'env> JniEnv<'env> {
         unsafe { Ok(self.as_inner().get_field(obj, name, ty)?) }
     }
 
+    /// Runs a function in a new local reference frame, deleting all local references created
+    /// inside it once the function returns.
+    ///
+    /// This should be used in loops that create many Java objects, as each local reference takes
+    /// up space in the local reference table of the JVM until the native method returns.
+    ///
+    /// `capacity` is the number of local references the frame should be able to hold. Use
+    /// [`JniEnv::with_local_frame_returning`] to return a Java object out of the frame.
+    ///
+    /// The function is passed a new [`JniEnv`] that only lives as long as the frame, so objects
+    /// created with it cannot escape the frame. Objects created with an environment captured from
+    /// outside the function are still deleted when the frame is popped.
+    pub fn with_local_frame<R>(
+        &self,
+        capacity: i32,
+        func: impl for<'frame> FnOnce(JniEnv<'frame>) -> Result<R>,
+    ) -> Result<R> {
+        self.env.push_local_frame(capacity)?;
+        let result = Self::with_cache(self.env, self.cache, func);
+        self.env.pop_local_frame(JObject::null())?;
+        result
+    }
+
+    /// Runs a function in a new local reference frame, returning a Java object out of it.
+    ///
+    /// The returned object is moved into the enclosing frame. All other local references created
+    /// inside the function are deleted once it returns.
+    pub fn with_local_frame_returning(
+        &self,
+        capacity: i32,
+        func: impl for<'frame> FnOnce(JniEnv<'frame>) -> Result<JObject<'frame>>,
+    ) -> Result<JObject<'env>> {
+        self.env.push_local_frame(capacity)?;
+        match Self::with_cache(self.env, self.cache, |env| Ok(func(env)?.into_inner())) {
+            Ok(obj) => Ok(self.env.pop_local_frame(JObject::from(obj))?),
+            Err(e) => {
+                self.env.pop_local_frame(JObject::null())?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Wraps a local reference so that it is deleted when it goes out of scope.
+    pub fn auto_local(&self, obj: impl Into<JObject<'env>>) -> AutoLocal<'env> {
+        AutoLocal::new(*self, obj.into())
+    }
+
     // TODO: Finish
     /*/// Returns the value of a private field in an object.
     pub fn get_private_field<O>(
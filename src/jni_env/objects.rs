@@ -2,6 +2,40 @@ use crate::{conversions::JavaConversionOwned, JniEnv};
 use jni::objects::JObject;
 use std::marker::PhantomData;
 
+/// A local reference that is deleted when it goes out of scope.
+///
+/// This is created with [`JniEnv::auto_local`].
+pub struct AutoLocal<'env> {
+    env: JniEnv<'env>,
+    obj: JObject<'env>,
+}
+impl<'env> AutoLocal<'env> {
+    pub(crate) fn new(env: JniEnv<'env>, obj: JObject<'env>) -> Self {
+        AutoLocal { env, obj }
+    }
+
+    /// Returns the object this reference points to.
+    ///
+    /// The returned object must not be used after this is dropped.
+    pub fn as_obj(&self) -> JObject<'env> {
+        self.obj
+    }
+
+    /// Returns the object this reference points to, without deleting the reference.
+    pub fn forget(self) -> JObject<'env> {
+        let obj = self.obj;
+        std::mem::forget(self);
+        obj
+    }
+}
+impl<'env> Drop for AutoLocal<'env> {
+    fn drop(&mut self) {
+        if !self.obj.is_null() {
+            self.env.delete_local_ref(self.obj).ok();
+        }
+    }
+}
+
 pub struct JArray<'env, T: JavaConversionOwned<'env>> {
     env: JniEnv<'env>,
     obj: JObject<'env>,
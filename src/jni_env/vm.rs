@@ -0,0 +1,79 @@
+use crate::{
+    errors::*,
+    jni_env::{JniEnv, JniEnvCacheData},
+};
+use jni::{sys::jobject, JNIEnv, JavaVM};
+use std::sync::Arc;
+
+/// The kind of a reference that can be deleted from any thread with [`JniVm::delete_ref`].
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum RefKind {
+    Global,
+    Weak,
+}
+
+/// A handle to a running JVM that may be stored and shared between threads.
+///
+/// This is primarily used to call into Java from threads that were not created by the JVM, such as
+/// background worker threads or thread pools. It can be retrieved from [`JniEnv::vm`].
+///
+/// Threads attached with this handle stay attached until they exit, at which point they are
+/// detached automatically. This means repeatedly attaching from the same thread is cheap.
+#[derive(Clone)]
+pub struct JniVm {
+    vm: Arc<JavaVM>,
+    cache: Arc<JniEnvCacheData>,
+}
+impl JniVm {
+    pub(super) fn new(vm: JavaVM, cache: Arc<JniEnvCacheData>) -> Self {
+        JniVm { vm: Arc::new(vm), cache }
+    }
+
+    /// Attaches the current thread to the JVM if needed, and runs a function with a [`JniEnv`].
+    ///
+    /// Attached threads prevent the JVM from exiting until they are detached. Use
+    /// [`JniVm::attach_current_thread_as_daemon`] for threads that should not do this.
+    pub fn attach_current_thread<R>(&self, func: impl FnOnce(JniEnv) -> Result<R>) -> Result<R> {
+        let env = self.vm.attach_current_thread_permanently()?;
+        self.cache.delete_deferred_refs(env);
+        JniEnv::with_cache(env, &self.cache, func)
+    }
+
+    /// Attaches the current thread to the JVM as a daemon thread if needed, and runs a function
+    /// with a [`JniEnv`].
+    ///
+    /// If the current thread is already attached, this will not change whether it is a daemon.
+    pub fn attach_current_thread_as_daemon<R>(
+        &self,
+        func: impl FnOnce(JniEnv) -> Result<R>,
+    ) -> Result<R> {
+        let env = self.vm.attach_current_thread_as_daemon()?;
+        self.cache.delete_deferred_refs(env);
+        JniEnv::with_cache(env, &self.cache, func)
+    }
+
+    /// Returns whether the current thread is attached to this JVM.
+    pub fn is_current_thread_attached(&self) -> bool {
+        self.vm.get_env().is_ok()
+    }
+
+    /// Deletes a global or weak global reference.
+    ///
+    /// If the current thread is not attached to the JVM, the reference is instead queued to be
+    /// deleted the next time any thread calls into Rust code from this JVM, or is attached to it
+    /// with a [`JniVm`].
+    pub(crate) unsafe fn delete_ref(&self, kind: RefKind, obj: jobject) {
+        match self.vm.get_env() {
+            Ok(env) => delete_ref_raw(env, kind, obj),
+            Err(_) => self.cache.defer_delete_ref(kind, obj),
+        }
+    }
+}
+
+pub(super) unsafe fn delete_ref_raw(env: JNIEnv, kind: RefKind, obj: jobject) {
+    let raw = env.get_native_interface();
+    match kind {
+        RefKind::Global => ((**raw).DeleteGlobalRef.unwrap())(raw, obj),
+        RefKind::Weak => ((**raw).DeleteWeakGlobalRef.unwrap())(raw, obj),
+    }
+}
@@ -10,8 +10,15 @@ mod java_class;
 mod jni_env;
 
 pub use errors::{Error, Result};
-pub use java_class::jni_ref::{JniRef, JniRefMut};
-pub use jni_env::JniEnv;
+pub use java_class::{
+    global_ref::{GlobalRef, WeakRef},
+    jni_ref::{JniRef, JniRefMut, JniRefReadGuard, JniRefWriteGuard},
+    leaks::{set_leak_reporter, LeakReporter},
+    object_id::ObjectStats,
+};
+pub use jni_env::{JniEnv, JniVm};
+#[cfg(feature = "invocation")]
+pub use jni_env::JvmOptions;
 
 /// The module containing the types used for conversions between Java and Rust types.
 // TODO: Make this private.
@@ -99,16 +106,8 @@ macro_rules! jni_module {
                 /// called.
                 #[jni(__njni_direct_export = $init_class_name)]
                 pub fn initialize(env: $crate::JniEnv) -> Result<()> {
-                    let info = crate::$module_name.get_info();
-
                     // load all native methods from all classes
-                    for class in info.class_info {
-                        unsafe {
-                            class.exported.register_natives(env)?;
-                        }
-                    }
-
-                    Ok(())
+                    unsafe { crate::$module_name.get_info().register_natives(env) }
                 }
 
                 /// A method exported from the .so/.dylib/.dll to allow the cli tool to pull
@@ -126,8 +125,12 @@ macro_rules! jni_module {
             const CL_ID: usize = <InitHelper as JavaClassImpl>::INIT_ID;
             impl $crate::objects::JavaModule for crate::$module_name { }
             impl JavaModuleImpl for crate::$module_name {
-                #[inline(never)]
                 fn get_info(&self) -> &'static JavaModuleInfo {
+                    Self::get_info_static()
+                }
+
+                #[inline(never)]
+                fn get_info_static() -> &'static JavaModuleInfo {
                     static CACHE: OnceCache<JavaModuleInfo> = OnceCache::new();
                     CACHE.init(|| {
                         static CACHE: OnceCache<Vec<&JavaClassInfo>> = OnceCache::new();
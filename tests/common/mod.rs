@@ -0,0 +1,21 @@
+use nekojni::{objects::JavaModule, JniEnv, JvmOptions, Result};
+
+/// A test run inside the JVM.
+pub type JvmTest = fn(JniEnv) -> Result<()>;
+
+/// Launches a JVM with the classes of the module `M`, and runs each test in it.
+///
+/// A process can only ever create one JVM, so these tests are run from a `main` function rather
+/// than by the default test harness.
+pub fn run_tests<M: JavaModule>(options: JvmOptions, tests: &[(&str, JvmTest)]) {
+    M::launch_jvm(options, |env| {
+        for (name, test) in tests {
+            if let Err(e) = test(env) {
+                panic!("test {name} failed: {e}");
+            }
+            println!("test {name} ... ok");
+        }
+        Ok(())
+    })
+    .unwrap();
+}
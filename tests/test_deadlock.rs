@@ -0,0 +1,74 @@
+#![feature(arbitrary_self_types)]
+
+mod common;
+
+use common::*;
+use jni::objects::{JObject, JValue};
+use nekojni::{conversions::JavaConversionOwned, *};
+
+jni_module!(
+    TestModule,
+    "moe.lymia.nekojni.test.DeadlockInit",
+    "moe.lymia.nekojni.test.DeadlockException"
+);
+
+pub struct Counter {
+    value: i32,
+}
+
+#[jni_export]
+#[jni(java_path = "moe.lymia.nekojni.test.Counter")]
+impl Counter {
+    #[jni(constructor)]
+    pub fn new(_env: JniEnv) -> Self {
+        Counter { value: 1 }
+    }
+
+    pub fn add(&mut self, other: &JniRef<Self>) -> i32 {
+        self.value += other.value;
+        self.value
+    }
+}
+
+/// Calls `add` on `obj`, returning the message of the exception it throws, if any.
+fn call_add<'env>(
+    env: JniEnv<'env>,
+    obj: JObject<'env>,
+    other: JObject<'env>,
+) -> Result<Option<String>> {
+    let sig = "(Lmoe/lymia/nekojni/test/Counter;)I";
+    match env.call_method(obj, "add", sig, &[JValue::Object(other)]) {
+        Ok(_) => Ok(None),
+        Err(_) => {
+            let exception = env.exception_occurred()?;
+            env.exception_clear()?;
+            assert!(env.is_instance_of(exception, "java/lang/IllegalStateException")?);
+            let message = env.call_method(exception, "getMessage", "()Ljava/lang/String;", &[])?;
+            Ok(Some(String::from_java_value(message, env)?))
+        }
+    }
+}
+
+fn test_different_objects(env: JniEnv) -> Result<()> {
+    let a = env.new_object(env.find_class("moe/lymia/nekojni/test/Counter")?, "()V", &[])?;
+    let b = env.new_object(env.find_class("moe/lymia/nekojni/test/Counter")?, "()V", &[])?;
+    assert_eq!(call_add(env, a, b)?, None);
+    Ok(())
+}
+
+fn test_reentrant_lock(env: JniEnv) -> Result<()> {
+    // `add` holds an exclusive lock on `this`, so it cannot also borrow `other` if they are the same
+    let obj = env.new_object(env.find_class("moe/lymia/nekojni/test/Counter")?, "()V", &[])?;
+    let message = call_add(env, obj, obj)?.expect("`add` did not throw");
+    assert!(message.starts_with("Deadlock detected"), "{message}");
+    assert!(message.contains("`moe.lymia.nekojni.test.Counter`"), "{message}");
+    assert!(!message.contains("test_deadlock::Counter"), "{message}");
+    Ok(())
+}
+
+fn main() {
+    run_tests::<TestModule>(Default::default(), &[
+        ("different_objects", test_different_objects),
+        ("reentrant_lock", test_reentrant_lock),
+    ]);
+}
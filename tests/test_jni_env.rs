@@ -0,0 +1,44 @@
+mod common;
+
+use common::*;
+use nekojni::*;
+
+jni_module!(
+    TestModule,
+    "moe.lymia.nekojni.test.JniEnvInit",
+    "moe.lymia.nekojni.test.JniEnvException"
+);
+
+/// The size of the arrays allocated by the tests. Each test allocates more of these in total than
+/// the heap of the JVM can hold, so the tests fail if the arrays are never freed.
+const ARRAY_SIZE: i32 = 1 << 20;
+
+fn test_local_frame(env: JniEnv) -> Result<()> {
+    for _ in 0..256 {
+        env.with_local_frame(1, |env| {
+            env.new_byte_array(ARRAY_SIZE)?;
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+fn test_local_frame_returning(env: JniEnv) -> Result<()> {
+    for i in 0..256 {
+        let str = env.with_local_frame_returning(2, |env| {
+            env.new_byte_array(ARRAY_SIZE)?;
+            Ok(env.new_string(i.to_string())?.into())
+        })?;
+        assert_eq!(String::from(env.get_string(str.into())?), i.to_string());
+        env.delete_local_ref(str)?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = JvmOptions { jvm_args: vec!["-Xmx32m".to_string()], ..Default::default() };
+    run_tests::<TestModule>(options, &[
+        ("local_frame", test_local_frame),
+        ("local_frame_returning", test_local_frame_returning),
+    ]);
+}
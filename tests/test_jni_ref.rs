@@ -0,0 +1,189 @@
+#![feature(arbitrary_self_types)]
+
+mod common;
+
+use common::*;
+use jni::objects::{JObject, JValue};
+use nekojni::{conversions::JavaConversionOwned, *};
+
+jni_module!(
+    TestModule,
+    "moe.lymia.nekojni.test.JniRefInit",
+    "moe.lymia.nekojni.test.JniRefException"
+);
+
+const CLASS_NAME: &str = "moe/lymia/nekojni/test/Holder";
+
+pub struct Holder {
+    value: i32,
+}
+
+#[jni_export]
+#[jni(java_path = "moe.lymia.nekojni.test.Holder")]
+impl Holder {
+    #[jni(constructor)]
+    pub fn new(_env: JniEnv, value: i32) -> Self {
+        Holder { value }
+    }
+
+    pub fn identity_hash(&self) -> Result<i32> {
+        let this = JniRef::from_self(self)?;
+        let obj = JValue::Object(JniRef::this(&this));
+        let env = this.env();
+        Ok(env
+            .call_static_method(
+                "java/lang/System",
+                "identityHashCode",
+                "(Ljava/lang/Object;)I",
+                &[obj],
+            )?
+            .i()?)
+    }
+
+    pub fn value_from_env_ref(self: &JniRef<Self>) -> Result<i32> {
+        Ok(JniRef::from_self(&**self)?.value)
+    }
+
+    pub fn value_from_static(_env: JniEnv, other: &JniRef<Self>) -> Result<i32> {
+        Ok(JniRef::from_self(&**other)?.value)
+    }
+
+    pub fn panic_in_method(&self) -> i32 {
+        panic!("`panic_in_method` was called on an object with value {}", self.value)
+    }
+}
+
+/// A class created from Rust code, extending a class other than `java.lang.Object`.
+pub struct RustThread {
+    value: i32,
+    initialized: bool,
+}
+
+#[jni_export]
+#[jni(
+    java_path = "moe.lymia.nekojni.test.RustThread",
+    extends = "java.lang.Thread",
+    from_rust
+)]
+impl RustThread {
+    #[jni(init)]
+    fn init(&mut self) {
+        self.initialized = true;
+    }
+
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+}
+
+/// A class created from Rust code, whose superclass has no constructor without parameters.
+pub struct RustStream;
+
+#[jni_export]
+#[jni(
+    java_path = "moe.lymia.nekojni.test.RustStream",
+    extends = "java.io.FilterInputStream"
+)]
+#[jni(from_rust)]
+impl RustStream {}
+
+fn new_holder(env: JniEnv, value: i32) -> Result<JObject> {
+    Ok(env.new_object(env.find_class(CLASS_NAME)?, "(I)V", &[JValue::Int(value)])?)
+}
+
+/// Returns the message of the exception thrown by a call, or an error if it did not throw.
+fn exception_message<R>(env: JniEnv, result: jni::errors::Result<R>) -> Result<String> {
+    if result.is_ok() {
+        jni_bail!("The call did not throw an exception.");
+    }
+    let exception = env.exception_occurred()?;
+    env.exception_clear()?;
+    let message = env.call_method(exception, "getMessage", "()Ljava/lang/String;", &[])?;
+    String::from_java_value(message, env)
+}
+
+fn test_from_self(env: JniEnv) -> Result<()> {
+    let obj = new_holder(env, 1)?;
+    let hash = env.call_method(obj, "identityHash", "()I", &[])?.i()?;
+    let expected = env
+        .call_static_method("java/lang/System", "identityHashCode", "(Ljava/lang/Object;)I", &[
+            JValue::Object(obj),
+        ])?
+        .i()?;
+    assert_eq!(hash, expected);
+    Ok(())
+}
+
+fn test_from_self_outside_ref_method(env: JniEnv) -> Result<()> {
+    let obj = new_holder(env, 2)?;
+    let result = env.call_method(obj, "valueFromEnvRef", "()I", &[]);
+    let message = exception_message(env, result)?;
+    assert!(message.contains("JniRef::from_self"), "{message}");
+
+    let class = env.find_class(CLASS_NAME)?;
+    let sig = format!("(L{CLASS_NAME};)I");
+    let result = env.call_static_method(class, "valueFromStatic", sig, &[JValue::Object(obj)]);
+    let message = exception_message(env, result)?;
+    assert!(message.contains("JniRef::from_self"), "{message}");
+    Ok(())
+}
+
+fn test_from_self_after_panic(env: JniEnv) -> Result<()> {
+    let obj = new_holder(env, 3)?;
+    let result = env.call_method(obj, "panicInMethod", "()I", &[]);
+    let message = exception_message(env, result)?;
+    assert!(message.contains("panic_in_method"), "{message}");
+
+    // the receiver of the method that panicked must not still be registered
+    let result = env.call_method(obj, "valueFromEnvRef", "()I", &[]);
+    let message = exception_message(env, result)?;
+    assert!(message.contains("JniRef::from_self"), "{message}");
+    env.call_method(obj, "identityHash", "()I", &[])?;
+    Ok(())
+}
+
+fn test_new_from_rust(env: JniEnv) -> Result<()> {
+    let thread = JniRef::new_from_rust(env, RustThread { value: 4, initialized: false })?;
+    let obj = JniRef::this(&thread);
+    assert!(thread.initialized);
+    assert_eq!(env.call_method(obj, "value", "()I", &[])?.i()?, 4);
+    assert!(env.call_method(obj, "isInitialized", "()Z", &[])?.z()?);
+
+    // the constructor of `java.lang.Thread` must have been run
+    let name = env.call_method(obj, "getName", "()Ljava/lang/String;", &[])?;
+    let name = String::from_java_value(name, env)?;
+    assert!(name.starts_with("Thread-"), "{name}");
+    Ok(())
+}
+
+fn test_new_from_rust_errors(env: JniEnv) -> Result<()> {
+    let message = match JniRef::new_from_rust(env, RustStream) {
+        Ok(_) => jni_bail!("`RustStream` was created without a superclass constructor."),
+        Err(e) => e.to_string(),
+    };
+    assert!(message.contains("java.io.FilterInputStream"), "{message}");
+    assert!(!env.exception_check()?);
+
+    let message = match JniRef::new_from_rust(env, Holder { value: 5 }) {
+        Ok(_) => jni_bail!("`Holder` was created without `#[jni(from_rust)]`."),
+        Err(e) => e.to_string(),
+    };
+    assert!(message.contains("#[jni(from_rust)]"), "{message}");
+    Ok(())
+}
+
+fn main() {
+    // verify the generated classes, which are loaded by a custom class loader
+    let options = JvmOptions { jvm_args: vec!["-Xverify:all".to_string()], ..Default::default() };
+    run_tests::<TestModule>(options, &[
+        ("from_self", test_from_self),
+        ("from_self_outside_ref_method", test_from_self_outside_ref_method),
+        ("from_self_after_panic", test_from_self_after_panic),
+        ("new_from_rust", test_new_from_rust),
+        ("new_from_rust_errors", test_new_from_rust_errors),
+    ]);
+}